@@ -0,0 +1,136 @@
+use std::{collections::BTreeSet, path::Path};
+
+use toml_edit::{ArrayOfTables, DocumentMut, Item, Table, value};
+
+use crate::report::Diagnostic;
+
+/// The problems of `--check` recorded by `--write-baseline`, a `--baseline`
+/// does not report them again so only new ones fail.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct Baseline {
+    problems: BTreeSet<Entry>,
+}
+
+/// A problem by its file, rule, table and key. The line is left out so the
+/// baseline still matches after the lines above it change.
+type Entry = (String, String, String, String);
+
+impl Baseline {
+    /// Records the problems of the diagnostics, the files relative to `cwd`.
+    pub(crate) fn new(diagnostics: &[Diagnostic], cwd: &Path) -> Self {
+        Self {
+            problems: diagnostics.iter().map(|d| entry(d, cwd)).collect(),
+        }
+    }
+
+    /// Reads a baseline written by `--write-baseline`.
+    pub(crate) fn parse(text: &str) -> Result<Self, String> {
+        let doc = text.parse::<DocumentMut>().map_err(|e| e.to_string())?;
+        let Some(problems) = doc.get("problem") else {
+            return Ok(Self::default());
+        };
+        let problems = problems.as_array_of_tables().ok_or("`problem` must be an array of tables")?;
+        let field = |table: &Table, key| table.get(key).and_then(Item::as_str).unwrap_or_default().to_owned();
+        Ok(Self {
+            problems: problems
+                .iter()
+                .map(|t| (field(t, "file"), field(t, "rule"), field(t, "table"), field(t, "key")))
+                .collect(),
+        })
+    }
+
+    /// Returns true if the problem is recorded, the file relative to `cwd`.
+    pub(crate) fn contains(&self, diagnostic: &Diagnostic, cwd: &Path) -> bool {
+        self.problems.contains(&entry(diagnostic, cwd))
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.problems.len()
+    }
+}
+
+impl std::fmt::Display for Baseline {
+    /// One `[[problem]]` per problem, sorted so the file diffs well.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut problems = ArrayOfTables::new();
+        for (file, rule, table, key) in &self.problems {
+            let mut problem = Table::new();
+            problem["file"] = value(file);
+            problem["rule"] = value(rule);
+            if !table.is_empty() {
+                problem["table"] = value(table);
+            }
+            if !key.is_empty() {
+                problem["key"] = value(key);
+            }
+            problems.push(problem);
+        }
+        let mut doc = DocumentMut::new();
+        doc.insert("problem", Item::ArrayOfTables(problems));
+        writeln!(
+            f,
+            "# The problems `cargo sort-fix --check --baseline` ignores, written by --write-baseline."
+        )?;
+        write!(f, "{doc}")
+    }
+}
+
+fn entry(diagnostic: &Diagnostic, cwd: &Path) -> Entry {
+    let (table, key) = diagnostic
+        .location()
+        .map(|(table, key, _)| (table.to_owned(), key.unwrap_or_default().to_owned()))
+        .unwrap_or_default();
+    (diagnostic.relative_file(cwd), diagnostic.rule_id().to_owned(), table, key)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::Baseline;
+    use crate::{
+        check::Violation,
+        report::{Diagnostic, Problem},
+    };
+
+    #[test]
+    fn baseline() {
+        let cwd = Path::new("/repo");
+        let violation = |key: &str, line| Violation {
+            table: "dependencies".to_owned(),
+            key: Some(key.to_owned()),
+            line,
+            expected_position: 1,
+            before: None,
+        };
+        let diagnostics = [
+            Diagnostic::new("/repo/crates/a/Cargo.toml", Problem::Violation(violation("b", 7))),
+            Diagnostic::new("./Cargo.toml", Problem::Unformatted),
+        ];
+        let baseline = Baseline::new(&diagnostics, cwd);
+        let written = baseline.to_string();
+        assert_eq!(
+            written,
+            r#"# The problems `cargo sort-fix --check --baseline` ignores, written by --write-baseline.
+[[problem]]
+file = "Cargo.toml"
+rule = "unformatted"
+
+[[problem]]
+file = "crates/a/Cargo.toml"
+rule = "unsorted-key"
+table = "dependencies"
+key = "b"
+"#
+        );
+        let baseline = Baseline::parse(&written).unwrap();
+        assert_eq!(baseline.len(), 2);
+
+        // The same key on another line is still recorded, another key is new
+        let moved = Diagnostic::new("crates/a/Cargo.toml", Problem::Violation(violation("b", 12)));
+        assert!(baseline.contains(&moved, cwd));
+        let new = Diagnostic::new("crates/a/Cargo.toml", Problem::Violation(violation("c", 8)));
+        assert!(!baseline.contains(&new, cwd));
+        assert!(Baseline::parse("problem = 1").is_err());
+    }
+}
@@ -0,0 +1,1363 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+use toml_edit::{Document, DocumentMut, Item, Key, Table, TableLike, TomlError, Value};
+
+use crate::{
+    CARGO_TOML, diff,
+    discover::is_glob,
+    fmt::{Config, Severity},
+    hoist::{self, Member},
+    sort::{DEPENDENCY_KINDS, normalize_path},
+};
+
+/// A key or table that is not where sorting puts it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Violation {
+    /// The dotted path of the table, `target.cfg(unix).dependencies`.
+    pub table: String,
+    /// The out of order key, `None` when the table itself is out of order.
+    pub key: Option<String>,
+    /// The 1-based line of the key or table heading in the original file.
+    pub line: usize,
+    /// The 1-based position the key should have in its table, or the table
+    /// among all the tables of the document.
+    pub expected_position: usize,
+    /// The key or table sorting puts right after it, `None` for the last one.
+    pub before: Option<String>,
+}
+
+impl Violation {
+    pub(crate) fn message(&self) -> String {
+        match &self.key {
+            Some(key) => format!("`{key}` in [{}] should be at position {}", self.table, self.expected_position),
+            None => format!("[{}] should be table {} of the document", self.table, self.expected_position),
+        }
+    }
+
+    /// Where it belongs, ``expected `serde` before `tokio` in [dependencies]``.
+    pub(crate) fn note(&self) -> String {
+        match (&self.key, &self.before) {
+            (Some(key), Some(before)) => format!("expected `{key}` before `{before}` in [{}]", self.table),
+            (Some(key), None) => format!("expected `{key}` last in [{}]", self.table),
+            (None, Some(before)) => format!("expected [{}] before [{before}]", self.table),
+            (None, None) => format!("expected [{}] last", self.table),
+        }
+    }
+}
+
+/// A mistake in the manifest that sorting does not fix, found by the lints
+/// of `--check`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Lint {
+    /// The id of the lint, also the rule of the SARIF report.
+    pub rule: &'static str,
+    /// The dotted path of the table, `target.cfg(unix).dependencies`.
+    pub table: String,
+    pub key: String,
+    /// The 1-based line of the key in the original file.
+    pub line: usize,
+    pub message: String,
+    /// The `[severity]` of the rule, never `Severity::Off`.
+    pub severity: Severity,
+}
+
+/// Runs the lints the config turns on, the problems are ordered by line.
+pub(crate) fn lint(original: &str, config: &Config) -> Vec<Lint> {
+    let Ok(doc) = Document::parse(original.to_owned()) else {
+        return vec![];
+    };
+    let mut lints = vec![];
+    duplicate_dependencies(&doc, &mut lints);
+    separator_variants(&doc, &mut lints);
+    loose_versions(&doc, &mut lints);
+    dangling_features(&doc, &mut lints);
+    unused_optional(&doc, &mut lints);
+    crates_io_limits(&doc, &mut lints);
+    invalid_versions(&doc, &mut lints);
+    workspace_overlap(&doc, &mut lints);
+    with_severity(lints, config)
+}
+
+/// Runs the lints that look at the files next to the manifest in `dir`, the
+/// problems are ordered by line.
+pub(crate) fn lint_paths(original: &str, dir: &Path, config: &Config) -> Vec<Lint> {
+    let Ok(doc) = Document::parse(original.to_owned()) else {
+        return vec![];
+    };
+    let mut lints = vec![];
+    missing_members(&doc, dir, &mut lints);
+    let workspace = doc.get("workspace").and_then(Item::as_table);
+    // A member that can not be read is reported when it is checked
+    if let Some(members) = workspace.and_then(|w| hoist::member_manifests(w, dir)) {
+        unused_workspace_dependencies(&doc, &members, &mut lints);
+        version_conflicts(&doc, &members, &mut lints);
+    }
+    with_severity(lints, config)
+}
+
+/// Sets the `[severity]` of every lint, drops the ones that are off and
+/// orders the rest by line.
+fn with_severity(mut lints: Vec<Lint>, config: &Config) -> Vec<Lint> {
+    lints.retain_mut(|lint| {
+        // The lints that are on by default only warn, a manifest that passed
+        // `--check` before they were added still passes
+        let (enabled, default) = match lint.rule {
+            "duplicate-dependency" => (config.lint_duplicate_dependencies, Severity::Warn),
+            "separator-variant" => (config.lint_separator_variants, Severity::Warn),
+            "wildcard-version" => (config.lint_wildcard_versions, Severity::Error),
+            "unbounded-version" => (config.lint_unbounded_versions, Severity::Error),
+            "dangling-feature" => (config.lint_features, Severity::Warn),
+            "unused-optional" => (config.lint_unused_optional, Severity::Error),
+            "crates-io-limits" => (config.lint_crates_io, Severity::Warn),
+            "invalid-version" => (config.lint_version_syntax, Severity::Warn),
+            "workspace-overlap" => (config.lint_workspace_overlap, Severity::Warn),
+            "missing-member" => (config.lint_missing_members, Severity::Warn),
+            "unused-workspace-dependency" => (config.lint_unused_workspace_dependencies, Severity::Warn),
+            "version-conflict" => (config.lint_version_conflicts, Severity::Warn),
+            _ => (true, Severity::Error),
+        };
+        lint.severity = config.severity_of(lint.rule, if enabled { default } else { Severity::Off });
+        lint.severity != Severity::Off
+    });
+    lints.sort_by_key(|lint| lint.line);
+    lints
+}
+
+/// The line of `key` in the original text.
+fn key_line(doc: &Document<String>, table: &dyn TableLike, key: &str) -> usize {
+    table.key(key).and_then(Key::span).map_or(0, |span| line_of(doc.raw(), span.start))
+}
+
+/// Every table of dependency specifications with its dotted path, the ones
+/// of `[target]` and `[workspace.dependencies]` included.
+fn dependency_tables(doc: &Document<String>) -> Vec<(String, &dyn TableLike)> {
+    fn kinds_of<'a>(prefix: &str, table: &'a Table, tables: &mut Vec<(String, &'a dyn TableLike)>) {
+        for kind in DEPENDENCY_KINDS {
+            if let Some(deps) = table.get(kind).and_then(Item::as_table_like) {
+                tables.push((format!("{prefix}{kind}"), deps));
+            }
+        }
+    }
+
+    let mut tables = vec![];
+    kinds_of("", doc.as_table(), &mut tables);
+    if let Some(targets) = doc.get("target").and_then(Item::as_table) {
+        for (cfg, item) in targets.iter() {
+            if let Some(table) = item.as_table() {
+                kinds_of(&format!("target.{cfg}."), table, &mut tables);
+            }
+        }
+    }
+    if let Some(deps) = doc
+        .get("workspace")
+        .and_then(|ws| ws.get("dependencies"))
+        .and_then(Item::as_table_like)
+    {
+        tables.push(("workspace.dependencies".to_owned(), deps));
+    }
+    tables
+}
+
+/// Flags the crates of a dependency table that have the same spec in an
+/// earlier kind of dependency table next to it, like a dev-dependency the
+/// `[dependencies]` already make available to the tests. `foo = "1"` is the
+/// same as `foo = { version = "1" }`.
+fn duplicate_dependencies(doc: &Document<String>, lints: &mut Vec<Lint>) {
+    let mut scopes = vec![(String::new(), doc.as_table())];
+    if let Some(targets) = doc.get("target").and_then(Item::as_table) {
+        for (cfg, item) in targets.iter() {
+            if let Some(table) = item.as_table() {
+                scopes.push((format!("target.{cfg}."), table));
+            }
+        }
+    }
+
+    for (prefix, scope) in scopes {
+        let kinds = DEPENDENCY_KINDS
+            .iter()
+            .filter_map(|kind| Some((*kind, scope.get(kind)?.as_table_like()?)))
+            .collect::<Vec<_>>();
+        for (i, (kind, deps)) in kinds.iter().enumerate() {
+            for (key, item) in deps.iter() {
+                let Some(earlier) = kinds[..i]
+                    .iter()
+                    .find(|(_, other)| other.get(key).and_then(spec_of).is_some_and(|spec| Some(spec) == spec_of(item)))
+                else {
+                    continue;
+                };
+                let table = format!("{prefix}{kind}");
+                lints.push(Lint {
+                    rule: "duplicate-dependency",
+                    severity: Severity::Error,
+                    message: format!("`{key}` in [{table}] has the same spec as in [{prefix}{}]", earlier.0),
+                    line: key_line(doc, *deps, key),
+                    table,
+                    key: key.to_owned(),
+                });
+            }
+        }
+    }
+}
+
+/// Flags a crate written as `foo_bar` when it is `foo-bar` somewhere else
+/// in the manifest, cargo treats the two names as one crate. The renamed
+/// dependencies are compared by their `package`.
+fn separator_variants(doc: &Document<String>, lints: &mut Vec<Lint>) {
+    // The first spelling of each crate, where it is
+    let mut first: BTreeMap<String, (String, String)> = BTreeMap::new();
+    for (table, deps) in dependency_tables(doc) {
+        for (key, item) in deps.iter() {
+            let name = item.get("package").and_then(Item::as_str).unwrap_or(key);
+            let (spelling, at) = first
+                .entry(name.replace('_', "-"))
+                .or_insert_with(|| (name.to_owned(), table.clone()));
+            if spelling != name {
+                lints.push(Lint {
+                    rule: "separator-variant",
+                    severity: Severity::Error,
+                    message: format!("`{name}` in [{table}] is the same crate as `{spelling}` in [{at}]"),
+                    line: key_line(doc, deps, key),
+                    table: table.clone(),
+                    key: key.to_owned(),
+                });
+            }
+        }
+    }
+}
+
+/// Flags the `*` version requirements, `wildcard-version`, and the `>=` ones
+/// without an upper bound, `unbounded-version`.
+fn loose_versions(doc: &Document<String>, lints: &mut Vec<Lint>) {
+    for (table, deps) in dependency_tables(doc) {
+        for (key, item) in deps.iter() {
+            let Some(version) = item.as_str().or_else(|| item.get("version").and_then(Item::as_str)) else {
+                continue;
+            };
+            let parts = version.split(',').map(str::trim).collect::<Vec<_>>();
+            let wildcard = parts.contains(&"*");
+            let unbounded = parts.iter().any(|p| p.starts_with('>')) && !parts.iter().any(|p| p.starts_with('<'));
+            let (rule, loose) = if wildcard {
+                ("wildcard-version", "any version")
+            } else if unbounded {
+                ("unbounded-version", "every future version")
+            } else {
+                continue;
+            };
+            lints.push(Lint {
+                rule,
+                severity: Severity::Error,
+                message: format!("`{key}` in [{table}] accepts {loose} with `{version}`"),
+                line: key_line(doc, deps, key),
+                table: table.clone(),
+                key: key.to_owned(),
+            });
+        }
+    }
+}
+
+/// Flags the values of `[features]` that point at nothing: a `dep:foo` or a
+/// bare `foo` without an optional dependency or feature `foo`, or a `foo/bar`
+/// without a dependency `foo`. Dev-dependencies can not be part of a feature,
+/// and cargo makes no implicit feature `foo` once a value says `dep:foo`.
+fn dangling_features(doc: &Document<String>, lints: &mut Vec<Lint>) {
+    let Some(features) = doc.get("features").and_then(Item::as_table) else {
+        return;
+    };
+    let mut deps = BTreeMap::new();
+    for (table, specs) in dependency_tables(doc) {
+        if table.ends_with("dev-dependencies") || table.starts_with("workspace.") {
+            continue;
+        }
+        for (key, item) in specs.iter() {
+            let optional = item.get("optional").and_then(Item::as_bool).unwrap_or_default();
+            *deps.entry(key.to_owned()).or_default() |= optional;
+        }
+    }
+    let explicit = features
+        .iter()
+        .filter_map(|(_, item)| item.as_array())
+        .flatten()
+        .filter_map(|value| value.as_str()?.strip_prefix("dep:"))
+        .collect::<BTreeSet<_>>();
+
+    for (feature, item) in features.iter() {
+        for value in item.as_array().into_iter().flatten() {
+            let Some(reference) = value.as_str() else {
+                continue;
+            };
+            let problem = if let Some(dep) = reference.strip_prefix("dep:") {
+                (deps.get(dep) != Some(&true)).then(|| format!("`{dep}` is not an optional dependency"))
+            } else if let Some((dep, _)) = reference.split_once('/') {
+                let dep = dep.trim_end_matches('?');
+                (!deps.contains_key(dep)).then(|| format!("`{dep}` is not a dependency"))
+            } else if features.contains_key(reference) {
+                None
+            } else if explicit.contains(reference) {
+                Some(format!("`dep:{reference}` leaves `{reference}` without an implicit feature"))
+            } else {
+                (deps.get(reference) != Some(&true)).then(|| format!("`{reference}` is neither a feature nor an optional dependency"))
+            };
+            if let Some(problem) = problem {
+                lints.push(Lint {
+                    rule: "dangling-feature",
+                    severity: Severity::Error,
+                    message: format!("`{reference}` in feature `{feature}` points at nothing, {problem}"),
+                    line: value.span().map_or(0, |span| line_of(doc.raw(), span.start)),
+                    table: "features".to_owned(),
+                    key: feature.to_owned(),
+                });
+            }
+        }
+    }
+}
+
+/// Flags the optional dependencies no value of `[features]` enables, only
+/// the implicit feature cargo makes for them turns them on.
+fn unused_optional(doc: &Document<String>, lints: &mut Vec<Lint>) {
+    let features = doc.get("features").and_then(Item::as_table);
+    let enabled = features
+        .into_iter()
+        .flat_map(|features| features.iter())
+        .filter_map(|(_, item)| item.as_array())
+        .flatten()
+        .filter_map(Value::as_str)
+        .map(|reference| {
+            let reference = reference.strip_prefix("dep:").unwrap_or(reference);
+            reference.split('/').next().unwrap_or(reference).trim_end_matches('?')
+        })
+        .collect::<Vec<_>>();
+
+    for (table, specs) in dependency_tables(doc) {
+        for (key, item) in specs.iter() {
+            if item.get("optional").and_then(Item::as_bool) == Some(true) && !enabled.contains(&key) {
+                lints.push(Lint {
+                    rule: "unused-optional",
+                    severity: Severity::Error,
+                    message: format!("`{key}` in [{table}] is optional but no feature enables it"),
+                    line: key_line(doc, specs, key),
+                    table: table.clone(),
+                    key: key.to_owned(),
+                });
+            }
+        }
+    }
+}
+
+/// Flags the version requirements of the dependency tables cargo can not
+/// parse.
+fn invalid_versions(doc: &Document<String>, lints: &mut Vec<Lint>) {
+    for (table, deps) in dependency_tables(doc) {
+        for (key, item) in deps.iter() {
+            let Some(version) = item.as_str().or_else(|| item.get("version").and_then(Item::as_str)) else {
+                continue;
+            };
+            if !is_version_req(version) {
+                lints.push(Lint {
+                    rule: "invalid-version",
+                    severity: Severity::Error,
+                    message: format!("`{key}` in [{table}] has an invalid version requirement `{version}`"),
+                    line: key_line(doc, deps, key),
+                    table: table.clone(),
+                    key: key.to_owned(),
+                });
+            }
+        }
+    }
+}
+
+/// Returns true if the text is a semver version requirement like cargo reads
+/// them, `1.2`, `^1.2.3-beta.1`, `>=1, <3` or `1.*`.
+fn is_version_req(req: &str) -> bool {
+    fn is_number(part: &str) -> bool {
+        !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()) && (part == "0" || !part.starts_with('0'))
+    }
+    fn is_identifiers(text: &str) -> bool {
+        text.split('.')
+            .all(|id| !id.is_empty() && id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-'))
+    }
+    fn is_comparator(comparator: &str) -> bool {
+        let op_len = [">=", "<=", ">", "<", "=", "~", "^"]
+            .iter()
+            .find(|op| comparator.starts_with(*op))
+            .map_or(0, |op| op.len());
+        let version = comparator[op_len..].trim_start();
+        let (version, build) = version.split_once('+').map_or((version, None), |(v, b)| (v, Some(b)));
+        let (version, pre) = version.split_once('-').map_or((version, None), |(v, p)| (v, Some(p)));
+        let parts = version.split('.').collect::<Vec<_>>();
+        let wildcard = |part: &&str| ["*", "x", "X"].contains(part);
+        // A wildcard is only allowed without an operator and for every part after it
+        let first_wildcard = parts.iter().position(wildcard).unwrap_or(parts.len());
+        parts.len() <= 3
+            && parts[..first_wildcard].iter().all(|part| is_number(part))
+            && parts[first_wildcard..].iter().all(wildcard)
+            && (first_wildcard == parts.len() || (op_len == 0 && first_wildcard > 0 && pre.is_none() && build.is_none()))
+            && pre.is_none_or(|pre| parts.len() == 3 && is_identifiers(pre))
+            && build.is_none_or(is_identifiers)
+    }
+
+    let req = req.trim();
+    req == "*" || (!req.is_empty() && req.split(',').map(str::trim).all(is_comparator))
+}
+
+/// The most keywords or categories crates.io takes.
+const MAX_KEYWORDS: usize = 5;
+/// The longest keyword crates.io takes.
+const MAX_KEYWORD_LEN: usize = 20;
+
+/// Flags the `keywords` and `categories` of `[package]` that crates.io
+/// rejects on publish. A package is left alone unless `publish` is missing,
+/// `true` or a list of registries with `crates-io` in it.
+fn crates_io_limits(doc: &Document<String>, lints: &mut Vec<Lint>) {
+    let Some(package) = doc.get("package").and_then(Item::as_table) else {
+        return;
+    };
+    let to_crates_io = match package.get("publish").and_then(Item::as_value) {
+        None => !package.contains_key("publish"),
+        Some(Value::Boolean(publish)) => *publish.value(),
+        Some(Value::Array(registries)) => registries.iter().any(|r| r.as_str() == Some("crates-io")),
+        Some(_) => false,
+    };
+    if !to_crates_io {
+        return;
+    }
+    let mut push = |key: &str, line, message| {
+        lints.push(Lint {
+            rule: "crates-io-limits",
+            severity: Severity::Error,
+            table: "package".to_owned(),
+            key: key.to_owned(),
+            line,
+            message,
+        });
+    };
+
+    for key in ["keywords", "categories"] {
+        let Some(arr) = package.get(key).and_then(Item::as_array) else {
+            continue;
+        };
+        if arr.len() > MAX_KEYWORDS {
+            let message = format!("crates.io takes at most {MAX_KEYWORDS} {key}, there are {}", arr.len());
+            push(key, key_line(doc, package, key), message);
+        }
+    }
+
+    for value in package.get("keywords").and_then(Item::as_array).into_iter().flatten() {
+        let Some(keyword) = value.as_str() else {
+            continue;
+        };
+        let valid = keyword.len() <= MAX_KEYWORD_LEN
+            && keyword.starts_with(|c: char| c.is_ascii_alphabetic())
+            && keyword.chars().all(|c| c.is_ascii_alphanumeric() || ['_', '-', '+'].contains(&c));
+        if !valid {
+            let message =
+                format!("crates.io takes keywords of at most {MAX_KEYWORD_LEN} letters, digits, `_`, `-` or `+`, not `{keyword}`");
+            push("keywords", value.span().map_or(0, |span| line_of(doc.raw(), span.start)), message);
+        }
+    }
+}
+
+/// Flags the paths of `workspace.members` that are also in `exclude`, or a
+/// member inside a directory or glob of `exclude`. Cargo keeps a listed
+/// member, so the exclude does nothing for it.
+fn workspace_overlap(doc: &Document<String>, lints: &mut Vec<Lint>) {
+    let Some(workspace) = doc.get("workspace").and_then(Item::as_table) else {
+        return;
+    };
+    let paths = |key| {
+        workspace
+            .get(key)
+            .and_then(Item::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|v| Some((normalize_path(v.as_str()?), v)))
+            .collect::<Vec<_>>()
+    };
+    let excludes = paths("exclude");
+    for (member, value) in paths("members") {
+        let covered = excludes.iter().find_map(|(exclude, _)| {
+            let glob = glob::Pattern::new(exclude).is_ok_and(|p| p.matches(member)) && exclude != &member;
+            let inside = member.strip_prefix(exclude).is_some_and(|rest| rest.starts_with('/'));
+            if exclude == &member {
+                Some(format!("`{member}` is in both workspace.members and workspace.exclude"))
+            } else if glob || inside {
+                Some(format!("member `{member}` is inside `{exclude}` of workspace.exclude"))
+            } else {
+                None
+            }
+        });
+        if let Some(message) = covered {
+            lints.push(Lint {
+                rule: "workspace-overlap",
+                severity: Severity::Error,
+                table: "workspace".to_owned(),
+                key: "members".to_owned(),
+                line: value.span().map_or(0, |span| line_of(doc.raw(), span.start)),
+                message,
+            });
+        }
+    }
+}
+
+/// Flags the paths of `workspace.members` without a `Cargo.toml` below `dir`,
+/// cargo refuses to load the workspace. Globs are left alone.
+fn missing_members(doc: &Document<String>, dir: &Path, lints: &mut Vec<Lint>) {
+    let members = doc.get("workspace").and_then(|w| w.get("members")).and_then(Item::as_array);
+    for value in members.into_iter().flatten() {
+        let Some(member) = value.as_str().map(normalize_path) else {
+            continue;
+        };
+        if !is_glob(member) && !dir.join(member).join(CARGO_TOML).is_file() {
+            lints.push(Lint {
+                rule: "missing-member",
+                severity: Severity::Error,
+                table: "workspace".to_owned(),
+                key: "members".to_owned(),
+                line: value.span().map_or(0, |span| line_of(doc.raw(), span.start)),
+                message: format!("member `{member}` has no {CARGO_TOML}"),
+            });
+        }
+    }
+}
+
+/// Reports the entries of `[workspace.dependencies]` that no member inherits.
+fn unused_workspace_dependencies(doc: &Document<String>, members: &[Member], lints: &mut Vec<Lint>) {
+    let deps = doc
+        .get("workspace")
+        .and_then(|w| w.get("dependencies"))
+        .and_then(Item::as_table_like);
+    let Some(deps) = deps else {
+        return;
+    };
+    for name in hoist::unused_dependencies(doc.as_table(), members) {
+        lints.push(Lint {
+            rule: "unused-workspace-dependency",
+            severity: Severity::Error,
+            table: "workspace.dependencies".to_owned(),
+            line: key_line(doc, deps, &name),
+            message: format!("`{name}` is not inherited by any workspace member"),
+            key: name,
+        });
+    }
+}
+
+/// Reports the crates that members require outside of
+/// `[workspace.dependencies]` in versions cargo can not unify into one, like
+/// `0.8` and `0.9` or `~1.2` and `1.5`. The root package counts as the member `.`.
+fn version_conflicts(doc: &Document<String>, members: &[Member], lints: &mut Vec<Lint>) {
+    let Some(workspace) = doc.get("workspace").and_then(Item::as_table_like) else {
+        return;
+    };
+    let root = doc.contains_key("package").then_some((".", doc.as_table()));
+    let manifests = root
+        .into_iter()
+        .chain(members.iter().map(|(path, doc)| (path.as_str(), doc.as_table())));
+    // The requirements of a crate by the versions they accept
+    let mut required: BTreeMap<String, BTreeMap<Range, (String, BTreeSet<&str>)>> = BTreeMap::new();
+    for (path, manifest) in manifests {
+        hoist::for_each_dependency(manifest, |key, item| {
+            let Some(req) = item.as_str().or_else(|| item.get("version").and_then(Item::as_str)) else {
+                return;
+            };
+            let Some(range) = version_range(req) else {
+                return;
+            };
+            let name = item.get("package").and_then(Item::as_str).unwrap_or(key);
+            let ranges = required.entry(name.to_owned()).or_default();
+            let (_, users) = ranges.entry(range).or_insert_with(|| (req.to_owned(), BTreeSet::new()));
+            users.insert(path);
+        });
+    }
+
+    for (name, ranges) in required {
+        let mut keys = ranges.keys();
+        // Cargo picks one version for requirements whose ranges overlap
+        let disjoint =
+            std::iter::from_fn(|| keys.next().map(|a| (a, keys.clone()))).any(|(a, mut rest)| rest.any(|b| a.intersect(*b).is_none()));
+        if !disjoint {
+            continue;
+        }
+        let versions = ranges
+            .values()
+            .map(|(req, users)| format!("`{req}` by {}", users.iter().copied().collect::<Vec<_>>().join(", ")))
+            .collect::<Vec<_>>();
+        lints.push(Lint {
+            rule: "version-conflict",
+            severity: Severity::Error,
+            table: "workspace.members".to_owned(),
+            line: key_line(doc, workspace, "members"),
+            message: format!("`{name}` is required in versions cargo can not unify: {}", versions.join("; ")),
+            key: name,
+        });
+    }
+}
+
+/// A version as its major, minor and patch number.
+type Version = (u64, u64, u64);
+
+/// The versions a requirement accepts, from the first up to but not including
+/// the second, `None` for no upper bound.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Range(Version, Option<Version>);
+
+impl Range {
+    /// The versions both ranges accept, `None` if there are none.
+    fn intersect(self, other: Range) -> Option<Range> {
+        let lower = self.0.max(other.0);
+        let upper = match (self.1, other.1) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        (upper.is_none_or(|upper| lower < upper)).then_some(Range(lower, upper))
+    }
+}
+
+/// The versions a requirement like `^1.2`, `~0.3.1` or `>=1, <3` accepts, with
+/// pre-release tags ignored. `None` for a requirement cargo would reject.
+fn version_range(req: &str) -> Option<Range> {
+    req.split(',').try_fold(Range((0, 0, 0), None), |range, comparator| {
+        range.intersect(comparator_range(comparator)?)
+    })
+}
+
+/// The versions a single comparator of a requirement accepts.
+fn comparator_range(comparator: &str) -> Option<Range> {
+    let comparator = comparator.trim();
+    let (op, version) = ["<=", ">=", "<", ">", "=", "^", "~"]
+        .into_iter()
+        .find_map(|op| Some((op, comparator.strip_prefix(op)?.trim_start())))
+        .unwrap_or(("^", comparator));
+    let version = version.split(['-', '+']).next()?;
+    let parts = version.split('.').collect::<Vec<_>>();
+    if parts.len() > 3 {
+        return None;
+    }
+    // `1.*` and `1.2.x` only give the parts before the wildcard
+    let given = parts
+        .iter()
+        .position(|part| matches!(*part, "*" | "x" | "X"))
+        .unwrap_or(parts.len());
+    let parts = parts[..given].iter().map(|part| part.parse().ok()).collect::<Option<Vec<u64>>>()?;
+    let part = |i: usize| parts.get(i).copied().unwrap_or(0);
+    let (major, minor, patch) = (part(0), part(1), part(2));
+    // The first version past the parts given, `1.3.0` for `1.2`
+    let next = match parts.len() {
+        0 => None,
+        1 => Some((major + 1, 0, 0)),
+        2 => Some((major, minor + 1, 0)),
+        _ => Some((major, minor, patch + 1)),
+    };
+    let lower = (major, minor, patch);
+    Some(match op {
+        "=" => Range(lower, next),
+        "~" if parts.len() > 2 => Range(lower, Some((major, minor + 1, 0))),
+        "~" => Range(lower, next),
+        ">=" => Range(lower, None),
+        ">" => Range(next?, None),
+        "<" => Range((0, 0, 0), Some(lower)),
+        "<=" => Range((0, 0, 0), next),
+        _ if parts.is_empty() => Range(lower, None),
+        _ if major > 0 || parts.len() == 1 => Range(lower, Some((major + 1, 0, 0))),
+        _ if minor > 0 || parts.len() == 2 => Range(lower, Some((0, minor + 1, 0))),
+        _ => Range(lower, next),
+    })
+}
+
+/// A dependency spec written out without any formatting and with its keys
+/// sorted so two specs can be compared.
+fn spec_of(item: &Item) -> Option<String> {
+    fn value(v: &Value) -> String {
+        match v {
+            Value::String(s) => format!("{:?}", s.value()),
+            Value::Array(arr) => format!("[{}]", arr.iter().map(value).collect::<Vec<_>>().join(",")),
+            Value::InlineTable(table) => table_like(table),
+            other => other.clone().decorated("", "").to_string(),
+        }
+    }
+    fn table_like(table: &dyn TableLike) -> String {
+        let mut pairs = table
+            .iter()
+            .map(|(k, item)| {
+                format!(
+                    "{k}={}",
+                    item.as_value()
+                        .map_or_else(|| item.as_table_like().map(table_like).unwrap_or_default(), value)
+                )
+            })
+            .collect::<Vec<_>>();
+        pairs.sort();
+        format!("{{{}}}", pairs.join(","))
+    }
+
+    match item {
+        Item::Value(Value::String(version)) => Some(format!("{{version={:?}}}", version.value())),
+        item => item.as_table_like().map(table_like),
+    }
+}
+
+/// Compares the original file with the sorted document and returns every key
+/// and table that was moved.
+///
+/// Only the entries outside of the longest run that is already in order are
+/// reported, `c, a, b` reports `c` rather than all three.
+pub(crate) fn find_violations(original: &str, sorted: &DocumentMut) -> Vec<Violation> {
+    let Ok(doc) = Document::parse(original.to_owned()) else {
+        return vec![];
+    };
+
+    let mut violations = vec![];
+    let mut original_tables = vec![];
+    let mut sorted_tables = vec![];
+    headed_tables(doc.as_table(), &mut vec![], &mut original_tables);
+    headed_tables(sorted.as_table(), &mut vec![], &mut sorted_tables);
+    original_tables.sort_by_key(|(pos, ..)| *pos);
+    sorted_tables.sort_by_key(|(pos, ..)| *pos);
+
+    let original_names = original_tables.iter().map(|(_, name, ..)| name.as_str()).collect::<Vec<_>>();
+    let sorted_names = sorted_tables.iter().map(|(_, name, ..)| name.as_str()).collect::<Vec<_>>();
+    for name in diff::moved(&original_names, &sorted_names) {
+        let Some(expected) = sorted_names.iter().position(|n| *n == name) else {
+            continue;
+        };
+        let start = original_tables.iter().find(|(_, n, ..)| n == name).and_then(|(.., start)| *start);
+        violations.push(Violation {
+            table: name.to_owned(),
+            key: None,
+            line: start.map_or(0, |start| line_of(original, start)),
+            expected_position: expected + 1,
+            before: sorted_names.get(expected + 1).map(|name| (*name).to_owned()),
+        });
+    }
+
+    for (_, name, table, _) in &original_tables {
+        let Some((_, _, sorted_table, _)) = sorted_tables.iter().find(|(_, n, ..)| n == name) else {
+            continue;
+        };
+        let original_keys = value_keys(table);
+        let sorted_keys = value_keys(sorted_table);
+        for key in diff::moved(&original_keys, &sorted_keys) {
+            let Some(expected) = sorted_keys.iter().position(|k| *k == key) else {
+                continue;
+            };
+            violations.push(Violation {
+                table: name.clone(),
+                key: Some(key.to_owned()),
+                line: table
+                    .key(key)
+                    .and_then(|k| k.span())
+                    .map_or(0, |span| line_of(original, span.start)),
+                expected_position: expected + 1,
+                before: sorted_keys.get(expected + 1).map(|key| (*key).to_owned()),
+            });
+        }
+    }
+
+    violations.sort_by_key(|v| v.line);
+    violations
+}
+
+/// A table with a `[heading]`, its position in the document, dotted name and
+/// where its heading starts in the original text.
+type HeadedTable<'a> = (isize, String, &'a Table, Option<usize>);
+
+/// Collects the tables with a `[heading]`.
+fn headed_tables<'a>(table: &'a Table, path: &mut Vec<String>, tables: &mut Vec<HeadedTable<'a>>) {
+    for (key, item) in table.iter() {
+        if let Item::Table(inner) = item {
+            path.push(key.to_owned());
+            if let Some(pos) = inner.position().filter(|_| !inner.is_implicit() && !inner.is_dotted()) {
+                tables.push((pos, path.join("."), inner, item.span().map(|span| span.start)));
+            }
+            headed_tables(inner, path, tables);
+            path.pop();
+        }
+    }
+}
+
+/// The keys written below the heading of a table, sub-tables with their own
+/// heading are left out.
+fn value_keys(table: &Table) -> Vec<&str> {
+    table
+        .iter()
+        .filter(|(_, item)| item.is_value() || item.as_table().is_some_and(Table::is_dotted))
+        .map(|(key, _)| key)
+        .collect()
+}
+
+/// Explains a `duplicate key` parse error with the name of the key or table
+/// and the lines of both of its definitions, `None` for any other error.
+pub(crate) fn duplicate_key(raw: &str, error: &TomlError) -> Option<String> {
+    if !error.message().contains("duplicate key") {
+        return None;
+    }
+    let span = error.span()?;
+    let line_start = raw[..span.start].rfind('\n').map_or(0, |i| i + 1);
+    // Everything above the second definition parses, the first one is in it
+    let doc = Document::parse(raw[..line_start].to_owned()).ok()?;
+    // The error points at the last part of a dotted key, the whole key is
+    // from the start of the line
+    let path = raw.get(line_start..span.end)?.trim_start();
+    let header = path.strip_prefix('[');
+    let keys = Key::parse(header.unwrap_or(path).trim()).ok()?;
+    let (last, parents) = keys.split_last()?;
+    let second = line_of(raw, span.start);
+
+    if header.is_some() {
+        let mut table = doc.as_table();
+        for key in parents {
+            table = table.get(key.get())?.as_table()?;
+        }
+        let first = table.key(last.get())?.span().map_or(0, |span| line_of(raw, span.start));
+        let name = keys.iter().map(Key::display_repr).collect::<Vec<_>>().join(".");
+        return Some(format!("duplicate table [{name}] on line {first} and line {second}"));
+    }
+
+    // The key belongs to the last heading above it
+    let mut tables = vec![];
+    headed_tables(doc.as_table(), &mut vec![], &mut tables);
+    let (name, mut table) = tables
+        .into_iter()
+        .filter_map(|(_, name, table, start)| Some((start?, name, table)))
+        .max_by_key(|(start, ..)| *start)
+        .map_or(("".to_owned(), doc.as_table()), |(_, name, table)| (name, table));
+    for key in parents {
+        table = table.get(key.get())?.as_table()?;
+    }
+    let first = table.key(last.get())?.span().map_or(0, |span| line_of(raw, span.start));
+    let key = keys.iter().map(Key::display_repr).collect::<Vec<_>>().join(".");
+    let table = if name.is_empty() { String::new() } else { format!(" in [{name}]") };
+    Some(format!("duplicate key `{key}`{table} on line {first} and line {second}"))
+}
+
+/// The line of `offset` in `text`, starting at 1.
+pub(crate) fn line_of(text: &str, offset: usize) -> usize {
+    text[..offset.min(text.len())].matches('\n').count() + 1
+}
+
+#[cfg(test)]
+mod test {
+    use toml_edit::DocumentMut;
+
+    use super::{Lint, Range, Violation, duplicate_key, find_violations, is_version_req, lint, lint_paths, version_range};
+    use crate::{
+        fmt::{Config, Severity},
+        sort,
+        test_utils::TempDir,
+    };
+
+    #[test]
+    fn violations() {
+        let input = r#"[package]
+name = "foo"
+
+[dev-dependencies]
+b = "1"
+
+[dependencies]
+c = "1"
+a = "1"
+b = "1"
+"#;
+        let config = Config {
+            table_order: vec!["package".to_owned(), "dependencies".to_owned(), "dev-dependencies".to_owned()],
+            ..Config::default()
+        };
+        let sorted = sort::sort_toml(input, sort::MATCHER, false, &config).unwrap();
+        let expected = vec![
+            Violation {
+                table: "dev-dependencies".to_owned(),
+                key: None,
+                line: 4,
+                expected_position: 3,
+                before: None,
+            },
+            Violation {
+                table: "dependencies".to_owned(),
+                key: Some("c".to_owned()),
+                line: 8,
+                expected_position: 3,
+                before: None,
+            },
+        ];
+        assert_eq!(expected, find_violations(input, &sorted));
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        let duplicate = |raw: &str| duplicate_key(raw, &raw.parse::<DocumentMut>().unwrap_err());
+        assert_eq!(
+            duplicate("[dependencies]\na = \"1\"\nb.version = \"1\"\nb.version = \"2\"\n").as_deref(),
+            Some("duplicate key `b.version` in [dependencies] on line 3 and line 4")
+        );
+        assert_eq!(
+            duplicate("[dependencies]\na = \"1\"\n\n[dependencies]\n").as_deref(),
+            Some("duplicate table [dependencies] on line 1 and line 4")
+        );
+        assert_eq!(duplicate("[package\n"), None);
+    }
+
+    #[test]
+    fn lints() {
+        let input = r#"[dependencies]
+serde = { version = "1", features = ["derive"] }
+log = "0.4"
+rand = "0.8"
+foo_bar = "1"
+
+[dev-dependencies]
+serde = { features = [ "derive" ], version = "1" }
+log = { version = "0.4" }
+rand = { version = "0.8", features = ["small_rng"] }
+baz = { package = "foo-bar", version = "1" }
+
+[target.'cfg(unix)'.dependencies]
+libc = "0.2"
+
+[target.'cfg(unix)'.dev-dependencies]
+libc = "0.2"
+"#;
+        let lint_at = |rule, table: &str, key: &str, line, message: &str| Lint {
+            rule,
+            severity: Severity::Warn,
+            table: table.to_owned(),
+            key: key.to_owned(),
+            line,
+            message: message.to_owned(),
+        };
+        assert_eq!(
+            lint(input, &Config::default()),
+            vec![
+                lint_at(
+                    "duplicate-dependency",
+                    "dev-dependencies",
+                    "serde",
+                    8,
+                    "`serde` in [dev-dependencies] has the same spec as in [dependencies]"
+                ),
+                lint_at(
+                    "duplicate-dependency",
+                    "dev-dependencies",
+                    "log",
+                    9,
+                    "`log` in [dev-dependencies] has the same spec as in [dependencies]"
+                ),
+                lint_at(
+                    "separator-variant",
+                    "dev-dependencies",
+                    "baz",
+                    11,
+                    "`foo-bar` in [dev-dependencies] is the same crate as `foo_bar` in [dependencies]"
+                ),
+                lint_at(
+                    "duplicate-dependency",
+                    "target.cfg(unix).dev-dependencies",
+                    "libc",
+                    17,
+                    "`libc` in [target.cfg(unix).dev-dependencies] has the same spec as in [target.cfg(unix).dependencies]"
+                ),
+            ]
+        );
+        let cfg = Config {
+            lint_duplicate_dependencies: false,
+            lint_separator_variants: false,
+            ..Config::default()
+        };
+        assert_eq!(lint(input, &cfg), vec![]);
+    }
+
+    #[test]
+    fn duplicate_dependency_kinds() {
+        let input = r#"[build-dependencies]
+cc = "1"
+serde = "1"
+log = "0.4"
+
+[dependencies]
+cc = { version = "1" }
+
+[dev-dependencies]
+serde = "1"
+log = "0.4.20"
+"#;
+        assert_eq!(
+            lint(input, &Config::default())
+                .iter()
+                .map(|l| (l.line, l.message.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (2, "`cc` in [build-dependencies] has the same spec as in [dependencies]"),
+                (3, "`serde` in [build-dependencies] has the same spec as in [dev-dependencies]"),
+            ]
+        );
+    }
+
+    #[test]
+    fn dangling_features() {
+        let input = r#"[features]
+default = ["std", "tls"]
+std = ["serde/std", "dep:log", "missing/std"]
+tls = ["dep:rustls", "dep:serde", "tokio?/rt"]
+
+[dependencies]
+serde = "1"
+log = { version = "0.4", optional = true }
+
+[target.'cfg(unix)'.dependencies]
+tokio = { version = "1", optional = true }
+
+[dev-dependencies]
+rustls = { version = "0.23" }
+"#;
+        let lints = lint(input, &Config::default());
+        assert_eq!(
+            lints
+                .iter()
+                .map(|l| (l.key.as_str(), l.line, l.message.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    "std",
+                    3,
+                    "`missing/std` in feature `std` points at nothing, `missing` is not a dependency"
+                ),
+                (
+                    "tls",
+                    4,
+                    "`dep:rustls` in feature `tls` points at nothing, `rustls` is not an optional dependency"
+                ),
+                (
+                    "tls",
+                    4,
+                    "`dep:serde` in feature `tls` points at nothing, `serde` is not an optional dependency"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn dep_without_implicit_feature() {
+        let input = r#"[features]
+json = ["dep:serde_json"]
+x = ["serde_json", "log"]
+serde = ["dep:serde"]
+y = ["serde"]
+
+[dependencies]
+serde_json = { version = "1", optional = true }
+serde = { version = "1", optional = true }
+log = { version = "0.4", optional = true }
+"#;
+        let lints = lint(input, &Config::default());
+        assert_eq!(
+            lints.iter().map(|l| (l.line, l.message.as_str())).collect::<Vec<_>>(),
+            vec![(
+                3,
+                "`serde_json` in feature `x` points at nothing, `dep:serde_json` leaves `serde_json` without an implicit feature"
+            )]
+        );
+    }
+
+    #[test]
+    fn unused_optional() {
+        let input = r#"[features]
+std = ["serde?/std", "dep:log"]
+json = ["serde_json"]
+
+[dependencies]
+serde = { version = "1", optional = true }
+log = { version = "0.4", optional = true }
+serde_json = { version = "1", optional = true }
+rand = { version = "0.8", optional = true }
+"#;
+        let cfg = Config {
+            lint_unused_optional: true,
+            ..Config::default()
+        };
+        let lints = lint(input, &cfg);
+        assert_eq!(
+            lints.iter().map(|l| (l.line, l.message.as_str())).collect::<Vec<_>>(),
+            vec![(9, "`rand` in [dependencies] is optional but no feature enables it")]
+        );
+    }
+
+    #[test]
+    fn crates_io_limits() {
+        let input = r#"[package]
+name = "foo"
+keywords = ["a", "b", "c", "d", "e", "f"]
+categories = [
+    "development-tools",
+    "command-line-utilities",
+]
+"#;
+        let lints = lint(
+            &input.replace(r#""f""#, r#""much-too-long-of-a-keyword", "c++ tools""#),
+            &Config::default(),
+        );
+        assert_eq!(
+            lints.iter().map(|l| (l.line, l.message.as_str())).collect::<Vec<_>>(),
+            vec![
+                (3, "crates.io takes at most 5 keywords, there are 7"),
+                (
+                    3,
+                    "crates.io takes keywords of at most 20 letters, digits, `_`, `-` or `+`, not `much-too-long-of-a-keyword`"
+                ),
+                (
+                    3,
+                    "crates.io takes keywords of at most 20 letters, digits, `_`, `-` or `+`, not `c++ tools`"
+                ),
+            ]
+        );
+        for publish in ["false", "[]", r#"["my-registry"]"#] {
+            let private = input.replace("name = \"foo\"", &format!("name = \"foo\"\npublish = {publish}"));
+            assert_eq!(lint(&private, &Config::default()), vec![], "publish = {publish}");
+        }
+        for publish in ["true", r#"["my-registry", "crates-io"]"#] {
+            let public = input.replace("name = \"foo\"", &format!("name = \"foo\"\npublish = {publish}"));
+            assert_eq!(lint(&public, &Config::default()).len(), 1, "publish = {publish}");
+        }
+    }
+
+    #[test]
+    fn version_syntax() {
+        for valid in [
+            "1",
+            "1.2",
+            "^1.2.3",
+            "~0.4",
+            ">=1, <3",
+            "=1.0.0-beta.1+build.5",
+            "1.*",
+            "*",
+            "0.10.x",
+            "> 1.2",
+        ] {
+            assert!(is_version_req(valid), "{valid}");
+        }
+        for invalid in ["", "1.2.3.4", "01.2", "1.2-beta", ">=1.*", "*.1", "1.2.3-", "latest", "1,", "^^1"] {
+            assert!(!is_version_req(invalid), "{invalid}");
+        }
+
+        let input = "[dependencies]\na = \"1\"\nb = { version = \"1.2.3.4\" }\n";
+        let lints = lint(input, &Config::default());
+        assert_eq!(
+            lints.iter().map(|l| (l.line, l.message.as_str())).collect::<Vec<_>>(),
+            vec![(3, "`b` in [dependencies] has an invalid version requirement `1.2.3.4`")]
+        );
+    }
+
+    #[test]
+    fn loose_versions() {
+        let input = r#"[dependencies]
+a = "*"
+b = { version = ">=1.2" }
+c = ">=1.2, <2"
+d = { path = "../d" }
+e = "1"
+"#;
+        let cfg = Config {
+            lint_wildcard_versions: true,
+            lint_unbounded_versions: true,
+            ..Config::default()
+        };
+        let lints = lint(input, &cfg);
+        assert_eq!(
+            lints.iter().map(|l| (l.rule, l.line)).collect::<Vec<_>>(),
+            vec![("wildcard-version", 2), ("unbounded-version", 3)]
+        );
+        assert_eq!(lints[0].message, "`a` in [dependencies] accepts any version with `*`");
+        assert_eq!(lint(input, &Config::default()), vec![]);
+    }
+
+    #[test]
+    fn workspace_overlap() {
+        let input = r#"[workspace]
+members = [
+    "crates/a",
+    "./crates/b/",
+    "vendor/c",
+    "tools/d",
+]
+exclude = ["crates/b", "vendor", "tools/*"]
+"#;
+        let lints = lint(input, &Config::default());
+        assert_eq!(
+            lints.iter().map(|l| (l.line, l.message.as_str())).collect::<Vec<_>>(),
+            vec![
+                (4, "`crates/b` is in both workspace.members and workspace.exclude"),
+                (5, "member `vendor/c` is inside `vendor` of workspace.exclude"),
+                (6, "member `tools/d` is inside `tools/*` of workspace.exclude"),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_members() {
+        let root = TempDir::new("missing");
+        std::fs::create_dir_all(root.join("crates/a")).unwrap();
+        std::fs::create_dir_all(root.join("crates/b")).unwrap();
+        std::fs::write(root.join("crates/a/Cargo.toml"), "[package]\nname = \"a\"\n").unwrap();
+        let input = r#"[workspace]
+members = ["crates/a", "crates/b", "crates/gone/", "tools/*"]
+"#;
+        let lints = lint_paths(input, &root, &Config::default());
+        assert_eq!(
+            lints.iter().map(|l| (l.line, l.message.as_str())).collect::<Vec<_>>(),
+            vec![
+                (2, "member `crates/b` has no Cargo.toml"),
+                (2, "member `crates/gone` has no Cargo.toml")
+            ]
+        );
+        let cfg = Config {
+            lint_missing_members: false,
+            ..Config::default()
+        };
+        assert_eq!(lint_paths(input, &root, &cfg), vec![]);
+    }
+
+    #[test]
+    fn unused_workspace_dependencies() {
+        let root = TempDir::new("unused");
+        for (dir, manifest) in [
+            ("crates/a", "[dependencies]\nlog = { workspace = true }\n"),
+            ("crates/b", "[target.'cfg(unix)'.dev-dependencies]\nlibc = { workspace = true }\n"),
+            ("vendor/c", "[dependencies]\nrand = { workspace = true }\n"),
+        ] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+            std::fs::write(root.join(dir).join("Cargo.toml"), manifest).unwrap();
+        }
+        let input = r#"[workspace]
+members = ["crates/*", "vendor/c"]
+
+[workspace.dependencies]
+libc = "0.2"
+log = "0.4"
+rand = "0.9"
+serde = "1"
+toml = "0.9"
+
+[dev-dependencies]
+toml = { workspace = true }
+"#;
+        let lints = lint_paths(input, &root, &Config::default());
+        assert_eq!(
+            lints.iter().map(|l| (l.line, l.message.as_str())).collect::<Vec<_>>(),
+            vec![(8, "`serde` is not inherited by any workspace member")]
+        );
+        let input = input
+            .replace(", \"vendor/c\"", "")
+            .replace("members", "exclude = [\"vendor\"]\nmembers");
+        assert_eq!(lint_paths(&input, &root, &Config::default()).len(), 2);
+
+        let cfg = Config {
+            lint_unused_workspace_dependencies: false,
+            ..Config::default()
+        };
+        assert_eq!(lint_paths(&input, &root, &cfg), vec![]);
+    }
+
+    #[test]
+    fn version_ranges() {
+        let conflict = |a: &str, b: &str| version_range(a).unwrap().intersect(version_range(b).unwrap()).is_none();
+        assert!(conflict("~1.2", "1.5"));
+        assert!(!conflict("~1.2", "1.2.7"));
+        assert!(conflict("~1.2.3", "1.3"));
+        assert!(!conflict("~1", "1.9"));
+        assert!(conflict("=1.2.3", "1.4"));
+        assert!(!conflict("=1.2.3", "1.2"));
+        assert!(conflict("=1.2.3", "=1.2.4"));
+        assert!(conflict("0.0.3", "0.0.4"));
+        assert!(!conflict("0.0.3", "^0.0.3"));
+        assert!(conflict("0.0", "0.1"));
+        assert!(!conflict("0.0", "0.0.7"));
+        assert!(!conflict("1.2", "1.5"));
+        assert!(conflict("0.8.5", "0.9"));
+        assert!(!conflict(">=0.2, <1", "0.2"));
+        assert!(conflict(">=1.2, <1.4", "1.4"));
+        assert!(conflict(">1", "1.9"));
+        assert!(!conflict("<=0.5", "0.5.3"));
+        assert!(conflict("<0.5", "0.5"));
+        assert!(!conflict("1.*", "1.9.0"));
+        assert!(!conflict("*", "0.0.1"));
+        assert_eq!(version_range("1.2.3-beta.1"), Some(Range((1, 2, 3), Some((2, 0, 0)))));
+        assert_eq!(version_range("1.2.3.4"), None);
+        assert_eq!(version_range(">=2, <1"), None);
+        assert_eq!(version_range("latest"), None);
+    }
+
+    #[test]
+    fn version_conflicts() {
+        let root = TempDir::new("conflicts");
+        for (dir, manifest) in [
+            ("a", "[dependencies]\nrand = \"0.8.5\"\nlog = \"0.4\"\nserde = \"1.0\"\n"),
+            ("b", "[dependencies]\nrand = { version = \"^0.9\" }\nlog = \"0.4.20\"\n"),
+            (
+                "c",
+                "[target.'cfg(unix)'.dev-dependencies]\nrandom = { package = \"rand\", version = \"0.9\" }\nlibc = \">=0.2, <1\"\n",
+            ),
+        ] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+            std::fs::write(root.join(dir).join("Cargo.toml"), manifest).unwrap();
+        }
+        let input = r#"[package]
+name = "root"
+
+[workspace]
+members = ["a", "b", "c"]
+
+[dependencies]
+serde = "2"
+libc = "0.2"
+"#;
+        let lints = lint_paths(input, &root, &Config::default());
+        assert_eq!(
+            lints.iter().map(|l| (l.line, l.message.as_str())).collect::<Vec<_>>(),
+            vec![
+                (
+                    5,
+                    "`rand` is required in versions cargo can not unify: `0.8.5` by a; `^0.9` by b, c"
+                ),
+                (5, "`serde` is required in versions cargo can not unify: `1.0` by a; `2` by .")
+            ]
+        );
+        let cfg = Config {
+            lint_version_conflicts: false,
+            ..Config::default()
+        };
+        assert_eq!(lint_paths(input, &root, &cfg), vec![]);
+    }
+
+    #[test]
+    fn severity() {
+        let input = r#"[dependencies]
+a = "*"
+b = "1.2.3.4"
+"#;
+        let mut cfg = Config::default();
+        cfg.severity.insert("wildcard-version".to_owned(), Severity::Warn);
+        cfg.severity.insert("invalid-version".to_owned(), Severity::Off);
+        assert_eq!(
+            lint(input, &cfg).iter().map(|l| (l.rule, l.severity)).collect::<Vec<_>>(),
+            vec![("wildcard-version", Severity::Warn)]
+        );
+
+        let cfg = Config::from_table("[severity]\nunsorted = \"warn\"\n".parse::<DocumentMut>().unwrap().as_table()).unwrap();
+        assert_eq!(cfg.severity_of("unsorted", Severity::Error), Severity::Warn);
+        assert_eq!(cfg.severity_of("unformatted", Severity::Error), Severity::Error);
+        assert_eq!(cfg.severity_of("unused-optional", Severity::Off), Severity::Off);
+
+        // On by default they only warn, turned on they fail
+        let input = "[dependencies]\na = \"1\"\n[dev-dependencies]\na = \"1\"\nb = \"*\"\n";
+        let cfg = Config {
+            lint_wildcard_versions: true,
+            ..Config::default()
+        };
+        assert_eq!(
+            lint(input, &cfg).iter().map(|l| (l.rule, l.severity)).collect::<Vec<_>>(),
+            vec![("duplicate-dependency", Severity::Warn), ("wildcard-version", Severity::Error)]
+        );
+        assert!(Config::from_table("[severity]\nunsorted = \"info\"\n".parse::<DocumentMut>().unwrap().as_table()).is_err());
+    }
+}
@@ -0,0 +1,361 @@
+use std::{
+    collections::BTreeMap,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use clap::crate_name;
+use toml_edit::{DocumentMut, Item, Table, TableLike, Value};
+
+use crate::{
+    CARGO_TOML, Result, editorconfig,
+    fmt::{Config, DEF_TABLE_ORDER},
+};
+
+/// The names of the config file, in the order they are looked for in a directory.
+const CONFIG_FILES: &[&str] = &["cargo-sort-fix.toml", ".cargo-sort-fix.toml", "tomlfmt.toml", ".tomlfmt.toml"];
+
+const PACKAGE: &str = "package";
+const WORKSPACE: &str = "workspace";
+
+/// The file written by `cargo sort-fix init`.
+pub(crate) const INIT_FILE: &str = CONFIG_FILES[0];
+
+/// Returns the config written by `cargo sort-fix init`, the default table
+/// order and every other option commented out with its default.
+pub(crate) fn starter_config() -> String {
+    let table_order = DEF_TABLE_ORDER
+        .iter()
+        .map(|table| format!("    \"{table}\",\n"))
+        .collect::<String>();
+    format!(
+        r#"# The order of the tables in Cargo.toml, tables not listed come after these.
+table_order = [
+{table_order}]
+
+# Tables whose keys are left as they are.
+# skip = ["features", "patch"]
+
+# Tables that always come first or last whatever the ordering.
+# pin_first = ["package"]
+# pin_last = ["profile", "workspace.metadata"]
+
+# Keep blank line separated groups of keys when sorting, like --grouped.
+# grouped = false
+# Merge the groups of dependency tables into one sorted list, like --no-group.
+# ungroup = false
+# With --grouped, keep crate families like `tokio` and `tokio-util` in one group.
+# prefix_affinity = false
+# Sort the `[features]` table and the features each one enables.
+# sort_features = true
+# Remove duplicate entries of the sorted arrays, like --dedup-arrays.
+# dedup_arrays = false
+# Write workspace members and exclude without `./` or a trailing `/`, deduplicated.
+# normalize_workspace_paths = true
+# Compare runs of digits by their value, `crate2` before `crate10`.
+# natural_sort = false
+# Sort `-` and `_` as the same character, `foo_bar` next to `foo-bar`.
+# fold_separators = false
+# Sort renamed dependencies by their `package` instead of the key.
+# sort_by_package = false
+# Crates that come first in every dependency table, in this order.
+# priority_crates = []
+# Inline dependencies on a line longer than this become `[dependencies.name]` tables.
+# max_inline_dependency_len = 100
+# `[dependencies.name]` tables that fit on one line become inline dependencies.
+# inline_dependency_tables = false
+# Keep the `default` feature first in `[features]`.
+# default_feature_first = false
+# Also fail --check if formatting changes the file, like --check-format.
+# check_format = false
+# Warn in --check about a crate with the same spec in [dependencies], [dev-dependencies] or [build-dependencies].
+# lint_duplicate_dependencies = true
+# Warn in --check about a crate written as foo_bar in one place and foo-bar in another.
+# lint_separator_variants = true
+# Fail --check for a `version = "*"` dependency.
+# lint_wildcard_versions = false
+# Fail --check for a `>=` version requirement without an upper bound.
+# lint_unbounded_versions = false
+# Warn in --check about a value of [features] that enables a dependency or feature that does not exist.
+# lint_features = true
+# Fail --check for an optional dependency no value of [features] enables.
+# lint_unused_optional = false
+# Warn in --check about keywords or categories crates.io would reject on publish.
+# lint_crates_io = true
+# Warn in --check about a dependency version requirement that is not valid semver.
+# lint_version_syntax = true
+# Warn in --check about a workspace member that is also in `exclude`.
+# lint_workspace_overlap = true
+# Warn in --check about a workspace member without a Cargo.toml, --fix removes it.
+# lint_missing_members = true
+# Warn in --check about a workspace dependency no member inherits, --fix removes it.
+# lint_unused_workspace_dependencies = true
+# Warn in --check about a crate the workspace members require in versions that do not unify.
+# lint_version_conflicts = true
+# Crates to skip, like --exclude.
+# exclude = []
+# Stop at the first crate that is not sorted or can not be checked, like --fail-fast.
+# fail_fast = false
+
+# The order of keys in `[package]`, `[profile.*]` and inline dependency tables,
+# either "alphabetical", "canonical" or a list of keys. Left untouched when unset.
+# package_key_order = "canonical"
+# profile_key_order = "canonical"
+# dependency_key_order = "canonical"
+
+# Formatting
+# always_trailing_comma = false
+# multiline_trailing_comma = true
+# max_array_line_len = 80
+# array_wrap = "one-per-line"
+# indent_count = 4
+# indent_style = "space"
+# key_indent = 0
+# space_around_eq = true
+# align_dependencies = false
+# normalize_quotes = false
+# compact_arrays = false
+# compact_inline_tables = false
+# trailing_newline = true
+# strip_bom = false
+# key_value_newlines = true
+# allowed_blank_lines = 1
+# blank_lines_between_tables = 1
+# crlf = false
+
+# How the tables matching a dotted path are sorted, the first matching rule wins:
+# "alphabetical", "grouped", "keep", "by-source" (workspace, path, git and
+# registry dependencies in blocks) or "by-key(name)" for arrays of tables.
+# [rules]
+# "target.*.dependencies" = "grouped"
+# features = "keep"
+# "patch.*" = {{ strategy = "alphabetical", reverse = true }}
+
+# Named groups of dependencies by glob, sorted into blank line separated
+# blocks in this order, the dependencies no group matches come last.
+# Write a `# -- async --` comment above every group, `# -- other --` for the rest.
+# group_headers = false
+# [groups]
+# async = ["tokio*", "hyper*"]
+
+# More tables to sort, top level tables sorted like [dependencies] and dotted
+# paths of any table or array.
+# [matcher]
+# headings = ["custom-dependencies"]
+# keys = ["package.metadata.deb.depends"]
+
+# How each problem of --check is reported, "off", "warn" or "error": "unsorted",
+# "unformatted" or a lint like "duplicate-dependency". Warnings do not fail. The lints
+# that are on by default warn, the ones turned on with their lint_* option fail.
+# [severity]
+# unformatted = "warn"
+
+# Settings for a single table, by its name.
+# [tables.dependencies]
+# sort = true
+# grouped = true
+"#
+    )
+}
+
+/// The directory of the crate given on the command line, made absolute so the
+/// config lookup can go up to the workspace root.
+pub(crate) fn manifest_dir(path: &Path) -> PathBuf {
+    let dir = if path.is_file() { path.parent().unwrap_or(path) } else { path };
+    std::env::current_dir().map_or_else(|_| dir.to_path_buf(), |cwd| cwd.join(dir))
+}
+
+/// Reads the config for the crate in `dir`.
+///
+/// Inside a workspace the configs from the workspace root down to the crate
+/// are merged, a setting of a member overrides the same setting of the root
+/// and everything it leaves out is inherited. Outside of a workspace the
+/// closest config of `dir` or its parents is used. The `fallback` directory
+/// is tried last, without any config the defaults are used. The directories
+/// read before are taken from `dirs`.
+pub(crate) fn load(dir: &Path, fallback: &Path, dirs: &mut Dirs) -> Result<Config> {
+    // The configs from the crate up, the closest first
+    let mut layers = vec![];
+    let mut workspace_root = false;
+    for (i, dir) in dir.ancestors().enumerate() {
+        let (file, manifest) = dirs.read(dir)?;
+        if let Some(layer) = config_in(dir, file.as_ref(), manifest.as_ref(), i == 0)? {
+            layers.push(layer);
+        }
+        if manifest.as_ref().is_some_and(|manifest| manifest.contains_key(WORKSPACE)) {
+            workspace_root = true;
+            break;
+        }
+    }
+    if !workspace_root {
+        layers.truncate(1);
+    }
+    if layers.is_empty() {
+        let (file, manifest) = dirs.read(fallback)?;
+        if let Some(layer) = config_in(fallback, file.as_ref(), manifest.as_ref(), false)? {
+            layers.push(layer);
+        }
+    }
+
+    // The `.editorconfig` settings only fill in what no config sets
+    let mut merged = editorconfig::settings(dir);
+    for layer in layers.iter().rev() {
+        merge(&mut merged, layer);
+    }
+    Ok(Config::from_table(&merged)?)
+}
+
+/// The modification times of every file [`load`] may read for `dir`, the
+/// config read before is still good while the stamp is the same.
+pub(crate) type Stamp = Vec<Option<SystemTime>>;
+
+pub(crate) fn stamp(dir: &Path, fallback: &Path) -> Stamp {
+    let names = CONFIG_FILES.iter().chain([&CARGO_TOML, &editorconfig::EDITORCONFIG]);
+    dir.ancestors()
+        .chain([fallback])
+        .flat_map(|dir| names.clone().map(move |name| dir.join(name)))
+        .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+/// The config file and the manifest of the directories read so far, the
+/// crates of one run read the ones of the parents they share once.
+#[derive(Default)]
+pub(crate) struct Dirs(BTreeMap<PathBuf, (Option<Table>, Option<DocumentMut>)>);
+
+impl Dirs {
+    fn read(&mut self, dir: &Path) -> Result<&(Option<Table>, Option<DocumentMut>)> {
+        if !self.0.contains_key(dir) {
+            let read = (config_file(dir)?, read_manifest(dir));
+            self.0.insert(dir.to_path_buf(), read);
+        }
+        Ok(&self.0[dir])
+    }
+}
+
+/// Returns the config of a directory, its config `file` or else the
+/// `[package.metadata.cargo-sort-fix]` or `[workspace.metadata.cargo-sort-fix]`
+/// table of its Cargo.toml.
+///
+/// The package metadata only applies to the crate itself, `own_crate` is false
+/// for the parent directories.
+fn config_in(dir: &Path, file: Option<&Table>, manifest: Option<&DocumentMut>, own_crate: bool) -> Result<Option<Table>> {
+    if let Some(file) = file {
+        return Ok(Some(file.clone()));
+    }
+
+    let Some(manifest) = manifest else {
+        return Ok(None);
+    };
+    let tables: &[&str] = if own_crate { &[PACKAGE, WORKSPACE] } else { &[WORKSPACE] };
+    let metadata = tables
+        .iter()
+        .find_map(|&table| manifest.get(table)?.get("metadata")?.get(crate_name!()));
+    let table = match metadata {
+        Some(Item::Table(table)) => table.clone(),
+        Some(Item::Value(Value::InlineTable(table))) => table.clone().into_table(),
+        _ => return Ok(None),
+    };
+    Config::from_table(&table).map_err(|e| format!("invalid config in {}: {e}", dir.join(CARGO_TOML).display()))?;
+    Ok(Some(table))
+}
+
+/// Reads the first of the [`CONFIG_FILES`] in `dir`.
+fn config_file(dir: &Path) -> Result<Option<Table>> {
+    let Some(path) = CONFIG_FILES.iter().map(|name| dir.join(name)).find(|path| path.is_file()) else {
+        return Ok(None);
+    };
+    let raw = read_to_string(&path)?;
+    let invalid = |e: &dyn std::fmt::Display| format!("invalid config {}: {e}", path.display());
+    // Checked on its own so an error points at the file it is in, and the
+    // line of a syntax error
+    let doc = raw.parse::<DocumentMut>().map_err(|e| invalid(&e))?;
+    Config::from_table(doc.as_table()).map_err(|e| invalid(&e))?;
+    Ok(Some(doc.as_table().clone()))
+}
+
+/// A manifest that does not parse is reported when it is checked, here it
+/// is the same as no manifest.
+fn read_manifest(dir: &Path) -> Option<DocumentMut> {
+    read_to_string(dir.join(CARGO_TOML)).ok()?.parse().ok()
+}
+
+/// Lays the settings of `over` on top of `base`, tables like the `[tables.*]`
+/// overrides are merged key by key.
+fn merge(base: &mut dyn TableLike, over: &dyn TableLike) {
+    for (key, item) in over.iter() {
+        if let Some(over) = item.as_table_like()
+            && let Some(base) = base.get_mut(key).and_then(Item::as_table_like_mut)
+        {
+            merge(base, over);
+        } else {
+            base.insert(key, item.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::{Dirs, load, starter_config};
+    use crate::{
+        fmt::{Config, DEF_TABLE_ORDER, TableConfig},
+        test_utils::TempDir,
+    };
+
+    #[test]
+    fn starter_config_is_default() {
+        let config = starter_config().parse::<Config>().unwrap();
+        assert_eq!(config.table_order, DEF_TABLE_ORDER);
+        assert!(config.sort_features);
+        assert!(config.tables.is_empty());
+    }
+
+    #[test]
+    fn workspace_inheritance() {
+        let root = TempDir::new("config");
+        let member = root.join("crates/a");
+        let other = root.join("crates/b");
+        fs::create_dir_all(&member).unwrap();
+        fs::create_dir_all(&other).unwrap();
+        fs::write(root.join("Cargo.toml"), "[workspace]\nmembers = [\"crates/*\"]\n").unwrap();
+        fs::write(
+            root.join("cargo-sort-fix.toml"),
+            "grouped = true\ntable_order = [\"package\"]\n[tables.dependencies]\nsort = false\n",
+        )
+        .unwrap();
+        fs::write(
+            member.join("Cargo.toml"),
+            "[package]\nname = \"a\"\n[package.metadata.cargo-sort-fix]\ngrouped = false\ntables.dependencies.grouped = true\n",
+        )
+        .unwrap();
+        fs::write(other.join("Cargo.toml"), "[package]\nname = \"b\"\n").unwrap();
+
+        let config = load(&member, &root, &mut Dirs::default()).unwrap();
+        assert!(!config.grouped);
+        assert_eq!(config.table_order, vec!["package".to_owned()]);
+        let table = TableConfig {
+            sort: false,
+            grouped: Some(true),
+        };
+        assert_eq!(config.table("dependencies"), Some(&table));
+
+        let config = load(&other, &root, &mut Dirs::default()).unwrap();
+        assert!(config.grouped);
+        assert_eq!(config.table("dependencies").map(|t| t.grouped), Some(None));
+
+        // The members share the root read for the first of them
+        let mut dirs = Dirs::default();
+        assert!(!load(&member, &root, &mut dirs).unwrap().grouped);
+        fs::remove_file(root.join("cargo-sort-fix.toml")).unwrap();
+        assert!(load(&other, &root, &mut dirs).unwrap().grouped);
+        assert!(!load(&other, &root, &mut Dirs::default()).unwrap().grouped);
+
+        // A syntax error points at its line
+        fs::write(other.join("cargo-sort-fix.toml"), "grouped = [\n").unwrap();
+        let e = load(&other, &root, &mut Dirs::default()).err().unwrap().to_string();
+        assert!(e.contains("cargo-sort-fix.toml: TOML parse error at line 1"), "{e}");
+    }
+}
@@ -0,0 +1,158 @@
+use toml_edit::DocumentMut;
+
+use crate::{merge, sort::DEPENDENCY_KINDS};
+
+const OURS: &str = "<<<<<<<";
+const BASE: &str = "|||||||";
+const THEIRS: &str = "=======";
+const END: &str = ">>>>>>>";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Part {
+    Shared,
+    Ours,
+    Base,
+    Theirs,
+}
+
+/// Resolves the git conflict markers of a manifest by taking the entries of
+/// both sides, if every conflict is inside a dependency table. With the base
+/// section of `merge.conflictStyle = diff3` an entry one side removed stays
+/// removed. Returns `None` without markers, for a conflict outside of the
+/// dependency tables or a side that does not parse, an error if both sides
+/// give the same dependency a different spec.
+pub(crate) fn resolve(raw: &str) -> Result<Option<String>, String> {
+    if !raw.lines().any(|line| line.starts_with(OURS)) {
+        return Ok(None);
+    }
+    let (mut ours, mut base, mut theirs) = (String::new(), String::new(), String::new());
+    let mut part = Part::Shared;
+    // Whether the lines outside of a conflict are in a dependency table
+    let mut dependencies = false;
+    for line in raw.split_inclusive('\n') {
+        part = match part {
+            Part::Shared if line.starts_with(OURS) && dependencies => Part::Ours,
+            Part::Shared if line.starts_with(OURS) => return Ok(None),
+            Part::Ours if line.starts_with(BASE) => Part::Base,
+            Part::Ours | Part::Base if line.starts_with(THEIRS) => Part::Theirs,
+            Part::Theirs if line.starts_with(END) => Part::Shared,
+            part => {
+                if line.trim_start().starts_with('[') {
+                    let header = is_dependency_header(line);
+                    if part != Part::Shared && !header {
+                        return Ok(None);
+                    }
+                    dependencies = header;
+                }
+                match part {
+                    Part::Shared => {
+                        ours.push_str(line);
+                        base.push_str(line);
+                        theirs.push_str(line);
+                    }
+                    Part::Ours => ours.push_str(line),
+                    Part::Base => base.push_str(line),
+                    Part::Theirs => theirs.push_str(line),
+                }
+                continue;
+            }
+        };
+    }
+    if part != Part::Shared {
+        return Ok(None);
+    }
+
+    let (Ok(base), Ok(mut ours), Ok(theirs)) = (
+        base.parse::<DocumentMut>(),
+        ours.parse::<DocumentMut>(),
+        theirs.parse::<DocumentMut>(),
+    ) else {
+        return Ok(None);
+    };
+    let conflicts = merge::merge(&base, &mut ours, &theirs);
+    if !conflicts.is_empty() {
+        let conflicts = conflicts.iter().map(|c| format!("`{c}`")).collect::<Vec<_>>();
+        return Err(format!("both sides of the conflict change {}", conflicts.join(", ")));
+    }
+    Ok(Some(ours.to_string()))
+}
+
+/// Returns true for the heading of a dependency table like `[dependencies]`,
+/// `[workspace.dependencies]`, `[target.'cfg(unix)'.dev-dependencies]` or
+/// `[build-dependencies.cc]`.
+fn is_dependency_header(line: &str) -> bool {
+    let Ok(doc) = line.parse::<DocumentMut>() else {
+        return false;
+    };
+    let mut path = vec![];
+    let mut table = doc.as_table();
+    while let Some((key, item)) = table.iter().next() {
+        path.push(key);
+        let Some(sub) = item.as_table() else {
+            break;
+        };
+        table = sub;
+    }
+    let kind = match path.as_slice() {
+        ["workspace", kind, ..] | ["target", _, kind, ..] => kind,
+        [kind, ..] => kind,
+        [] => return false,
+    };
+    DEPENDENCY_KINDS.contains(kind)
+}
+
+#[cfg(test)]
+mod test {
+    use super::resolve;
+
+    #[test]
+    fn resolve_conflicts() {
+        let input = r#"[package]
+name = "foo"
+
+[dependencies]
+log = "0.4"
+<<<<<<< HEAD
+anyhow = "1"
+serde = "1"
+=======
+serde = "1"
+tokio = "1"
+>>>>>>> feature
+
+[target.'cfg(unix)'.dev-dependencies]
+<<<<<<< HEAD
+=======
+libc = "0.2"
+>>>>>>> feature
+"#;
+        assert_eq!(
+            resolve(input).unwrap().unwrap(),
+            r#"[package]
+name = "foo"
+
+[dependencies]
+log = "0.4"
+anyhow = "1"
+serde = "1"
+tokio = "1"
+
+[target.'cfg(unix)'.dev-dependencies]
+libc = "0.2"
+"#
+        );
+
+        // The base section of diff3 keeps a removal
+        let diff3 = "[dependencies]\n<<<<<<< ours\nb = \"1\"\n||||||| base\na = \"1\"\nb = \"1\"\n=======\na = \"1\"\nb = \"1\"\nc = \"1\"\n>>>>>>> theirs\n";
+        assert_eq!(resolve(diff3).unwrap().unwrap(), "[dependencies]\nb = \"1\"\nc = \"1\"\n");
+
+        let both = "[dependencies]\n<<<<<<< ours\na = \"1\"\n=======\na = \"2\"\n>>>>>>> theirs\n";
+        assert_eq!(resolve(both).unwrap_err(), "both sides of the conflict change `dependencies.a`");
+
+        let package = "[package]\n<<<<<<< ours\nversion = \"1.0.0\"\n=======\nversion = \"2.0.0\"\n>>>>>>> theirs\n";
+        assert_eq!(resolve(package), Ok(None));
+        let header = "[dependencies]\n<<<<<<< ours\n[features]\n=======\na = \"1\"\n>>>>>>> theirs\n";
+        assert_eq!(resolve(header), Ok(None));
+        assert_eq!(resolve("[dependencies]\na = \"1\"\n"), Ok(None));
+    }
+}
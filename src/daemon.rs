@@ -0,0 +1,262 @@
+use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use std::{
+    fs::{DirBuilder, Permissions},
+    io::{self, BufReader},
+    os::unix::{
+        fs::{DirBuilderExt, FileTypeExt, MetadataExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+};
+
+#[cfg(unix)]
+use serde_json::{Value, json};
+
+#[cfg(unix)]
+use crate::{
+    CARGO_TOML,
+    fmt::Severity,
+    lsp::{self, Cache, Server},
+    report::SORTING_RULES,
+    write_green, write_red, write_snippet,
+};
+use crate::{Cli, Result};
+
+/// The name of the socket in the directory of [`default_socket`].
+#[cfg(unix)]
+const SOCKET: &str = "cargo-sort-fix.sock";
+
+/// The socket of `--daemon` and `--client` without `--socket`, in
+/// `$XDG_RUNTIME_DIR` or else in a directory of the temporary directory only
+/// the user can enter. Another user could bind a socket in a shared directory
+/// first and answer the client with any manifest.
+#[cfg(unix)]
+pub(crate) fn default_socket() -> Result<PathBuf> {
+    let uid = current_uid();
+    let dir = match std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            let dir = std::env::temp_dir().join(format!("cargo-sort-fix-{uid}"));
+            match DirBuilder::new().mode(0o700).create(&dir) {
+                Err(e) if e.kind() != io::ErrorKind::AlreadyExists => {
+                    return Err(format!("Failed to create {}: {e}", dir.display()).into());
+                }
+                _ => {}
+            }
+            dir
+        }
+    };
+    // Not a symlink, ours and closed to everyone else
+    let meta = std::fs::symlink_metadata(&dir)?;
+    if !meta.is_dir() || meta.uid() != uid || meta.mode() & 0o077 != 0 {
+        return Err(format!("{} is not a directory only this user can access", dir.display()).into());
+    }
+    Ok(dir.join(SOCKET))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn default_socket() -> Result<PathBuf> {
+    Ok(std::env::temp_dir().join("cargo-sort-fix.sock"))
+}
+
+#[cfg(unix)]
+fn current_uid() -> u32 {
+    // SAFETY: getuid has no preconditions and can not fail
+    unsafe { libc::getuid() }
+}
+
+/// Serves the requests of `--lsp` on a socket until it is killed. Every
+/// connection is a session of its own, they share the configs read and the
+/// manifests sorted so far.
+#[cfg(unix)]
+pub(crate) fn run(cli: &Cli, cwd: &Path, socket: &Path) -> Result<bool> {
+    if UnixStream::connect(socket).is_ok() {
+        return Err(format!("A daemon is listening on {} already", socket.display()).into());
+    }
+    // Left behind by a daemon that was killed
+    if std::fs::symlink_metadata(socket).is_ok_and(|m| m.file_type().is_socket()) {
+        std::fs::remove_file(socket)?;
+    }
+    let listener = UnixListener::bind(socket).map_err(|e| format!("Failed to listen on {}: {e}", socket.display()))?;
+    // A `--socket` in a shared directory only takes connections of this user
+    std::fs::set_permissions(socket, Permissions::from_mode(0o600))?;
+    write_green("Listening: ", socket.display())?;
+
+    let cache = Cache::default();
+    std::thread::scope(|scope| {
+        for stream in listener.incoming().flatten() {
+            let cache = &cache;
+            scope.spawn(move || {
+                let mut server = Server::new(cli, cwd, cache);
+                // A client that goes away only ends its own session
+                let _ = lsp::serve(&mut server, &mut BufReader::new(&stream), &mut &stream);
+            });
+        }
+    });
+    Ok(true)
+}
+
+/// Checks or sorts the manifests with the daemon listening on `socket`, the
+/// options that change the sorting are the ones the daemon was started with.
+#[cfg(unix)]
+pub(crate) fn client(cli: &Cli, socket: &Path, paths: &[String]) -> Result<bool> {
+    // The answers are written into the manifests, they must come from a daemon of this user
+    if let Ok(meta) = std::fs::symlink_metadata(socket)
+        && meta.uid() != current_uid()
+    {
+        return Err(format!("{} belongs to another user, not connecting to it", socket.display()).into());
+    }
+    let stream = UnixStream::connect(socket).map_err(|_| {
+        format!(
+            "No daemon is listening on {}, start one with `cargo sort-fix --daemon`",
+            socket.display()
+        )
+    })?;
+    let mut input = BufReader::new(&stream);
+    let mut output = &stream;
+    let method = if cli.check {
+        "cargo-sort-fix/check"
+    } else {
+        "textDocument/formatting"
+    };
+
+    let mut failed = 0;
+    let mut linted = 0;
+    let mut errored = 0;
+    for (id, path) in paths.iter().enumerate() {
+        let mut path = PathBuf::from(path);
+        if path.is_dir() {
+            path.push(CARGO_TOML);
+        }
+        let krate = path.components().nth_back(1).map(|c| c.as_os_str().to_string_lossy().into_owned());
+        let (Ok(absolute), Some(krate)) = (path.canonicalize(), krate) else {
+            write_red("error: ", format!("No file found at: {}", path.display()))?;
+            errored += 1;
+            continue;
+        };
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": {"textDocument": {"uri": lsp::uri_of(&absolute)}},
+        });
+        lsp::write_message(&mut output, &request.to_string())?;
+        let reply = lsp::read_message(&mut input)?
+            .and_then(|reply| serde_json::from_str::<Value>(&reply).ok())
+            .ok_or("The daemon closed the connection")?;
+        let Some(result) = reply["result"].as_array() else {
+            write_red("error: ", format!("Failed to parse {}", path.display()))?;
+            errored += 1;
+            continue;
+        };
+
+        if cli.check {
+            write_green("Checking ", format!("{krate}..."))?;
+            let source = std::fs::read_to_string(&path).unwrap_or_default();
+            let file = path.display().to_string();
+            let (mut unsorted, mut lint) = (false, false);
+            for diagnostic in result {
+                let line = diagnostic["range"]["start"]["line"].as_u64().map_or(1, |line| line as usize + 1);
+                let severity = if diagnostic["severity"] == 2 {
+                    Severity::Warn
+                } else {
+                    let code = diagnostic["code"].as_str().unwrap_or_default();
+                    if SORTING_RULES.contains(&code) {
+                        unsorted = true;
+                    } else {
+                        lint = true;
+                    }
+                    Severity::Error
+                };
+                let message = diagnostic["message"].as_str().unwrap_or_default();
+                write_snippet(severity, message, &file, (&source, line), None)?;
+            }
+            if unsorted {
+                failed += 1;
+            } else if lint {
+                linted += 1;
+            }
+            continue;
+        }
+
+        match result.first().and_then(|edit| edit["newText"].as_str()) {
+            Some(sorted) => {
+                std::fs::write(&path, sorted)?;
+                write_green("Finished: ", format!("{CARGO_TOML} for {krate:?} has been rewritten"))?;
+            }
+            None => write_green("Finished: ", format!("{CARGO_TOML} for {krate} is sorted already, no changes made"))?,
+        }
+    }
+
+    let total = paths.len();
+    if total > 1 {
+        write_green("Finished: ", format!("{} of {total} crates are sorted", total - failed - errored))?;
+    }
+    if errored > 0 {
+        return Err(format!("{errored} of {total} {CARGO_TOML} files could not be checked").into());
+    }
+    if failed > 0 {
+        write_red("error: ", format!("{failed} of {total} {CARGO_TOML} files are not sorted"))?;
+    }
+    if linted > 0 {
+        write_red("error: ", format!("{linted} of {total} {CARGO_TOML} files fail a lint"))?;
+    }
+    Ok(failed + linted == 0)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn run(_: &Cli, _: &Path, _: &Path) -> Result<bool> {
+    Err("--daemon needs unix domain sockets".into())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn client(_: &Cli, _: &Path, _: &[String]) -> Result<bool> {
+    Err("--client needs unix domain sockets".into())
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use std::os::unix::{fs::MetadataExt, net::UnixStream};
+
+    use super::{client, default_socket, run};
+    use crate::{Cli, test_utils::TempDir};
+
+    #[test]
+    fn daemon() {
+        let dir = TempDir::new("daemon");
+        std::fs::create_dir_all(dir.join("a")).unwrap();
+        let socket = dir.join("daemon.sock");
+        let manifest = dir.join("a/Cargo.toml");
+        std::fs::write(&manifest, "[dependencies]\nb = \"1\"\na = \"1\"\n").unwrap();
+
+        let daemon = <Cli as clap::Parser>::parse_from(["cargo-sort-fix", "--daemon"]);
+        let (path, daemon_dir) = (socket.clone(), dir.to_path_buf());
+        std::thread::spawn(move || run(&daemon, &daemon_dir, &path));
+        while UnixStream::connect(&socket).is_err() {
+            std::thread::yield_now();
+        }
+
+        let paths = [manifest.display().to_string()];
+        let check = <Cli as clap::Parser>::parse_from(["cargo-sort-fix", "--client", "--check"]);
+        assert!(!client(&check, &socket, &paths).unwrap());
+        let sort = <Cli as clap::Parser>::parse_from(["cargo-sort-fix", "--client"]);
+        assert!(client(&sort, &socket, &paths).unwrap());
+        assert_eq!(
+            std::fs::read_to_string(&manifest).unwrap(),
+            "[dependencies]\na = \"1\"\nb = \"1\"\n"
+        );
+        assert!(client(&check, &socket, &paths).unwrap());
+
+        // The config is read again once it changes
+        std::fs::write(dir.join("a/cargo-sort-fix.toml"), "[severity]\nunsorted = \"off\"\n").unwrap();
+        std::fs::write(&manifest, "[dependencies]\nb = \"1\"\na = \"1\"\n").unwrap();
+        assert!(client(&check, &socket, &paths).unwrap());
+
+        assert!(client(&check, &dir.join("none.sock"), &paths).is_err());
+
+        // Only the user can get at the default socket
+        let socket = default_socket().unwrap();
+        let parent = std::fs::metadata(socket.parent().unwrap()).unwrap();
+        assert_eq!(parent.mode() & 0o077, 0);
+    }
+}
@@ -0,0 +1,342 @@
+use std::collections::BTreeMap;
+
+use toml_edit::{DocumentMut, Item, Table, TableLike, Value};
+
+use crate::sort::DEPENDENCY_KINDS;
+
+/// Lines of unchanged context around every hunk.
+const CONTEXT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Returns the unified diff between the original and the sorted file, empty
+/// if no line changed.
+///
+/// The output looks like that of `diff -u` so CI logs show which lines are out of order.
+pub(crate) fn unified_diff(old: &str, new: &str, path: &str) -> String {
+    let old_lines = old.lines().collect::<Vec<_>>();
+    let new_lines = new.lines().collect::<Vec<_>>();
+    let ops = diff_lines(&old_lines, &new_lines);
+
+    let changes = ops
+        .iter()
+        .enumerate()
+        .filter(|(_, (op, _))| *op != Op::Equal)
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    let mut out = format!("--- {path}\n+++ {path}\n");
+    let mut first = 0;
+    while first < changes.len() {
+        // Changes close enough to share their context end up in the same hunk
+        let mut last = first;
+        while last + 1 < changes.len() && changes[last + 1] - changes[last] <= 2 * CONTEXT {
+            last += 1;
+        }
+        let from = changes[first].saturating_sub(CONTEXT);
+        let to = (changes[last] + CONTEXT + 1).min(ops.len());
+
+        let old_start = ops[..from].iter().filter(|(op, _)| *op != Op::Insert).count();
+        let new_start = ops[..from].iter().filter(|(op, _)| *op != Op::Delete).count();
+        let old_len = ops[from..to].iter().filter(|(op, _)| *op != Op::Insert).count();
+        let new_len = ops[from..to].iter().filter(|(op, _)| *op != Op::Delete).count();
+        out.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(old_start, old_len),
+            hunk_range(new_start, new_len)
+        ));
+
+        for (op, line) in &ops[from..to] {
+            let sign = match op {
+                Op::Equal => ' ',
+                Op::Delete => '-',
+                Op::Insert => '+',
+            };
+            out.push(sign);
+            out.push_str(line);
+            out.push('\n');
+        }
+        first = last + 1;
+    }
+    out
+}
+
+/// Returns the items of `old` that are not part of the longest common
+/// subsequence with `new`, the items that moved.
+pub(crate) fn moved<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<&'a str> {
+    diff_lines(old, new)
+        .into_iter()
+        .filter(|(op, _)| *op == Op::Delete)
+        .map(|(_, item)| item)
+        .collect()
+}
+
+/// The keys of every table of a manifest with their values written out the
+/// same way whatever their formatting, by the heading of the table.
+type Flattened = Vec<(String, BTreeMap<String, String>)>;
+
+/// Returns the keys that were added, removed or changed from `old` to `new`
+/// under the heading of their table, empty if the manifests only differ in
+/// order and formatting. A dependency is compared as a whole, `"1"` is the
+/// same as `{ version = "1" }`.
+pub(crate) fn manifest_diff(old: &DocumentMut, new: &DocumentMut, old_path: &str, new_path: &str) -> String {
+    let (mut old_tables, mut new_tables) = (vec![], vec![]);
+    flatten(old.as_table(), "", String::new(), false, &mut old_tables);
+    flatten(new.as_table(), "", String::new(), false, &mut new_tables);
+
+    // The tables in the order of the new file, the removed ones last
+    let mut paths = new_tables.iter().map(|(path, _)| path).collect::<Vec<_>>();
+    paths.extend(
+        old_tables
+            .iter()
+            .map(|(path, _)| path)
+            .filter(|p| !new_tables.iter().any(|(n, _)| n == *p)),
+    );
+    let find = |tables: &Flattened, path: &str| tables.iter().find(|(p, _)| p == path).map(|(_, keys)| keys.clone());
+
+    let mut out = String::new();
+    for path in paths {
+        let old_keys = find(&old_tables, path).unwrap_or_default();
+        let new_keys = find(&new_tables, path).unwrap_or_default();
+        let mut lines = vec![];
+        for (key, value) in &old_keys {
+            match new_keys.get(key) {
+                Some(new) if new == value => {}
+                Some(new) => {
+                    lines.push(format!("-{key} = {value}"));
+                    lines.push(format!("+{key} = {new}"));
+                }
+                None => lines.push(format!("-{key} = {value}")),
+            }
+        }
+        for (key, value) in new_keys.iter().filter(|(key, _)| !old_keys.contains_key(*key)) {
+            lines.push(format!("+{key} = {value}"));
+        }
+        if lines.is_empty() {
+            continue;
+        }
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        // The keys before the first table have no heading
+        if !path.is_empty() {
+            out.push_str(&format!("{path}\n"));
+        }
+        out.push_str(&format!("{}\n", lines.join("\n")));
+    }
+    if out.is_empty() {
+        return out;
+    }
+    format!("--- {old_path}\n+++ {new_path}\n{out}")
+}
+
+/// Collects the keys of `table` and of the tables below it, the specs of a
+/// dependency table and the dotted keys are kept whole. The entries of an
+/// array of tables are told apart by their position, `[[bin]] #2`.
+fn flatten(table: &Table, path: &str, heading: String, dependencies: bool, tables: &mut Flattened) {
+    let mut keys = BTreeMap::new();
+    for (key, item) in table.iter() {
+        let child = if path.is_empty() { key.to_owned() } else { format!("{path}.{key}") };
+        match item {
+            Item::Table(sub) if !dependencies && !sub.is_dotted() => {
+                flatten(sub, &child, format!("[{child}]"), DEPENDENCY_KINDS.contains(&key), tables);
+            }
+            Item::ArrayOfTables(array) => {
+                for (idx, sub) in array.iter().enumerate() {
+                    flatten(sub, &child, format!("[[{child}]] #{}", idx + 1), false, tables);
+                }
+            }
+            Item::None => {}
+            _ => {
+                keys.insert(key.to_owned(), normalized(item, dependencies));
+            }
+        }
+    }
+    if !keys.is_empty() || !table.is_implicit() {
+        tables.push((heading, keys));
+    }
+}
+
+/// An item written out the same way whatever its formatting and the order of
+/// its keys. A `dependency` spec of only a `version` is written as the plain
+/// version.
+pub(crate) fn normalized(item: &Item, dependency: bool) -> String {
+    if dependency
+        && let Some(spec) = item.as_table_like()
+        && spec.len() == 1
+        && let Some(version) = spec.get("version").and_then(Item::as_value)
+        && version.is_str()
+    {
+        return value(version);
+    }
+    match item {
+        Item::Value(v) => value(v),
+        Item::Table(table) => table_like(table),
+        Item::ArrayOfTables(array) => {
+            let tables = array.iter().map(|t| table_like(t)).collect::<Vec<_>>();
+            format!("[{}]", tables.join(", "))
+        }
+        Item::None => String::new(),
+    }
+}
+
+fn value(v: &Value) -> String {
+    match v {
+        Value::Array(arr) => format!("[{}]", arr.iter().map(value).collect::<Vec<_>>().join(", ")),
+        Value::InlineTable(table) => table_like(table),
+        other => other.clone().decorated("", "").to_string(),
+    }
+}
+
+/// An inline table with its keys sorted.
+fn table_like(table: &dyn TableLike) -> String {
+    let mut pairs = table
+        .iter()
+        .map(|(key, item)| format!("{key} = {}", normalized(item, false)))
+        .collect::<Vec<_>>();
+    pairs.sort();
+    format!("{{ {} }}", pairs.join(", "))
+}
+
+fn hunk_range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{start},0"),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{len}", start + 1),
+    }
+}
+
+/// A line diff based on the longest common subsequence, manifests are small
+/// enough that the quadratic table is not a problem.
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(Op, &'a str)> {
+    let (n, m) = (old.len(), new.len());
+    // lcs[i][j] is the length of the common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0_u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut ops = Vec::with_capacity(n.max(m));
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if old[i] == new[j] {
+            ops.push((Op::Equal, old[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            ops.push((Op::Delete, old[i]));
+            i += 1;
+        } else {
+            ops.push((Op::Insert, new[j]));
+            j += 1;
+        }
+    }
+    ops.extend(old[i..].iter().map(|&l| (Op::Delete, l)));
+    ops.extend(new[j..].iter().map(|&l| (Op::Insert, l)));
+    ops
+}
+
+#[cfg(test)]
+mod test {
+    use toml_edit::DocumentMut;
+
+    use super::{manifest_diff, unified_diff};
+
+    #[test]
+    fn unified() {
+        let old = "[package]\nname = \"foo\"\n\n[dependencies]\nc = \"1\"\na = \"1\"\nb = \"1\"\n";
+        let new = "[package]\nname = \"foo\"\n\n[dependencies]\na = \"1\"\nb = \"1\"\nc = \"1\"\n";
+        let expected = concat!(
+            "--- Cargo.toml\n",
+            "+++ Cargo.toml\n",
+            "@@ -2,6 +2,6 @@\n",
+            " name = \"foo\"\n",
+            " \n",
+            " [dependencies]\n",
+            "-c = \"1\"\n",
+            " a = \"1\"\n",
+            " b = \"1\"\n",
+            "+c = \"1\"\n",
+        );
+        similar_asserts::assert_eq!(expected, unified_diff(old, new, "Cargo.toml"));
+        assert_eq!("", unified_diff(old, old, "Cargo.toml"));
+    }
+
+    #[test]
+    fn manifest() {
+        let old = r#"name = "top"
+
+[package]
+name = "foo"
+version = "0.1.0"
+
+[dependencies]
+log = { version = "0.4" }
+serde = "1"
+
+[target.'cfg(unix)'.dependencies]
+libc = "0.2"
+
+[[bin]]
+name = "foo"
+"#
+        .parse::<DocumentMut>()
+        .unwrap();
+        let new = r#"name = "top"
+
+[package]
+version = "0.2.0"
+name = "foo"
+
+[dependencies]
+anyhow = "1"
+log = "0.4"
+
+[dependencies.serde]
+features = ["derive"]
+version = "1"
+
+[[bin]]
+name = "foo"
+
+[[bin]]
+name = "bar"
+"#
+        .parse::<DocumentMut>()
+        .unwrap();
+        let expected = concat!(
+            "--- old/Cargo.toml\n",
+            "+++ Cargo.toml\n",
+            "[package]\n",
+            "-version = \"0.1.0\"\n",
+            "+version = \"0.2.0\"\n",
+            "\n",
+            "[dependencies]\n",
+            "-serde = \"1\"\n",
+            "+serde = { features = [\"derive\"], version = \"1\" }\n",
+            "+anyhow = \"1\"\n",
+            "\n",
+            "[[bin]] #2\n",
+            "+name = \"bar\"\n",
+            "\n",
+            "[target.cfg(unix).dependencies]\n",
+            "-libc = \"0.2\"\n",
+        );
+        similar_asserts::assert_eq!(expected, manifest_diff(&old, &new, "old/Cargo.toml", "Cargo.toml"));
+        assert_eq!("", manifest_diff(&old, &old, "Cargo.toml", "Cargo.toml"));
+    }
+}
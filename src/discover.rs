@@ -0,0 +1,312 @@
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use toml_edit::{DocumentMut, Item};
+
+use crate::{CARGO_TOML, Result};
+
+/// Directories that never hold a crate of their own.
+const SKIPPED_DIRS: &[&str] = &["target"];
+
+/// Returns true if the path given on the command line is a glob pattern
+/// rather than a file or directory.
+pub(crate) fn is_glob(path: &str) -> bool {
+    path.contains(['*', '?', '['])
+}
+
+/// Expands a glob pattern like `crates/*` or `crates/**` into the crates it matches.
+///
+/// A match is kept if it is a `Cargo.toml` file or a directory that contains
+/// one, everything else the pattern matches (`src`, `README.md`) is skipped.
+pub(crate) fn expand_glob(pattern: &str) -> Result<Vec<PathBuf>> {
+    let mut found = Vec::new();
+    for path in glob::glob(pattern)? {
+        let path = path?;
+        let is_manifest = path.file_name().is_some_and(|name| name == CARGO_TOML);
+        if (path.is_file() && is_manifest) || (path.is_dir() && path.join(CARGO_TOML).is_file()) {
+            found.push(path);
+        }
+    }
+    Ok(found)
+}
+
+/// Collects `dir` and every directory below it that contains a `Cargo.toml`.
+///
+/// Hidden directories and `target` directories are not entered, with
+/// `respect_gitignore` neither are the directories git ignores.
+pub(crate) fn find_manifests(dir: &Path, respect_gitignore: bool, found: &mut Vec<PathBuf>) -> io::Result<()> {
+    let ignored = if respect_gitignore { ignored_dirs(dir) } else { BTreeSet::new() };
+    walk(dir, &ignored, found)
+}
+
+fn walk(dir: &Path, ignored: &BTreeSet<PathBuf>, found: &mut Vec<PathBuf>) -> io::Result<()> {
+    if dir.join(CARGO_TOML).is_file() {
+        found.push(dir.to_path_buf());
+    }
+
+    let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with('.') || SKIPPED_DIRS.contains(&name.as_ref()) {
+            continue;
+        }
+        let path = entry.path();
+        if entry.file_type()?.is_dir() && !ignored.contains(&path) {
+            walk(&path, ignored, found)?;
+        }
+    }
+    Ok(())
+}
+
+/// Asks git for the directories below `dir` it ignores, by any `.gitignore` of
+/// the repository, `.git/info/exclude` or `core.excludesFile`. Outside of a
+/// repository, or without git, nothing is ignored.
+fn ignored_dirs(dir: &Path) -> BTreeSet<PathBuf> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["ls-files", "--others", "--ignored", "--exclude-standard", "--directory", "-z"])
+        .output();
+    let Some(output) = output.ok().filter(|output| output.status.success()) else {
+        return BTreeSet::new();
+    };
+    // The paths are relative to `dir`, a directory ends with a `/`
+    output
+        .stdout
+        .split(|&byte| byte == 0)
+        .filter_map(|path| std::str::from_utf8(path).ok()?.strip_suffix('/'))
+        .map(|path| dir.join(path))
+        .collect()
+}
+
+/// Asks git for the `Cargo.toml` files of the repository of `dir` that differ
+/// from where the current branch forked off `base`, committed or not, and the
+/// new ones git does not track yet. The paths are canonical.
+pub(crate) fn changed_manifests(dir: &Path, base: &str) -> Result<Vec<PathBuf>> {
+    let git = |args: &[&str]| -> Result<String> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .output()
+            .map_err(|e| format!("Failed to run git: {e}"))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(format!("git {} failed: {}", args.join(" "), stderr.trim()).into());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    };
+    let root = PathBuf::from(git(&["rev-parse", "--show-toplevel"])?.trim());
+    let changed = git(&["diff", "--name-only", "--diff-filter=d", "--merge-base", base, "--"])?;
+    // `:/` lists the whole repository like the diff does, not only `dir`
+    let untracked = git(&["ls-files", "--others", "--exclude-standard", "--full-name", "--", ":/"])?;
+    Ok(changed
+        .lines()
+        .chain(untracked.lines())
+        .filter(|path| Path::new(path).file_name().is_some_and(|name| name == CARGO_TOML))
+        .filter_map(|path| root.join(path).canonicalize().ok())
+        .collect())
+}
+
+/// Returns the manifest of the workspace root of the crate at `manifest` the
+/// way cargo finds it: the manifest itself if it has a `[workspace]`, the one
+/// its `package.workspace` points to or else the closest parent directory
+/// with a `[workspace]`. Outside of a workspace it is the manifest itself.
+pub(crate) fn workspace_root(manifest: &Path) -> Result<PathBuf> {
+    let read = |path: &Path| -> Result<DocumentMut> {
+        let raw = fs::read_to_string(path).map_err(|_| format!("No file found at: {}", path.display()))?;
+        raw.parse::<DocumentMut>()
+            .map_err(|e| format!("Failed to parse {}: {e}", path.display()).into())
+    };
+    let doc = read(manifest)?;
+    if doc.contains_key("workspace") {
+        return Ok(manifest.to_path_buf());
+    }
+    let dir = std::path::absolute(manifest)?.parent().map(Path::to_path_buf).unwrap_or_default();
+    if let Some(root) = doc.get("package").and_then(|p| p.get("workspace")).and_then(Item::as_str) {
+        return Ok(dir.join(root).join(CARGO_TOML));
+    }
+    for dir in dir.ancestors().skip(1) {
+        let path = dir.join(CARGO_TOML);
+        if path.is_file() && read(&path)?.contains_key("workspace") {
+            return Ok(path);
+        }
+    }
+    Ok(manifest.to_path_buf())
+}
+
+/// Returns true if the crate at `path` matches one of the `--exclude` patterns.
+///
+/// The patterns are matched against the path relative to `base` (the current
+/// directory) both with and without the trailing `Cargo.toml`.
+pub(crate) fn is_excluded(path: &Path, base: &Path, excludes: &[glob::Pattern]) -> bool {
+    let path = path.strip_prefix(base).unwrap_or(path);
+    let path = path.strip_prefix(".").unwrap_or(path);
+    let dir = if path.file_name().is_some_and(|name| name == CARGO_TOML) {
+        path.parent().unwrap_or(path)
+    } else {
+        path
+    };
+    excludes
+        .iter()
+        .any(|pat| pat.matches_path(dir) || pat.matches_path(&dir.join(CARGO_TOML)))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, path::PathBuf};
+
+    use super::{expand_glob, find_manifests, is_excluded, workspace_root};
+    use crate::test_utils::TempDir;
+
+    fn crate_tree(name: &str) -> TempDir {
+        let root = TempDir::new(name);
+        for dir in ["", "crates/a", "crates/b/src", "crates/b/nested", "target/debug", ".git"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for manifest in [
+            "Cargo.toml",
+            "crates/a/Cargo.toml",
+            "crates/b/Cargo.toml",
+            "crates/b/nested/Cargo.toml",
+            "target/debug/Cargo.toml",
+        ] {
+            fs::write(root.join(manifest), "[package]\n").unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn recursive_discovery() {
+        let root = crate_tree("recursive");
+        let mut found = vec![];
+        find_manifests(&root, true, &mut found).unwrap();
+        let expected = vec![
+            root.to_path_buf(),
+            root.join("crates/a"),
+            root.join("crates/b"),
+            root.join("crates/b/nested"),
+        ];
+        assert_eq!(found, expected);
+
+        let found = expand_glob(&format!("{}/crates/*", root.display())).unwrap();
+        assert_eq!(found, vec![root.join("crates/a"), root.join("crates/b")]);
+    }
+
+    #[test]
+    fn workspace_roots() {
+        let root = crate_tree("workspace-root");
+        fs::write(root.join("Cargo.toml"), "[workspace]\nmembers = [\"crates/*\"]\n").unwrap();
+        fs::write(root.join("crates/b/nested/Cargo.toml"), "[package]\nworkspace = \"../../a\"\n").unwrap();
+        fs::write(root.join("crates/a/Cargo.toml"), "[workspace]\n").unwrap();
+
+        let manifest = root.join("Cargo.toml");
+        assert_eq!(workspace_root(&manifest).unwrap(), manifest);
+        assert_eq!(workspace_root(&root.join("crates/b/Cargo.toml")).unwrap(), manifest);
+        assert_eq!(
+            workspace_root(&root.join("crates/b/nested/Cargo.toml")).unwrap(),
+            root.join("crates/b/nested/../../a/Cargo.toml")
+        );
+        assert!(workspace_root(&root.join("crates/c/Cargo.toml")).is_err());
+    }
+
+    #[test]
+    fn exclude_patterns() {
+        let base = PathBuf::from("/work");
+        let excludes = ["vendor/*", "**/fuzz"].map(|p| glob::Pattern::new(p).unwrap());
+        assert!(is_excluded(&base.join("vendor/foo"), &base, &excludes));
+        assert!(is_excluded(&PathBuf::from("./vendor/foo/Cargo.toml"), &base, &excludes));
+        assert!(is_excluded(&base.join("crates/a/fuzz"), &base, &excludes));
+        assert!(!is_excluded(&base.join("crates/a"), &base, &excludes));
+        assert!(!is_excluded(&base, &base, &excludes));
+    }
+
+    #[test]
+    fn gitignore_discovery() {
+        let root = crate_tree("gitignore");
+        let git = |args: &[&str]| {
+            let status = std::process::Command::new("git").arg("-C").arg(&*root).args(args).status().unwrap();
+            assert!(status.success(), "git {args:?}");
+        };
+        fs::write(root.join(".gitignore"), "# generated\n/crates/b/\n!crates/b/nested\nvendor\n").unwrap();
+        fs::create_dir_all(root.join("crates/vendor/c")).unwrap();
+        fs::write(root.join("crates/vendor/c/Cargo.toml"), "[package]\n").unwrap();
+
+        // Outside of a repository a `.gitignore` means nothing
+        let mut found = vec![];
+        find_manifests(&root, true, &mut found).unwrap();
+        assert_eq!(found.len(), 5);
+
+        git(&["init", "-q"]);
+        let mut found = vec![];
+        find_manifests(&root, true, &mut found).unwrap();
+        assert_eq!(found, vec![root.to_path_buf(), root.join("crates/a")]);
+
+        // The `.gitignore` above the directory walked counts too
+        let mut found = vec![];
+        find_manifests(&root.join("crates"), true, &mut found).unwrap();
+        assert_eq!(found, vec![root.join("crates/a")]);
+
+        let mut found = vec![];
+        find_manifests(&root, false, &mut found).unwrap();
+        assert_eq!(found.len(), 5);
+
+        fs::write(root.join(".gitignore"), "").unwrap();
+        fs::write(root.join(".git/info/exclude"), "a/\n").unwrap();
+        fs::write(root.join("excludes"), "nested\n").unwrap();
+        git(&["config", "core.excludesFile", &root.join("excludes").to_string_lossy()]);
+        let mut found = vec![];
+        find_manifests(&root, true, &mut found).unwrap();
+        assert_eq!(found, vec![root.to_path_buf(), root.join("crates/b"), root.join("crates/vendor/c")]);
+    }
+
+    #[test]
+    fn changed_manifests() {
+        let root = TempDir::new("changed");
+        let git = |args: &[&str]| {
+            let status = std::process::Command::new("git")
+                .arg("-C")
+                .arg(&*root)
+                .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success(), "git {args:?}");
+        };
+        for dir in ["crates/a", "crates/b", "crates/c"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for manifest in ["Cargo.toml", "crates/a/Cargo.toml", "crates/b/Cargo.toml"] {
+            fs::write(root.join(manifest), "[package]\n").unwrap();
+        }
+        git(&["init", "-q", "-b", "main"]);
+        git(&["add", "-A"]);
+        git(&["commit", "-q", "-m", "base"]);
+        fs::write(root.join("crates/a/Cargo.toml"), "[package]\nname = \"a\"\n").unwrap();
+        fs::write(root.join("crates/b/README.md"), "").unwrap();
+        fs::write(root.join("crates/c/Cargo.toml"), "[package]\n").unwrap();
+
+        let mut changed = super::changed_manifests(&root, "main").unwrap();
+        changed.sort();
+        let canonical = root.canonicalize().unwrap();
+        assert_eq!(
+            changed,
+            vec![canonical.join("crates/a/Cargo.toml"), canonical.join("crates/c/Cargo.toml")]
+        );
+        // The same from a subdirectory, `crates/c` is outside of it
+        let mut changed = super::changed_manifests(&root.join("crates/a"), "main").unwrap();
+        changed.sort();
+        assert_eq!(
+            changed,
+            vec![canonical.join("crates/a/Cargo.toml"), canonical.join("crates/c/Cargo.toml")]
+        );
+        assert!(super::changed_manifests(&root, "no-such-branch").is_err());
+    }
+}
@@ -0,0 +1,150 @@
+use std::{collections::BTreeMap, fs::read_to_string, path::Path};
+
+use toml_edit::{Table, value};
+
+use crate::CARGO_TOML;
+
+pub(crate) const EDITORCONFIG: &str = ".editorconfig";
+
+/// Returns the settings the `.editorconfig` files give the `Cargo.toml` in
+/// `dir` as a config table, the layer every `cargo-sort-fix.toml` goes over.
+///
+/// `end_of_line`, `insert_final_newline`, `indent_style` and `indent_size`
+/// are read, they set `crlf`, `trailing_newline`, `indent_style` and
+/// `indent_count`. The files are read from `dir` up to the first one with
+/// `root = true`, a closer file overrides the ones above it.
+pub(crate) fn settings(dir: &Path) -> Table {
+    let mut files = vec![];
+    for dir in dir.ancestors() {
+        let Ok(text) = read_to_string(dir.join(EDITORCONFIG)) else {
+            continue;
+        };
+        let root = sections(&text)
+            .next()
+            .is_some_and(|(glob, props)| glob.is_none() && props.get("root").is_some_and(|v| v == "true"));
+        files.push((dir, text));
+        if root {
+            break;
+        }
+    }
+
+    let manifest = dir.join(CARGO_TOML);
+    let mut props = BTreeMap::new();
+    for (base, text) in files.iter().rev() {
+        let Ok(relative) = manifest.strip_prefix(base) else {
+            continue;
+        };
+        for (glob, section) in sections(text) {
+            if glob.is_some_and(|glob| matches(glob, relative)) {
+                props.extend(section);
+            }
+        }
+    }
+
+    let mut table = Table::new();
+    match props.get("end_of_line").map(String::as_str) {
+        Some("crlf") => table["crlf"] = value(true),
+        Some("lf") => table["crlf"] = value(false),
+        _ => {}
+    }
+    match props.get("insert_final_newline").map(String::as_str) {
+        Some("true") => table["trailing_newline"] = value(true),
+        Some("false") => table["trailing_newline"] = value(false),
+        _ => {}
+    }
+    if let Some(style @ ("space" | "tab")) = props.get("indent_style").map(String::as_str) {
+        table["indent_style"] = value(style);
+    }
+    if let Some(size) = props.get("indent_size").and_then(|size| size.parse::<i64>().ok()) {
+        table["indent_count"] = value(size);
+    }
+    table
+}
+
+/// Splits an `.editorconfig` into its sections, the glob of each and its
+/// lowercase properties. The properties above the first section, `root`,
+/// have no glob.
+fn sections(text: &str) -> impl Iterator<Item = (Option<&str>, BTreeMap<String, String>)> {
+    let mut sections = vec![(None, BTreeMap::new())];
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with(['#', ';']) {
+            continue;
+        }
+        if let Some(glob) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            sections.push((Some(glob), BTreeMap::new()));
+        } else if let Some((key, val)) = line.split_once('=') {
+            let props = &mut sections.last_mut().unwrap().1;
+            props.insert(key.trim().to_lowercase(), val.trim().to_lowercase());
+        }
+    }
+    sections.into_iter()
+}
+
+/// Matches a section glob against the path of the manifest relative to the
+/// `.editorconfig`, a glob without a `/` matches the file name in any
+/// directory.
+fn matches(glob: &str, relative: &Path) -> bool {
+    let glob = match glob.strip_prefix('/') {
+        Some(anchored) => anchored.to_owned(),
+        None if glob.contains('/') => glob.to_owned(),
+        None => format!("**/{glob}"),
+    };
+    let options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..glob::MatchOptions::new()
+    };
+    expand_braces(&glob).iter().any(|glob| {
+        glob::Pattern::new(glob).is_ok_and(|p| p.matches_path_with(relative, options))
+            // `**/` also matches no directory at all
+            || glob
+                .strip_prefix("**/")
+                .and_then(|glob| glob::Pattern::new(glob).ok())
+                .is_some_and(|p| p.matches_path_with(relative, options))
+    })
+}
+
+/// Expands the `{a,b}` alternatives of a glob, which the `glob` crate does
+/// not know.
+fn expand_braces(glob: &str) -> Vec<String> {
+    let Some((head, rest)) = glob.split_once('{') else {
+        return vec![glob.to_owned()];
+    };
+    let Some((alternatives, tail)) = rest.split_once('}') else {
+        return vec![glob.to_owned()];
+    };
+    alternatives
+        .split(',')
+        .flat_map(|alt| expand_braces(&format!("{head}{alt}{tail}")))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::settings;
+    use crate::test_utils::TempDir;
+
+    #[test]
+    fn editorconfig_settings() {
+        let root = TempDir::new("editorconfig");
+        fs::create_dir_all(root.join("crates/a")).unwrap();
+        fs::write(
+            root.join(".editorconfig"),
+            "root = true\n\n[*]\nend_of_line = crlf\nindent_size = 2\n\n[*.md]\nindent_size = 8\n\n[{Cargo.toml,*.json}]\nindent_style = tab\ninsert_final_newline = false\n",
+        )
+        .unwrap();
+        fs::write(root.join("crates/a/.editorconfig"), "# the crate\n[Cargo.toml]\nend_of_line = LF\n").unwrap();
+
+        let table = settings(&root);
+        assert_eq!(
+            table.to_string(),
+            "crlf = true\ntrailing_newline = false\nindent_style = \"tab\"\nindent_count = 2\n"
+        );
+        let table = settings(&root.join("crates/a"));
+        assert_eq!(
+            table.to_string(),
+            "crlf = false\ntrailing_newline = false\nindent_style = \"tab\"\nindent_count = 2\n"
+        );
+    }
+}
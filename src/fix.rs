@@ -0,0 +1,270 @@
+use std::{fs, path::Path};
+
+use toml_edit::{Array, DocumentMut, Item, Value};
+
+use crate::{
+    CARGO_TOML, Result, discover,
+    fmt::Config,
+    hoist,
+    sort::{self, normalize_path, retain_values},
+};
+
+/// Applies the fixes of `--fix` to the sorted document of the manifest in
+/// `dir`, returns how many problems were fixed. Without a `dir`, reading
+/// stdin, the fixes that look at the file system are skipped.
+pub(crate) fn fix(doc: &mut DocumentMut, dir: Option<&Path>) -> usize {
+    overlapping_excludes(doc)
+        + dir.map_or(0, |dir| {
+            missing_members_removed(doc, dir) + inherited_dependencies(doc, dir) + unused_workspace_dependencies(doc, dir)
+        })
+}
+
+/// Removes the paths of `workspace.exclude` that are also members, cargo keeps
+/// a listed member so they do nothing. An exclude that only covers a member
+/// may still exclude other directories and is kept.
+fn overlapping_excludes(doc: &mut DocumentMut) -> usize {
+    let Some(workspace) = doc.get_mut("workspace").and_then(Item::as_table_mut) else {
+        return 0;
+    };
+    let members = workspace
+        .get("members")
+        .and_then(Item::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|v| Some(normalize_path(v.as_str()?).to_owned()))
+        .collect::<Vec<_>>();
+    let Some(exclude) = workspace.get_mut("exclude").and_then(Item::as_array_mut) else {
+        return 0;
+    };
+    retain_values(exclude, |v| {
+        v.as_str().is_none_or(|path| !members.iter().any(|m| m == normalize_path(path)))
+    })
+}
+
+/// Removes the paths of `workspace.members` without a `Cargo.toml` below
+/// `dir`, globs are kept.
+fn missing_members_removed(doc: &mut DocumentMut, dir: &Path) -> usize {
+    let members = doc
+        .get_mut("workspace")
+        .and_then(Item::as_table_mut)
+        .and_then(|w| w.get_mut("members"))
+        .and_then(Item::as_array_mut);
+    let Some(members) = members else {
+        return 0;
+    };
+    retain_values(members, |v| {
+        v.as_str()
+            .map(normalize_path)
+            .is_none_or(|m| discover::is_glob(m) || dir.join(m).join(CARGO_TOML).is_file())
+    })
+}
+
+/// Rewrites the dependencies of a member in `dir` that its workspace root
+/// already declares with the same source to `{ workspace = true }`.
+fn inherited_dependencies(doc: &mut DocumentMut, dir: &Path) -> usize {
+    if doc.contains_key("workspace") {
+        return 0;
+    }
+    let Ok(dir) = std::path::absolute(dir) else {
+        return 0;
+    };
+    for dir in dir.ancestors().skip(1) {
+        let Ok(raw) = fs::read_to_string(dir.join(CARGO_TOML)) else {
+            continue;
+        };
+        let Ok(root) = raw.parse::<DocumentMut>() else {
+            return 0;
+        };
+        if root.contains_key("workspace") {
+            return hoist::inherit_existing(&root, doc).len();
+        }
+    }
+    0
+}
+
+/// Removes the entries of `[workspace.dependencies]` of the root in `dir`
+/// that no member inherits. An entry a member declares the same way is kept,
+/// `--fix` rewrites the member to inherit it.
+fn unused_workspace_dependencies(doc: &mut DocumentMut, dir: &Path) -> usize {
+    let members = doc
+        .get("workspace")
+        .and_then(Item::as_table)
+        .and_then(|w| hoist::member_manifests(w, dir));
+    let Some(mut members) = members else {
+        return 0;
+    };
+    for (_, member) in &mut members {
+        hoist::inherit_existing(doc, member);
+    }
+    let unused = hoist::unused_dependencies(doc.as_table(), &members);
+    let deps = doc
+        .get_mut("workspace")
+        .and_then(|w| w.get_mut("dependencies"))
+        .and_then(Item::as_table_like_mut);
+    let Some(deps) = deps else {
+        return 0;
+    };
+    for name in &unused {
+        deps.remove(name);
+    }
+    unused.len()
+}
+
+/// Adds the crates below the workspace root `dir` that no path or glob of
+/// `workspace.members` covers to the members, the directories in `exclude`,
+/// ignored by git or holding a workspace of their own are left out. Returns how many members were added.
+pub(crate) fn missing_members(doc: &mut DocumentMut, dir: &Path, config: &Config) -> Result<usize> {
+    let Some(workspace) = doc.get_mut("workspace").and_then(Item::as_table_mut) else {
+        return Ok(0);
+    };
+    let paths = |key| {
+        workspace
+            .get(key)
+            .and_then(Item::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|v| Some(normalize_path(v.as_str()?).to_owned()))
+            .collect::<Vec<_>>()
+    };
+    let (members, excludes) = (paths("members"), paths("exclude"));
+    let options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..glob::MatchOptions::new()
+    };
+    let covered = |path: &str| {
+        members
+            .iter()
+            .any(|m| m == path || glob::Pattern::new(m).is_ok_and(|p| p.matches_with(path, options)))
+            || excludes.iter().any(|e| {
+                path.strip_prefix(e.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+    };
+
+    let mut found = vec![];
+    discover::find_manifests(dir, true, &mut found)?;
+    let mut added = vec![];
+    for crate_dir in found {
+        let Ok(relative) = crate_dir.strip_prefix(dir) else {
+            continue;
+        };
+        let path = relative.to_string_lossy().replace('\\', "/");
+        if path.is_empty() || covered(&path) {
+            continue;
+        }
+        let Ok(manifest) = fs::read_to_string(crate_dir.join(CARGO_TOML))?.parse::<DocumentMut>() else {
+            continue;
+        };
+        if manifest.contains_key("package") && !manifest.contains_key("workspace") {
+            added.push(path);
+        }
+    }
+
+    if added.is_empty() {
+        return Ok(0);
+    }
+    let members = workspace
+        .entry("members")
+        .or_insert_with(|| Item::Value(Value::Array(Array::new())));
+    let Some(members) = members.as_array_mut() else {
+        return Ok(0);
+    };
+    members.extend(added.iter());
+    if !config.is_skipped("workspace.members") {
+        sort::sort_array(members, config.compare);
+    }
+    Ok(added.len())
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use toml_edit::DocumentMut;
+
+    use super::fix;
+    use crate::{fmt::Config, test_utils::TempDir};
+
+    #[test]
+    fn overlapping_excludes() {
+        let mut doc = r#"[workspace]
+members = ["crates/a", "crates/b/"]
+exclude = ["./crates/b", "crates", "vendor"]
+"#
+        .parse::<DocumentMut>()
+        .unwrap();
+        assert_eq!(fix(&mut doc, None), 1);
+        assert_eq!(
+            doc.to_string(),
+            r#"[workspace]
+members = ["crates/a", "crates/b/"]
+exclude = ["crates", "vendor"]
+"#
+        );
+        assert_eq!(fix(&mut "[package]\nname = \"a\"\n".parse::<DocumentMut>().unwrap(), None), 0);
+    }
+
+    #[test]
+    fn missing_members() {
+        let root = TempDir::new("members");
+        let package = "[package]\nname = \"x\"\n";
+        for (dir, manifest) in [
+            ("crates/a", package),
+            ("crates/b", package),
+            ("tools/c", package),
+            ("tools/c/fuzz", "[package]\nname = \"fuzz\"\n\n[workspace]\n"),
+            ("vendor/d", package),
+            ("examples/e", package),
+            ("target/debug/f", package),
+        ] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+            fs::write(root.join(dir).join("Cargo.toml"), manifest).unwrap();
+        }
+        let mut doc = "[workspace]\nmembers = [\"crates/*\"]\nexclude = [\"vendor\"]\n"
+            .parse::<DocumentMut>()
+            .unwrap();
+
+        assert_eq!(super::missing_members(&mut doc, &root, &Config::default()).unwrap(), 2);
+        assert_eq!(
+            doc.to_string(),
+            "[workspace]\nmembers = [\"crates/*\", \"examples/e\", \"tools/c\"]\nexclude = [\"vendor\"]\n"
+        );
+        assert_eq!(super::missing_members(&mut doc, &root, &Config::default()).unwrap(), 0);
+
+        let mut doc = "[workspace]\nmembers = [\"crates/gone\", \"crates/a\", \"tools/*\", \"./tools/c/\"]\n"
+            .parse::<DocumentMut>()
+            .unwrap();
+        assert_eq!(fix(&mut doc, Some(&root)), 1);
+        assert_eq!(
+            doc.to_string(),
+            "[workspace]\nmembers = [\"crates/a\", \"tools/*\", \"./tools/c/\"]\n"
+        );
+    }
+
+    #[test]
+    fn unused_workspace_dependencies() {
+        let root = TempDir::new("unused-deps");
+        fs::create_dir_all(root.join("a")).unwrap();
+        fs::write(
+            root.join("a/Cargo.toml"),
+            "[dependencies]\nlog = { workspace = true }\nrand = \"0.9\"\nserde = \"1.0\"\n",
+        )
+        .unwrap();
+        let mut doc = r#"[workspace]
+members = ["a"]
+
+[workspace.dependencies]
+log = "0.4"
+rand = "0.9"
+serde = "1"
+toml = "0.9"
+"#
+        .parse::<DocumentMut>()
+        .unwrap();
+        assert_eq!(fix(&mut doc, Some(&root)), 2);
+        assert_eq!(
+            doc.to_string(),
+            "[workspace]\nmembers = [\"a\"]\n\n[workspace.dependencies]\nlog = \"0.4\"\nrand = \"0.9\"\n"
+        );
+    }
+}
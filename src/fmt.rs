@@ -1,12 +1,17 @@
-use std::str::FromStr;
+use std::{cmp::Ordering, collections::BTreeMap, str::FromStr};
 
-use toml_edit::{DocumentMut, Item, RawString, Table, Value};
+use toml_edit::{Array, DocumentMut, Formatted, InlineTable, Item, RawString, Table, TableLike, Value};
+
+use crate::sort::for_each_dependency_table;
 
 #[cfg(target_os = "windows")]
 pub(crate) const DEF_CRLF: bool = true;
 #[cfg(not(target_os = "windows"))]
 pub(crate) const DEF_CRLF: bool = false;
 
+/// The UTF-8 byte order mark, `toml_edit` skips it when parsing.
+pub(crate) const BOM: char = '\u{feff}';
+
 pub(crate) const DEF_TABLE_ORDER: &[&str] = &[
     "package",
     "workspace",
@@ -18,6 +23,344 @@ pub(crate) const DEF_TABLE_ORDER: &[&str] = &[
     "dev-dependencies",
 ];
 
+/// The order of keys in a `[profile.*]` table as documented in the cargo book.
+pub(crate) const DEF_PROFILE_KEY_ORDER: &[&str] = &[
+    "inherits",
+    "opt-level",
+    "debug",
+    "split-debuginfo",
+    "strip",
+    "debug-assertions",
+    "overflow-checks",
+    "lto",
+    "panic",
+    "incremental",
+    "codegen-units",
+    "rpath",
+];
+
+/// The conventional order of keys in the `[package]` table.
+pub(crate) const DEF_PACKAGE_KEY_ORDER: &[&str] = &[
+    "name",
+    "version",
+    "edition",
+    "rust-version",
+    "authors",
+    "description",
+    "documentation",
+    "readme",
+    "homepage",
+    "repository",
+    "license",
+    "license-file",
+    "keywords",
+    "categories",
+    "workspace",
+    "build",
+    "links",
+    "exclude",
+    "include",
+    "publish",
+    "default-run",
+    "autolib",
+    "autobins",
+    "autoexamples",
+    "autotests",
+    "autobenches",
+    "resolver",
+];
+
+/// The order of keys in an inline dependency table `dep = { version = "1", .. }`.
+pub(crate) const DEF_DEPENDENCY_KEY_ORDER: &[&str] = &[
+    "workspace",
+    "package",
+    "version",
+    "registry",
+    "path",
+    "git",
+    "branch",
+    "tag",
+    "rev",
+    "features",
+    "default-features",
+    "optional",
+];
+
+/// How the keys of a table are ordered.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum KeyOrder {
+    /// Sort the keys lexically.
+    Alphabetical,
+    /// Keys are written out in the order of the list, any key not in the list
+    /// comes after these sorted lexically.
+    Listed(Vec<String>),
+}
+
+impl KeyOrder {
+    /// Parses `"alphabetical"`, `"canonical"` or an array of keys.
+    fn from_item(item: &Item, canonical: &[&str]) -> Result<Self, &'static str> {
+        match item.as_str() {
+            Some("alphabetical") => Ok(Self::Alphabetical),
+            Some("canonical") => Ok(Self::Listed(canonical.iter().map(|&s| s.to_owned()).collect())),
+            Some(_) => Err(r#"key order must be "alphabetical", "canonical" or an array of keys"#),
+            None => item
+                .as_array()
+                .map(|arr| Self::Listed(arr.iter().filter_map(|v| v.as_str()).map(|s| s.to_owned()).collect()))
+                .ok_or(r#"key order must be "alphabetical", "canonical" or an array of keys"#),
+        }
+    }
+}
+
+/// How an array too long for one line is broken up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum ArrayWrap {
+    /// One value per line.
+    #[default]
+    OnePerLine,
+    /// As many values on each line as fit in `max_array_line_len`.
+    Fill,
+    /// One value per line for every array with more than one value, even the
+    /// short ones, so adding a value is a one line diff.
+    Always,
+}
+
+impl FromStr for ArrayWrap {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "one-per-line" => Ok(Self::OnePerLine),
+            "fill" => Ok(Self::Fill),
+            "always" => Ok(Self::Always),
+            _ => Err(r#"array_wrap must be "one-per-line", "fill" or "always""#),
+        }
+    }
+}
+
+/// How a problem found by `--check` is reported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Severity {
+    /// The problem is not looked for.
+    Off,
+    /// The problem is printed but the check still passes.
+    Warn,
+    /// The problem fails the check.
+    Error,
+}
+
+impl FromStr for Severity {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Self::Off),
+            "warn" => Ok(Self::Warn),
+            "error" => Ok(Self::Error),
+            _ => Err(r#"a severity must be "off", "warn" or "error""#),
+        }
+    }
+}
+
+/// What an indentation is made of.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum IndentStyle {
+    #[default]
+    Space,
+    /// One tab for each level of indentation.
+    Tab,
+}
+
+impl FromStr for IndentStyle {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "space" => Ok(Self::Space),
+            "tab" => Ok(Self::Tab),
+            _ => Err(r#"indent_style must be "space" or "tab""#),
+        }
+    }
+}
+
+/// How the keys of the tables matching a rule are sorted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Strategy {
+    /// Sort the keys lexically.
+    Alphabetical,
+    /// Sort the keys within each blank line separated group.
+    Grouped,
+    /// Leave the keys in the order they are written.
+    Keep,
+    /// Split a dependency table into blank line separated blocks by where the
+    /// dependencies come from, workspace, path, git and registry.
+    BySource,
+    /// Sort an array of tables, `[[bin]]`, by the value of a key of each table.
+    ByKey(String),
+}
+
+impl FromStr for Strategy {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "alphabetical" => Ok(Self::Alphabetical),
+            "grouped" => Ok(Self::Grouped),
+            "keep" => Ok(Self::Keep),
+            "by-source" => Ok(Self::BySource),
+            _ => s
+                .strip_prefix("by-key(")
+                .and_then(|s| s.strip_suffix(')'))
+                .map(|key| Self::ByKey(key.trim().trim_matches('"').to_owned()))
+                .ok_or(r#"a rule must be "alphabetical", "grouped", "keep", "by-source" or "by-key(name)""#),
+        }
+    }
+}
+
+/// How keys and the strings of arrays are compared when sorting.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Comparator {
+    /// Compare runs of digits by their value, `crate2` before `crate10`.
+    pub natural: bool,
+    /// Compare `-` and `_` as the same character like crates.io does for
+    /// names, `foo-bar` and `foo_baz` stay next to each other.
+    pub fold_separators: bool,
+    /// Sort a renamed dependency, `rt = { package = "tokio-util" }`, by its
+    /// `package` instead of the key.
+    pub by_package: bool,
+    /// Keep the crates of a family, `tokio` and `tokio-*`, in the group of
+    /// the first one when sorting groups.
+    pub prefix_affinity: bool,
+    /// Sort in descending order, set by the `reverse` of a rule.
+    pub reverse: bool,
+}
+
+impl Comparator {
+    pub(crate) fn cmp(self, a: &str, b: &str) -> Ordering {
+        let ord = if self.fold_separators {
+            let (a, b) = (a.replace('_', "-"), b.replace('_', "-"));
+            self.cmp_unfolded(&a, &b)
+        } else {
+            Ordering::Equal
+        };
+        let ord = ord.then_with(|| self.cmp_unfolded(a, b));
+        if self.reverse { ord.reverse() } else { ord }
+    }
+
+    fn cmp_unfolded(self, a: &str, b: &str) -> Ordering {
+        if self.natural { natural_cmp(a, b) } else { a.cmp(b) }
+    }
+}
+
+/// Compares the digit and non digit runs of both strings in turn, digits by
+/// their value so `0.5` comes before `0.48`. Equal strings like `a01` and `a1`
+/// fall back to the lexical order.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a_runs, mut b_runs) = (runs(a), runs(b));
+    loop {
+        let ord = match (a_runs.next(), b_runs.next()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.starts_with(|c: char| c.is_ascii_digit()) && y.starts_with(|c: char| c.is_ascii_digit()) => {
+                let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+            }
+            (Some(x), Some(y)) => x.cmp(y),
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+}
+
+/// Splits a string into its runs of ascii digits and of everything else.
+fn runs(s: &str) -> impl Iterator<Item = &str> {
+    let mut rest = s;
+    std::iter::from_fn(move || {
+        let digit = rest.chars().next()?.is_ascii_digit();
+        let end = rest.find(|c: char| c.is_ascii_digit() != digit).unwrap_or(rest.len());
+        let (run, tail) = rest.split_at(end);
+        rest = tail;
+        Some(run)
+    })
+}
+
+/// A rule of the `[rules]` table, `"target.*.dependencies" = "grouped"` or
+/// `patch = { strategy = "alphabetical", reverse = true }`.
+#[derive(Clone, Debug)]
+pub(crate) struct Rule {
+    /// The dotted path of the tables, each segment is a glob.
+    pub pattern: Vec<glob::Pattern>,
+    pub strategy: Strategy,
+    /// Sort in descending order.
+    pub reverse: bool,
+}
+
+impl Rule {
+    fn from_item(pattern: &str, item: &Item) -> Result<Self, &'static str> {
+        let pattern = pattern
+            .split('.')
+            .map(glob::Pattern::new)
+            .collect::<Result<_, _>>()
+            .map_err(|_| "a rule pattern is not a valid glob")?;
+        let (strategy, reverse) = match item.as_table_like() {
+            Some(table) => (
+                table.get("strategy").and_then(Item::as_str).unwrap_or("alphabetical"),
+                table.get("reverse").and_then(Item::as_bool).unwrap_or_default(),
+            ),
+            None => (item.as_str().ok_or("a rule must be a string or a table")?, false),
+        };
+        Ok(Self {
+            pattern,
+            strategy: strategy.parse()?,
+            reverse,
+        })
+    }
+
+    /// Returns true if the dotted path of a table matches the rule.
+    pub(crate) fn matches(&self, path: &[String]) -> bool {
+        self.pattern.len() == path.len() && self.pattern.iter().zip(path).all(|(pat, seg)| pat.matches(seg))
+    }
+}
+
+/// A named group of dependencies of the `[groups]` table,
+/// `async = ["tokio*", "hyper*"]`.
+#[derive(Clone, Debug)]
+pub(crate) struct DependencyGroup {
+    /// Written in the header comment of the group with `group_headers`.
+    pub name: String,
+    /// Globs matched against the name of a dependency.
+    pub patterns: Vec<glob::Pattern>,
+}
+
+impl DependencyGroup {
+    /// Returns true if one of the patterns matches the dependency.
+    pub(crate) fn matches(&self, dependency: &str) -> bool {
+        self.patterns.iter().any(|pat| pat.matches(dependency))
+    }
+}
+
+/// Settings for a single table, `[tables.dependencies]` in the config file,
+/// that take precedence over the global ones.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct TableConfig {
+    /// Sort the keys of the table.
+    ///
+    /// Defaults to `true`.
+    pub sort: bool,
+
+    /// Keep blank line separated groups of keys when sorting.
+    ///
+    /// Defaults to `None`, the `grouped` setting is used.
+    pub grouped: Option<bool>,
+}
+
+impl TableConfig {
+    fn from_item(item: &Item) -> Result<Self, &'static str> {
+        let table = item.as_table_like().ok_or("table overrides must be tables")?;
+        Ok(Self {
+            sort: table.get("sort").and_then(Item::as_bool).unwrap_or(true),
+            grouped: table.get("grouped").and_then(Item::as_bool),
+        })
+    }
+}
+
 /// The config file for formatting toml after sorting.
 ///
 /// Use the `FromStr` to create a config from a string.
@@ -37,12 +380,14 @@ pub(crate) struct Config {
     /// Defaults to `false`.
     pub always_trailing_comma: bool,
 
-    /// Use trailing comma for multi-line arrays.
+    /// Use trailing comma for multi-line arrays, `"always"` or `"never"` in
+    /// the config file work like `true` and `false`. Applied to every array
+    /// that is on more than one line after sorting.
     ///
     /// Defaults to `true`.
     pub multiline_trailing_comma: bool,
 
-    /// Use space around equal sign for table key values.
+    /// Use exactly one space on each side of the equal sign of table key values.
     ///
     /// Defaults to `true`.
     pub space_around_eq: bool,
@@ -57,21 +402,61 @@ pub(crate) struct Config {
     /// Defaults to 80.
     pub max_array_line_len: usize,
 
+    /// How arrays longer than `max_array_line_len`, counting the key, are
+    /// broken up, `"one-per-line"` or `"fill"`. `"always"` puts the values of
+    /// every array on lines of their own.
+    ///
+    /// Defaults to one per line.
+    pub array_wrap: ArrayWrap,
+
     /// Number of spaces to indent for arrays broken up with newlines.
     ///
     /// Defaults to 4.
     pub indent_count: usize,
 
+    /// Indent with `"space"`s or `"tab"`s, a tab stands for the `indent_count`
+    /// of a broken up array and for any `key_indent`.
+    ///
+    /// Defaults to spaces.
+    pub indent_style: IndentStyle,
+
+    /// Number of spaces to indent every key line of a table with, dotted keys
+    /// and the comments above them included. The values of broken up arrays
+    /// are indented by `indent_count` on top of it.
+    ///
+    /// Defaults to `None`, the indentation of the keys is kept.
+    pub key_indent: Option<usize>,
+
     /// Omit whitespace padding inside inline tables.
     ///
     /// Defaults to `false`.
     pub compact_inline_tables: bool,
 
-    /// Add trailing newline to the source.
+    /// Write `'literal'` strings as `"basic"` strings when that needs no
+    /// escapes, the keys are left as they are.
+    ///
+    /// Defaults to `false`.
+    pub normalize_quotes: bool,
+
+    /// Pad the keys of dependency tables so the `=` of each blank line
+    /// separated group line up.
+    ///
+    /// Defaults to `false`.
+    pub align_dependencies: bool,
+
+    /// End the file with exactly one newline, a missing one is added and the
+    /// blank lines after the last line are removed. `--check --check-format`
+    /// reports a file that does not.
     ///
     /// Defaults to `true`.
     pub trailing_newline: bool,
 
+    /// Drop the UTF-8 byte order mark a file starts with instead of writing
+    /// it back.
+    ///
+    /// Defaults to `false`.
+    pub strip_bom: bool,
+
     /// Are newlines allowed between key value pairs in a table.
     ///
     /// This must be true for the `--grouped` flag to be used.
@@ -83,16 +468,298 @@ pub(crate) struct Config {
     /// Defaults to `1`.
     pub allowed_blank_lines: usize,
 
-    /// Use CRLF line endings
+    /// The exact number of blank lines above every table heading, the
+    /// comments right above a heading stay with it.
     ///
-    /// Defaults to `None`, which means use the original file's line endings
-    /// or the system's.
+    /// Defaults to `None`, the blank lines are only limited by `allowed_blank_lines`.
+    pub blank_lines_between_tables: Option<usize>,
+
+    /// Use CRLF line endings, every line of the written file gets the same
+    /// line ending.
+    ///
+    /// Defaults to `None`, which means use the line ending most lines of the
+    /// original file have or the system's.
     pub crlf: Option<bool>,
 
     /// The user specified ordering of tables in a document.
     ///
     /// All unspecified tables will come after these.
     pub table_order: Vec<String>,
+
+    /// Keep the `default` feature first in the `[features]` table, with
+    /// `grouped` first in its group of keys.
+    ///
+    /// Defaults to `false`.
+    pub default_feature_first: bool,
+
+    /// The order of the keys in each `[profile.*]` table.
+    ///
+    /// Defaults to `None`, the keys are left as they are.
+    pub profile_key_order: Option<KeyOrder>,
+
+    /// The order of the keys in the `[package]` table.
+    ///
+    /// Defaults to `None`, the keys are left as they are.
+    pub package_key_order: Option<KeyOrder>,
+
+    /// The order of the keys in inline dependency tables.
+    ///
+    /// Defaults to `None`, the keys are left as they are.
+    pub dependency_key_order: Option<KeyOrder>,
+
+    /// Keep blank line separated groups of keys when sorting, like `--grouped`.
+    ///
+    /// Defaults to `false`.
+    pub grouped: bool,
+
+    /// Merge the blank line separated groups of dependency tables into one
+    /// sorted list, like `--no-group`.
+    ///
+    /// Defaults to `false`.
+    pub ungroup: bool,
+
+    /// Sort the `[features]` table and the features each one enables.
+    ///
+    /// Defaults to `true`.
+    pub sort_features: bool,
+
+    /// Remove the exact duplicates of the sorted string arrays, the workspace
+    /// `members` and `exclude`, the values of `[features]` and the arrays of
+    /// `[matcher] keys`, like `--dedup-arrays`.
+    ///
+    /// Defaults to `false`.
+    pub dedup_arrays: bool,
+
+    /// Write the paths of `workspace.members` and `workspace.exclude` the same
+    /// way, without a `./` prefix or a trailing `/`, and remove the duplicates
+    /// this leaves.
+    ///
+    /// Defaults to `true`.
+    pub normalize_workspace_paths: bool,
+
+    /// Also fail the check if formatting changes the file, like `--check-format`.
+    ///
+    /// Defaults to `false`.
+    pub check_format: bool,
+
+    /// Warn in the check about a crate with the same spec in two of the
+    /// `[dependencies]`, `[dev-dependencies]` and `[build-dependencies]` of
+    /// the same target.
+    ///
+    /// Defaults to `true`, as a warning unless `[severity]` raises it to
+    /// `"error"`.
+    pub lint_duplicate_dependencies: bool,
+
+    /// Warn in the check about a crate written as `foo_bar` in one place and
+    /// as `foo-bar` in another.
+    ///
+    /// Defaults to `true`, as a warning unless `[severity]` raises it to
+    /// `"error"`.
+    pub lint_separator_variants: bool,
+
+    /// Fail the check for a `version = "*"` dependency.
+    ///
+    /// Defaults to `false`.
+    pub lint_wildcard_versions: bool,
+
+    /// Fail the check for a `>=` version requirement without a `<` upper
+    /// bound.
+    ///
+    /// Defaults to `false`.
+    pub lint_unbounded_versions: bool,
+
+    /// Warn in the check about a `dep:foo`, `foo/bar` or `foo` in
+    /// `[features]` without the optional dependency, dependency or feature it
+    /// enables.
+    ///
+    /// Defaults to `true`, as a warning unless `[severity]` raises it to
+    /// `"error"`.
+    pub lint_features: bool,
+
+    /// Fail the check for an optional dependency no value of `[features]`
+    /// enables. It can still be turned on through its implicit feature.
+    ///
+    /// Defaults to `false`.
+    pub lint_unused_optional: bool,
+
+    /// Warn in the check about `keywords` or `categories` crates.io would
+    /// reject, more than 5 or a keyword longer than 20 characters.
+    ///
+    /// Defaults to `true`, as a warning unless `[severity]` raises it to
+    /// `"error"`.
+    pub lint_crates_io: bool,
+
+    /// Warn in the check about a dependency version requirement that is not
+    /// valid semver.
+    ///
+    /// Defaults to `true`, as a warning unless `[severity]` raises it to
+    /// `"error"`.
+    pub lint_version_syntax: bool,
+
+    /// Warn in the check about a path in both `workspace.members` and
+    /// `workspace.exclude`, or a member inside an excluded directory.
+    ///
+    /// Defaults to `true`, as a warning unless `[severity]` raises it to
+    /// `"error"`.
+    pub lint_workspace_overlap: bool,
+
+    /// Warn in the check about a path of `workspace.members` without a
+    /// `Cargo.toml`, `--fix` removes it.
+    ///
+    /// Defaults to `true`, as a warning unless `[severity]` raises it to
+    /// `"error"`.
+    pub lint_missing_members: bool,
+
+    /// Warn in the check about an entry of `[workspace.dependencies]` that no
+    /// member inherits, `--fix` removes it.
+    ///
+    /// Defaults to `true`, as a warning unless `[severity]` raises it to
+    /// `"error"`.
+    pub lint_unused_workspace_dependencies: bool,
+
+    /// Warn in the check of a workspace root about a crate its members
+    /// require in versions cargo can not unify, like `0.8` and `0.9`.
+    ///
+    /// Defaults to `true`, as a warning unless `[severity]` raises it to
+    /// `"error"`.
+    pub lint_version_conflicts: bool,
+
+    /// The `[severity]` of each problem of `--check`, `"off"`, `"warn"` or
+    /// `"error"`, keyed by its rule in the SARIF report: `unsorted`,
+    /// `unformatted` or a lint like `duplicate-dependency`. A warning is
+    /// printed but does not fail the check, a lint with a severity other than
+    /// `"off"` runs even if its `lint_*` option is `false`.
+    ///
+    /// Defaults to `"error"` for `unsorted`, `unformatted` and the lints that
+    /// are turned on with their `lint_*` option, and to `"warn"` for the lints
+    /// that are on without it, so an upgrade does not fail a passing check.
+    pub severity: BTreeMap<String, Severity>,
+
+    /// How keys and array strings are compared, `natural_sort = true` sorts
+    /// `crate2` before `crate10`, `fold_separators = true` sorts `foo_bar`
+    /// like `foo-bar` and `sort_by_package = true` sorts renamed dependencies
+    /// by their `package`.
+    ///
+    /// Defaults to lexical.
+    pub compare: Comparator,
+
+    /// More top level tables to sort like `[dependencies]`, `headings` of the
+    /// `[matcher]` table.
+    ///
+    /// Defaults to empty.
+    pub matcher_headings: Vec<String>,
+
+    /// Dotted paths of more tables or arrays to sort, like
+    /// `package.metadata.deb.depends`, `keys` of the `[matcher]` table.
+    ///
+    /// Defaults to empty.
+    pub matcher_keys: Vec<String>,
+
+    /// How the tables matching a pattern are sorted, this wins over the
+    /// built in sorting. The first matching rule is used.
+    ///
+    /// Defaults to empty.
+    pub rules: Vec<Rule>,
+
+    /// Tables whose keys are not sorted at all, by name like `features` or
+    /// `workspace.dependencies`.
+    ///
+    /// Defaults to empty.
+    pub skip: Vec<String>,
+
+    /// Inline dependencies, `dep = { .. }`, on a line longer than this are
+    /// rewritten into `[dependencies.dep]` tables.
+    ///
+    /// Defaults to `None`, inline dependencies are left inline.
+    pub max_inline_dependency_len: Option<usize>,
+
+    /// Rewrite `[dependencies.dep]` tables that fit on one line, of
+    /// `max_inline_dependency_len` or `max_array_line_len`, into inline dependencies.
+    ///
+    /// Defaults to `false`.
+    pub inline_dependency_tables: bool,
+
+    /// Named groups of dependencies by glob, every dependency table is sorted
+    /// into one blank line separated block per group in the order they are
+    /// listed. The dependencies no group matches come last.
+    ///
+    /// Defaults to empty.
+    pub groups: Vec<DependencyGroup>,
+
+    /// Write a `# -- name --` comment above every block of the `[groups]` and
+    /// of the `by-source` rule, `other` for the dependencies of no group.
+    ///
+    /// Defaults to `false`.
+    pub group_headers: bool,
+
+    /// Crates that come first in every dependency table in the order they are
+    /// listed, the rest are sorted after them.
+    ///
+    /// Defaults to empty.
+    pub priority_crates: Vec<String>,
+
+    /// Tables that always come first, before the ordered tables.
+    ///
+    /// Defaults to empty.
+    pub pin_first: Vec<String>,
+
+    /// Tables that always come last, after everything else.
+    ///
+    /// Defaults to empty.
+    pub pin_last: Vec<String>,
+
+    /// Glob patterns of crates to skip, like `--exclude`.
+    ///
+    /// Defaults to empty.
+    pub exclude: Vec<String>,
+
+    /// Stop at the first crate that is not sorted or can not be checked, like
+    /// `--fail-fast`.
+    ///
+    /// Defaults to `false`.
+    pub fail_fast: bool,
+
+    /// Settings for single tables by their name, `dependencies` or
+    /// `workspace.dependencies`.
+    ///
+    /// Defaults to empty.
+    pub tables: BTreeMap<String, TableConfig>,
+}
+
+impl Config {
+    /// Returns the settings of the table with the dotted name `table`.
+    pub(crate) fn table(&self, table: &str) -> Option<&TableConfig> {
+        self.tables.get(table)
+    }
+
+    /// Returns true if the keys of the table are left as they are, it is in
+    /// `skip` or its `[tables.*]` settings turn sorting off.
+    pub(crate) fn is_skipped(&self, table: &str) -> bool {
+        self.skip.iter().any(|skip| skip == table) || self.table(table).is_some_and(|t| !t.sort)
+    }
+
+    /// The severity of the problems of the rule `rule`, the one of
+    /// `[severity]` or else `default`.
+    pub(crate) fn severity_of(&self, rule: &str, default: Severity) -> Severity {
+        self.severity.get(rule).copied().unwrap_or(default)
+    }
+
+    /// The whitespace in front of every key line, `None` without `key_indent`.
+    fn key_indentation(&self) -> Option<String> {
+        self.key_indent.map(|n| match self.indent_style {
+            IndentStyle::Space => " ".repeat(n),
+            IndentStyle::Tab => "\t".repeat(usize::from(n > 0)),
+        })
+    }
+
+    /// The whitespace in front of the values of a broken up array.
+    fn value_indentation(&self) -> String {
+        let indent = match self.indent_style {
+            IndentStyle::Space => " ".repeat(self.indent_count),
+            IndentStyle::Tab => "\t".to_owned(),
+        };
+        self.key_indentation().unwrap_or_default() + &indent
+    }
 }
 
 impl Default for Config {
@@ -101,15 +768,60 @@ impl Default for Config {
             always_trailing_comma: false,
             multiline_trailing_comma: true,
             max_array_line_len: 80,
+            array_wrap: ArrayWrap::default(),
             indent_count: 4,
+            indent_style: IndentStyle::Space,
+            key_indent: None,
             space_around_eq: true,
             compact_arrays: false,
             compact_inline_tables: false,
+            normalize_quotes: false,
+            align_dependencies: false,
             trailing_newline: true,
+            strip_bom: false,
             key_value_newlines: true,
             allowed_blank_lines: 1,
+            blank_lines_between_tables: None,
             crlf: None,
             table_order: DEF_TABLE_ORDER.iter().map(|&s| s.to_owned()).collect(),
+            default_feature_first: false,
+            profile_key_order: None,
+            package_key_order: None,
+            dependency_key_order: None,
+            grouped: false,
+            ungroup: false,
+            sort_features: true,
+            dedup_arrays: false,
+            normalize_workspace_paths: true,
+            check_format: false,
+            lint_duplicate_dependencies: true,
+            lint_separator_variants: true,
+            lint_wildcard_versions: false,
+            lint_unbounded_versions: false,
+            lint_features: true,
+            lint_unused_optional: false,
+            lint_crates_io: true,
+            lint_version_syntax: true,
+            lint_workspace_overlap: true,
+            lint_missing_members: true,
+            lint_unused_workspace_dependencies: true,
+            lint_version_conflicts: true,
+            severity: BTreeMap::new(),
+            compare: Comparator::default(),
+            matcher_headings: vec![],
+            matcher_keys: vec![],
+            rules: vec![],
+            skip: vec![],
+            max_inline_dependency_len: None,
+            inline_dependency_tables: false,
+            groups: vec![],
+            group_headers: false,
+            priority_crates: vec![],
+            pin_first: vec![],
+            pin_last: vec![],
+            exclude: vec![],
+            fail_fast: false,
+            tables: BTreeMap::new(),
         }
     }
 }
@@ -122,40 +834,193 @@ impl FromStr for Config {
         }
 
         let toml = s.parse::<DocumentMut>().map_err(|_| "failed to parse as toml")?;
+        Self::from_table(toml.as_table())
+    }
+}
+
+impl Config {
+    /// Reads the config from a table, the root of a config file or the
+    /// `[package.metadata.cargo-sort-fix]` table of a manifest.
+    pub(crate) fn from_table(toml: &dyn TableLike) -> Result<Self, &'static str> {
         Ok(Config {
             always_trailing_comma: toml.get("always_trailing_comma").and_then(Item::as_bool).unwrap_or_default(),
-            multiline_trailing_comma: toml.get("multiline_trailing_comma").and_then(Item::as_bool).unwrap_or(true),
+            multiline_trailing_comma: match toml.get("multiline_trailing_comma") {
+                // `"always"` and `"never"` read better next to each other in a config
+                Some(item) => match item.as_str() {
+                    Some("always") => true,
+                    Some("never") => false,
+                    Some(_) => return Err(r#"multiline_trailing_comma must be "always", "never" or a bool"#),
+                    None => item.as_bool().unwrap_or(true),
+                },
+                None => true,
+            },
             max_array_line_len: toml.get("max_array_line_len").and_then(Item::as_integer).unwrap_or(80) as usize,
+            array_wrap: toml
+                .get("array_wrap")
+                .and_then(Item::as_str)
+                .map(str::parse)
+                .transpose()?
+                .unwrap_or_default(),
             indent_count: toml.get("indent_count").and_then(Item::as_integer).unwrap_or(4) as usize,
+            indent_style: toml
+                .get("indent_style")
+                .and_then(Item::as_str)
+                .map(str::parse)
+                .transpose()?
+                .unwrap_or_default(),
+            key_indent: toml.get("key_indent").and_then(Item::as_integer).map(|n| n as usize),
             space_around_eq: toml.get("space_around_eq").and_then(Item::as_bool).unwrap_or(true),
             compact_arrays: toml.get("compact_arrays").and_then(Item::as_bool).unwrap_or_default(),
             compact_inline_tables: toml.get("compact_inline_tables").and_then(Item::as_bool).unwrap_or_default(),
+            normalize_quotes: toml.get("normalize_quotes").and_then(Item::as_bool).unwrap_or_default(),
+            align_dependencies: toml.get("align_dependencies").and_then(Item::as_bool).unwrap_or_default(),
             trailing_newline: toml.get("trailing_newline").and_then(Item::as_bool).unwrap_or(true),
+            strip_bom: toml.get("strip_bom").and_then(Item::as_bool).unwrap_or_default(),
             key_value_newlines: toml.get("key_value_newlines").and_then(Item::as_bool).unwrap_or(true),
             allowed_blank_lines: toml.get("allowed_blank_lines").and_then(Item::as_integer).unwrap_or(1) as usize,
+            blank_lines_between_tables: toml
+                .get("blank_lines_between_tables")
+                .and_then(Item::as_integer)
+                .map(|n| n as usize),
             crlf: toml.get("crlf").and_then(Item::as_bool),
+            // `order` is the short name matching the `--order` flag
             table_order: toml
                 .get("table_order")
+                .or_else(|| toml.get("order"))
                 .and_then(Item::as_array)
                 .map_or(DEF_TABLE_ORDER.iter().map(|&s| s.to_owned()).collect(), |arr| {
                     arr.into_iter().filter_map(|v| v.as_str()).map(|s| s.to_owned()).collect()
                 }),
+            default_feature_first: toml.get("default_feature_first").and_then(Item::as_bool).unwrap_or_default(),
+            profile_key_order: toml
+                .get("profile_key_order")
+                .map(|item| KeyOrder::from_item(item, DEF_PROFILE_KEY_ORDER))
+                .transpose()?,
+            package_key_order: toml
+                .get("package_key_order")
+                .map(|item| KeyOrder::from_item(item, DEF_PACKAGE_KEY_ORDER))
+                .transpose()?,
+            dependency_key_order: toml
+                .get("dependency_key_order")
+                .map(|item| KeyOrder::from_item(item, DEF_DEPENDENCY_KEY_ORDER))
+                .transpose()?,
+            grouped: toml.get("grouped").and_then(Item::as_bool).unwrap_or_default(),
+            ungroup: toml.get("ungroup").and_then(Item::as_bool).unwrap_or_default(),
+            sort_features: toml.get("sort_features").and_then(Item::as_bool).unwrap_or(true),
+            dedup_arrays: toml.get("dedup_arrays").and_then(Item::as_bool).unwrap_or_default(),
+            normalize_workspace_paths: toml.get("normalize_workspace_paths").and_then(Item::as_bool).unwrap_or(true),
+            check_format: toml.get("check_format").and_then(Item::as_bool).unwrap_or_default(),
+            lint_duplicate_dependencies: toml.get("lint_duplicate_dependencies").and_then(Item::as_bool).unwrap_or(true),
+            lint_separator_variants: toml.get("lint_separator_variants").and_then(Item::as_bool).unwrap_or(true),
+            lint_wildcard_versions: toml.get("lint_wildcard_versions").and_then(Item::as_bool).unwrap_or_default(),
+            lint_unbounded_versions: toml.get("lint_unbounded_versions").and_then(Item::as_bool).unwrap_or_default(),
+            lint_features: toml.get("lint_features").and_then(Item::as_bool).unwrap_or(true),
+            lint_unused_optional: toml.get("lint_unused_optional").and_then(Item::as_bool).unwrap_or_default(),
+            lint_crates_io: toml.get("lint_crates_io").and_then(Item::as_bool).unwrap_or(true),
+            lint_version_syntax: toml.get("lint_version_syntax").and_then(Item::as_bool).unwrap_or(true),
+            lint_workspace_overlap: toml.get("lint_workspace_overlap").and_then(Item::as_bool).unwrap_or(true),
+            lint_missing_members: toml.get("lint_missing_members").and_then(Item::as_bool).unwrap_or(true),
+            lint_unused_workspace_dependencies: toml
+                .get("lint_unused_workspace_dependencies")
+                .and_then(Item::as_bool)
+                .unwrap_or(true),
+            lint_version_conflicts: toml.get("lint_version_conflicts").and_then(Item::as_bool).unwrap_or(true),
+            severity: toml.get("severity").and_then(Item::as_table_like).map_or_else(
+                || Ok(BTreeMap::new()),
+                |levels| {
+                    levels
+                        .iter()
+                        .map(|(rule, level)| {
+                            let level = level.as_str().ok_or(r#"a severity must be "off", "warn" or "error""#)?;
+                            Ok((rule.to_owned(), level.parse()?))
+                        })
+                        .collect()
+                },
+            )?,
+            compare: Comparator {
+                natural: toml.get("natural_sort").and_then(Item::as_bool).unwrap_or_default(),
+                fold_separators: toml.get("fold_separators").and_then(Item::as_bool).unwrap_or_default(),
+                by_package: toml.get("sort_by_package").and_then(Item::as_bool).unwrap_or_default(),
+                prefix_affinity: toml.get("prefix_affinity").and_then(Item::as_bool).unwrap_or_default(),
+                reverse: false,
+            },
+            matcher_headings: string_array(toml.get("matcher").and_then(|m| m.get("headings"))),
+            matcher_keys: string_array(toml.get("matcher").and_then(|m| m.get("keys"))),
+            rules: toml.get("rules").and_then(Item::as_table_like).map_or_else(
+                || Ok(vec![]),
+                |rules| rules.iter().map(|(pattern, item)| Rule::from_item(pattern, item)).collect(),
+            )?,
+            skip: string_array(toml.get("skip")),
+            max_inline_dependency_len: toml.get("max_inline_dependency_len").and_then(Item::as_integer).map(|n| n as usize),
+            inline_dependency_tables: toml.get("inline_dependency_tables").and_then(Item::as_bool).unwrap_or_default(),
+            groups: toml.get("groups").and_then(Item::as_table_like).map_or_else(
+                || Ok(vec![]),
+                |groups| {
+                    groups
+                        .iter()
+                        .map(|(name, item)| {
+                            Ok(DependencyGroup {
+                                name: name.to_owned(),
+                                patterns: string_array(Some(item))
+                                    .iter()
+                                    .map(|p| glob::Pattern::new(p))
+                                    .collect::<Result<_, _>>()
+                                    .map_err(|_| "a group pattern is not a valid glob")?,
+                            })
+                        })
+                        .collect()
+                },
+            )?,
+            group_headers: toml.get("group_headers").and_then(Item::as_bool).unwrap_or_default(),
+            priority_crates: string_array(toml.get("priority_crates")),
+            pin_first: string_array(toml.get("pin_first")),
+            pin_last: string_array(toml.get("pin_last")),
+            exclude: string_array(toml.get("exclude")),
+            fail_fast: toml.get("fail_fast").and_then(Item::as_bool).unwrap_or_default(),
+            tables: toml.get("tables").and_then(Item::as_table_like).map_or_else(
+                || Ok(BTreeMap::new()),
+                |tables| {
+                    tables
+                        .iter()
+                        .map(|(name, item)| Ok((name.to_owned(), TableConfig::from_item(item)?)))
+                        .collect()
+                },
+            )?,
         })
     }
 }
 
-fn fmt_value(value: &mut Value, config: &Config) {
+/// The strings of an array setting, empty when it is not set.
+fn string_array(item: Option<&Item>) -> Vec<String> {
+    item.and_then(Item::as_array).map_or_else(Vec::new, |arr| {
+        arr.iter().filter_map(|v| v.as_str()).map(|s| s.to_owned()).collect()
+    })
+}
+
+/// Formats a value, `key_width` is the width of the `key = ` in front of it
+/// which counts towards the length of an array.
+fn fmt_value(value: &mut Value, key_width: usize, config: &Config) {
     // Note: Here `newline_pattern` is useless since the table will use `\n` insternally always.
     let newline_pattern = if config.crlf.unwrap_or(DEF_CRLF) { "\r\n" } else { "\n" };
+    if config.normalize_quotes {
+        double_quote(value);
+    }
     match value {
         Value::Array(arr) => {
-            if arr.to_string().len() > config.max_array_line_len {
+            // Comments only survive when each value has a line of its own
+            let has_comments = arr.iter().any(|v| v.prefix().contains('#') || v.suffix().contains('#'))
+                || arr.trailing().as_str().is_some_and(|t| t.contains('#'));
+            let too_long =
+                key_width + single_line_len(arr) > config.max_array_line_len || (config.array_wrap == ArrayWrap::Always && arr.len() > 1);
+            if too_long && !has_comments && config.array_wrap == ArrayWrap::Fill {
+                fill_array(arr, newline_pattern, config);
+            } else if too_long || has_comments {
                 let old_trailing_comma = arr.trailing_comma();
                 let new_trailing_comma = config.multiline_trailing_comma;
 
                 let trailing = arr.trailing().as_str().unwrap_or_default().to_owned();
 
-                let indent = " ".repeat(config.indent_count);
+                let indent = config.value_indentation();
                 let arr_len = arr.len();
 
                 // Process all elements' prefix and suffix.
@@ -178,6 +1043,9 @@ fn fmt_value(value: &mut Value, config: &Config) {
                             } else {
                                 "".to_owned() + newline_pattern
                             }
+                        } else if !new_trailing_comma && !suffix.ends_with('\n') {
+                            // An array that was on one line has no newline before the `]` yet
+                            suffix.trim_end().to_owned() + newline_pattern
                         } else {
                             suffix.to_owned()
                         }
@@ -231,7 +1099,7 @@ fn fmt_value(value: &mut Value, config: &Config) {
         }
         Value::InlineTable(table) => {
             table.decor_mut().set_prefix(" ");
-            table.fmt();
+            fmt_inline_table(table);
         }
         // Since the above variants have fmt methods we can only ever
         // get here from a headed table (`[header] key = val`)
@@ -248,28 +1116,8 @@ fn fmt_table(table: &mut Table, config: &Config) {
     let newline_pattern = if config.crlf.unwrap_or(DEF_CRLF) { "\r\n" } else { "\n" };
 
     // Checks the header decor for blank lines
-
     let current_decor = table.decor().prefix().and_then(RawString::as_str).unwrap_or("");
-    let mut new_decor = String::with_capacity(current_decor.len());
-
-    let mut num_consecutive_blank_lines = 0;
-
-    for line in current_decor.lines() {
-        if line.starts_with("#") {
-            new_decor.push_str(line);
-            new_decor.push_str(newline_pattern);
-            num_consecutive_blank_lines = 0;
-            continue;
-        }
-
-        num_consecutive_blank_lines += 1;
-
-        if num_consecutive_blank_lines <= config.allowed_blank_lines {
-            new_decor.push_str(line);
-            new_decor.push_str(newline_pattern);
-        }
-    }
-
+    let new_decor = collapse_blank_lines(current_decor, config.allowed_blank_lines);
     table.decor_mut().set_prefix(new_decor);
 
     let keys: Vec<_> = table.iter().map(|(k, _)| k.to_owned()).collect();
@@ -278,6 +1126,11 @@ fn fmt_table(table: &mut Table, config: &Config) {
             .get(&key)
             .is_some_and(|item| item.is_value() && item.as_inline_table().is_none_or(|t| !t.is_dotted()));
 
+        let key_width = config.key_indentation().map_or(0, |indent| indent.len())
+            + table.key(&key).map_or(key.len(), |k| k.display_repr().len())
+            + " = ".len();
+        // Sub tables have a header line of their own instead
+        let is_key_line = table.get(&key).is_some_and(|item| item.as_table().is_none_or(Table::is_dotted));
         let mut dec = table.key_mut(&key).unwrap();
         let dec = dec.leaf_decor_mut();
         let prefix = dec.prefix().and_then(RawString::as_str).unwrap_or("");
@@ -285,9 +1138,9 @@ fn fmt_table(table: &mut Table, config: &Config) {
 
         // Check each item in the table for blank lines
         if config.key_value_newlines {
-            if config.allowed_blank_lines < blank_lines {
-                dec.set_prefix(prefix.replacen(newline_pattern, "", blank_lines - config.allowed_blank_lines));
-            }
+            // Every run is collapsed on its own so the blank line above a
+            // comment, the start of a group, is kept
+            dec.set_prefix(collapse_blank_lines(prefix, config.allowed_blank_lines));
         } else {
             dec.set_prefix(if prefix.contains('#') {
                 prefix.replacen(newline_pattern, "", blank_lines)
@@ -295,11 +1148,15 @@ fn fmt_table(table: &mut Table, config: &Config) {
                 "".to_owned()
             });
         }
+        if let Some(indent) = config.key_indentation().filter(|_| is_key_line) {
+            let prefix = dec.prefix().and_then(RawString::as_str).unwrap_or("");
+            dec.set_prefix(indent_lines(prefix, &indent));
+        }
 
-        // This is weirdly broken, inserts underscores into `[foo.bar]` table
-        // headers. Revisit later.
-        if config.space_around_eq && dec.suffix().and_then(RawString::as_str).is_none_or(str::is_empty) && is_value_for_space {
-            dec.set_suffix(format!("{}{}", dec.suffix().and_then(RawString::as_str).unwrap_or(""), ' '));
+        // Only the decor around a `=` is whitespace, `[foo.bar]` headers and
+        // dotted keys are left alone
+        if config.space_around_eq && is_value_for_space {
+            dec.set_suffix(" ");
         }
 
         match table.get_mut(&key).unwrap() {
@@ -308,7 +1165,13 @@ fn fmt_table(table: &mut Table, config: &Config) {
                 fmt_table(table, config);
             }
             Item::Value(val) => {
-                fmt_value(val, config);
+                fmt_value(val, key_width, config);
+                if let (Some(indent), Value::Array(arr)) = (config.key_indentation(), &mut *val) {
+                    indent_closing_bracket(arr, &indent);
+                }
+                if config.space_around_eq && is_value_for_space {
+                    val.decor_mut().set_prefix(" ");
+                }
             }
             Item::ArrayOfTables(_) => {}
             Item::None => {}
@@ -316,9 +1179,180 @@ fn fmt_table(table: &mut Table, config: &Config) {
     }
 }
 
+/// Indents every comment line of a key prefix and the key itself with
+/// `indent`, blank lines stay empty.
+fn indent_lines(prefix: &str, indent: &str) -> String {
+    let mut lines = prefix.split('\n').collect::<Vec<_>>();
+    let key_line = lines.pop().unwrap_or_default();
+    let mut indented = String::with_capacity(prefix.len());
+    for line in lines {
+        let line = line.trim_start();
+        if !line.trim().is_empty() {
+            indented.push_str(indent);
+            indented.push_str(line);
+        } else if line.ends_with('\r') {
+            indented.push('\r');
+        }
+        indented.push('\n');
+    }
+    // Only whitespace can be in front of the key on its own line
+    debug_assert!(key_line.trim().is_empty());
+    indented.push_str(indent);
+    indented
+}
+
+/// Lines the `]` of a broken up array up with its key.
+fn indent_closing_bracket(arr: &mut Array, indent: &str) {
+    let trailing = arr.trailing().as_str().unwrap_or_default();
+    if let Some(end) = trailing.rfind('\n') {
+        let trailing = format!("{}{indent}", &trailing[..=end]);
+        arr.set_trailing(trailing);
+    } else if trailing.trim().is_empty()
+        && let Some(last) = arr.iter_mut().last()
+        && let Some(end) = last.decor().suffix().and_then(RawString::as_str).and_then(|s| s.rfind('\n'))
+    {
+        let suffix = last.decor().suffix().and_then(RawString::as_str).unwrap_or_default();
+        let suffix = format!("{}{indent}", &suffix[..=end]);
+        last.decor_mut().set_suffix(suffix);
+    }
+}
+
+/// Writes an inline table as `{ key = value, key = value }`, the single line
+/// arrays and inline tables nested in it included.
+fn fmt_inline_table(table: &mut InlineTable) {
+    table.fmt();
+    for (_, value) in table.iter_mut() {
+        match value {
+            Value::Array(arr) if !arr.to_string().contains('\n') => arr.fmt(),
+            Value::InlineTable(nested) => fmt_inline_table(nested),
+            _ => {}
+        }
+    }
+}
+
+/// Pads the keys of every blank line separated group of a table so the `=`
+/// of the group line up, the tables and dotted keys are left alone.
+fn align_keys(table: &mut Table, space_around_eq: bool) {
+    let mut groups: Vec<Vec<(String, usize)>> = vec![];
+    for (key, item) in table.iter() {
+        if item.as_value().is_none_or(|v| v.as_inline_table().is_some_and(|t| t.is_dotted())) {
+            continue;
+        }
+        let Some(key_repr) = table.key(key) else {
+            continue;
+        };
+        let prefix = key_repr.leaf_decor().prefix().and_then(RawString::as_str).unwrap_or_default();
+        let blank_line = prefix.split_inclusive('\n').any(|l| l.trim().is_empty() && l.ends_with('\n'));
+        if blank_line || groups.is_empty() {
+            groups.push(vec![]);
+        }
+        groups.last_mut().unwrap().push((key.to_owned(), key_repr.display_repr().len()));
+    }
+
+    for group in groups {
+        let width = group.iter().map(|(_, len)| *len).max().unwrap_or_default();
+        for (key, len) in group {
+            if let Some(mut k) = table.key_mut(&key) {
+                k.leaf_decor_mut()
+                    .set_suffix(" ".repeat(width - len + usize::from(space_around_eq)));
+            }
+        }
+    }
+}
+
+/// Rewrites the single line `'literal'` strings in a value as `"basic"`
+/// strings, unless they hold a `\` or `"` which would then need escaping.
+fn double_quote(value: &mut Value) {
+    match value {
+        Value::String(s) => {
+            let raw = s.as_repr().and_then(|r| r.as_raw().as_str()).unwrap_or_default();
+            let literal = raw.starts_with('\'') && !raw.starts_with("\'\'\'");
+            if literal && !s.value().contains(['\\', '"']) {
+                let decor = s.decor().clone();
+                *s = Formatted::new(s.value().clone());
+                *s.decor_mut() = decor;
+            }
+        }
+        Value::Array(arr) => arr.iter_mut().for_each(double_quote),
+        Value::InlineTable(table) => table.iter_mut().for_each(|(_, v)| double_quote(v)),
+        _ => {}
+    }
+}
+
+/// The length of the array written out on a single line.
+fn single_line_len(arr: &Array) -> usize {
+    let mut single = arr.clone();
+    for val in single.iter_mut() {
+        val.decor_mut().clear();
+    }
+    single.fmt();
+    single.set_trailing("");
+    single.set_trailing_comma(false);
+    single.to_string().trim().len()
+}
+
+/// Packs the values of an array onto as few indented lines as fit in
+/// `max_array_line_len`.
+fn fill_array(arr: &mut Array, newline_pattern: &str, config: &Config) {
+    let indent = config.value_indentation();
+    let mut line_len = 0;
+    for (i, val) in arr.iter_mut().enumerate() {
+        val.decor_mut().clear();
+        // The value and its comma
+        let len = val.to_string().trim().len() + 1;
+        if i == 0 || line_len + 1 + len > config.max_array_line_len {
+            val.decor_mut().set_prefix(format!("{newline_pattern}{indent}"));
+            line_len = indent.len() + len;
+        } else {
+            val.decor_mut().set_prefix(" ");
+            line_len += 1 + len;
+        }
+        val.decor_mut().set_suffix("");
+    }
+    arr.set_trailing_comma(config.multiline_trailing_comma);
+    arr.set_trailing(newline_pattern);
+}
+
+/// Collapses every run of blank lines in a decor prefix to at most `max`, the
+/// comment lines and the indentation after the last line are kept.
+fn collapse_blank_lines(prefix: &str, max: usize) -> String {
+    let mut collapsed = String::with_capacity(prefix.len());
+    let mut run = 0;
+    let mut rest = prefix;
+    while let Some(end) = rest.find('\n') {
+        let line = &rest[..=end];
+        rest = &rest[end + 1..];
+        if line.trim().is_empty() {
+            run += 1;
+            if run > max {
+                continue;
+            }
+        } else {
+            run = 0;
+        }
+        collapsed.push_str(line);
+    }
+    collapsed.push_str(rest);
+    collapsed
+}
+
+/// Returns `Some(true)` if most lines of the text end with `\r\n`, `None`
+/// when there is no line ending at all.
+pub(crate) fn dominant_crlf(text: &str) -> Option<bool> {
+    let lines = text.matches('\n').count();
+    let crlf = text.matches("\r\n").count();
+    (lines > 0).then_some(crlf * 2 >= lines)
+}
+
+/// Writes every line ending of the text as `\r\n` or as `\n`.
+pub(crate) fn set_line_endings(text: &str, crlf: bool) -> String {
+    let lf = text.replace("\r\n", "\n");
+    if crlf { lf.replace('\n', "\r\n") } else { lf }
+}
+
 /// Formats a toml `DocumentMut` according to `tomlfmt.toml`.
 pub(crate) fn fmt_toml(toml: &mut DocumentMut, config: &Config) {
-    for (_key, item) in toml.as_table_mut().iter_mut() {
+    for (key, item) in toml.as_table_mut().iter_mut() {
         match item {
             Item::ArrayOfTables(table) => {
                 for tab in table.iter_mut() {
@@ -329,18 +1363,77 @@ pub(crate) fn fmt_toml(toml: &mut DocumentMut, config: &Config) {
                 fmt_table(table, config);
             }
             Item::Value(val) => {
-                fmt_value(val, config);
+                fmt_value(val, key.display_repr().len() + " = ".len(), config);
             }
             Item::None => {}
         }
     }
 
-    // TODO:
-    // This is TERRIBLE!! Convert the Document to a string only to check it ends with a
-    // newline
-    if config.trailing_newline && !toml.to_string().ends_with('\n') {
-        toml.decor_mut().set_suffix("\n");
+    if config.align_dependencies {
+        for_each_dependency_table(toml, |_, table| align_keys(table, config.space_around_eq));
     }
+
+    if let Some(count) = config.blank_lines_between_tables {
+        space_tables(toml, count);
+    }
+
+    if config.trailing_newline {
+        // The comments after the last key stay, the blank lines go
+        let trailing = toml.trailing().as_str().unwrap_or_default().trim_end().to_owned();
+        toml.set_trailing(if trailing.is_empty() { trailing } else { trailing + "\n" });
+        if !ends_with_one_newline(&toml.to_string()) {
+            toml.decor_mut().set_suffix("\n");
+        }
+    }
+}
+
+/// Returns true if the text ends with a single line ending, no blank line
+/// after it.
+pub(crate) fn ends_with_one_newline(text: &str) -> bool {
+    text.strip_suffix('\n')
+        .map(|rest| rest.trim_end_matches([' ', '\t', '\r']))
+        .is_some_and(|rest| !rest.is_empty() && !rest.ends_with('\n'))
+}
+
+/// Sets the blank lines above every table heading to `count`, except above
+/// the first heading of a document that starts with it.
+fn space_tables(toml: &mut DocumentMut, count: usize) {
+    fn each_heading(table: &mut Table, f: &mut impl FnMut(&mut Table)) {
+        for (_, item) in table.iter_mut() {
+            match item {
+                Item::Table(table) => {
+                    if !table.is_implicit() && !table.is_dotted() {
+                        f(table);
+                    }
+                    each_heading(table, f);
+                }
+                Item::ArrayOfTables(arr) => {
+                    for table in arr.iter_mut() {
+                        f(table);
+                        each_heading(table, f);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut first = None;
+    let starts_with_heading = toml.iter().all(|(_, item)| !item.is_value());
+    each_heading(toml.as_table_mut(), &mut |table| {
+        if let Some(position) = table.position() {
+            first = Some(first.map_or(position, |first| position.min(first)));
+        }
+    });
+    each_heading(toml.as_table_mut(), &mut |table| {
+        if starts_with_heading && table.position().is_some() && table.position() == first {
+            return;
+        }
+        let prefix = table.decor().prefix().and_then(RawString::as_str).unwrap_or_default();
+        let rest = prefix.lines().skip_while(|l| l.trim().is_empty()).map(|l| format!("{l}\n"));
+        let prefix = "\n".repeat(count) + &rest.collect::<String>();
+        table.decor_mut().set_prefix(prefix);
+    });
 }
 
 trait ValueExt {
@@ -362,7 +1455,7 @@ impl ValueExt for Value {
 mod test {
     use std::fs;
 
-    use super::{Config, DocumentMut, fmt_toml};
+    use super::{Config, DocumentMut, dominant_crlf, ends_with_one_newline, fmt_toml, set_line_endings};
     use crate::test_utils::assert_eq;
 
     #[test]
@@ -396,6 +1489,32 @@ mod test {
         similar_asserts::assert_eq!(expected, toml.to_string());
     }
 
+    #[test]
+    fn trailing_newline() {
+        for (input, expected) in [
+            ("[package]\nname = \"foo\"", "[package]\nname = \"foo\"\n"),
+            ("[package]\nname = \"foo\"\n\n\n", "[package]\nname = \"foo\"\n"),
+            ("[package]\nname = \"foo\"\n\n# end\n\n", "[package]\nname = \"foo\"\n\n# end\n"),
+        ] {
+            let mut toml = input.parse::<DocumentMut>().unwrap();
+            fmt_toml(&mut toml, &Config::default());
+            similar_asserts::assert_eq!(expected, toml.to_string());
+            assert!(ends_with_one_newline(expected));
+        }
+        assert!(!ends_with_one_newline("a = 1\n\n"));
+        assert!(!ends_with_one_newline("a = 1"));
+        assert!(ends_with_one_newline("a = 1\r\n"));
+    }
+
+    #[test]
+    fn line_endings() {
+        assert_eq!(dominant_crlf("a = 1\r\nb = 2\r\nc = 3\n"), Some(true));
+        assert_eq!(dominant_crlf("a = 1\nb = 2\r\nc = 3\n"), Some(false));
+        assert_eq!(dominant_crlf("a = 1"), None);
+        assert_eq!(set_line_endings("a = 1\nb = 2\r\n", true), "a = 1\r\nb = 2\r\n");
+        assert_eq!(set_line_endings("a = 1\nb = 2\r\n", false), "a = 1\nb = 2\n");
+    }
+
     #[test]
     fn array() {
         let input = fs::read_to_string("examp/clippy.toml").unwrap();
@@ -484,4 +1603,223 @@ integration = [
         fmt_toml(&mut toml, &cfg);
         similar_asserts::assert_eq!(expected2, toml.to_string());
     }
+    #[test]
+    fn collapse_blank_lines() {
+        let input = "[dependencies]\na = \"1\"\n\n\n\n# group\n\n\nb = \"1\"\n\n\nc = \"1\"\n";
+        let expected = "[dependencies]\na = \"1\"\n\n# group\n\nb = \"1\"\n\nc = \"1\"\n";
+        let mut toml = input.parse::<DocumentMut>().unwrap();
+        fmt_toml(&mut toml, &Config::default());
+        similar_asserts::assert_eq!(expected, toml.to_string());
+    }
+
+    #[test]
+    fn array_wrap() {
+        let input = r#"[workspace]
+members = ["crates/alpha", "crates/beta", "crates/gamma", "crates/delta"]
+short = ["crates/alpha", "crates/beta", "crates/gamma"]
+"#;
+        let expected = r#"[workspace]
+members = [
+    "crates/alpha", "crates/beta", "crates/gamma",
+    "crates/delta",
+]
+short = ["crates/alpha", "crates/beta", "crates/gamma"]
+"#;
+        let mut toml = input.parse::<DocumentMut>().unwrap();
+        let cfg = Config {
+            max_array_line_len: 60,
+            array_wrap: "fill".parse().unwrap(),
+            ..Config::default()
+        };
+        fmt_toml(&mut toml, &cfg);
+        similar_asserts::assert_eq!(expected, toml.to_string());
+    }
+
+    #[test]
+    fn array_wrap_always() {
+        let input = "[features]\nfull = [\"a\", \"b\"]\none = [\"a\"]\n";
+        let expected = "[features]\nfull = [\n    \"a\",\n    \"b\",\n]\none = [\"a\"]\n";
+        let mut toml = input.parse::<DocumentMut>().unwrap();
+        let cfg = Config {
+            array_wrap: "always".parse().unwrap(),
+            ..Config::default()
+        };
+        fmt_toml(&mut toml, &cfg);
+        similar_asserts::assert_eq!(expected, toml.to_string());
+    }
+
+    #[test]
+    fn trailing_comma_policy() {
+        let input = "[features]\nfull = [\"feature-one\", \"feature-two\", \"feature-three\", \"feature-four\", \"feature-five\"]\n";
+        let expected = "[features]\nfull = [\n    \"feature-one\",\n    \"feature-two\",\n    \"feature-three\",\n    \"feature-four\",\n    \"feature-five\"\n]\n";
+        let mut toml = input.parse::<DocumentMut>().unwrap();
+        let cfg = "multiline_trailing_comma = \"never\"".parse::<Config>().unwrap();
+        fmt_toml(&mut toml, &cfg);
+        similar_asserts::assert_eq!(expected, toml.to_string());
+
+        assert!(
+            "multiline_trailing_comma = \"always\""
+                .parse::<Config>()
+                .unwrap()
+                .multiline_trailing_comma
+        );
+        assert!("multiline_trailing_comma = \"sometimes\"".parse::<Config>().is_err());
+    }
+
+    #[test]
+    fn align_dependencies() {
+        let input = r#"[package]
+name = "foo"
+
+[dependencies]
+anyhow = "1"
+serde_json = "1"
+tokio = { version = "1" }
+
+wasm-bindgen-futures = "0.4"
+"#;
+        let expected = r#"[package]
+name = "foo"
+
+[dependencies]
+anyhow     = "1"
+serde_json = "1"
+tokio      = { version = "1" }
+
+wasm-bindgen-futures = "0.4"
+"#;
+        let mut toml = input.parse::<DocumentMut>().unwrap();
+        let cfg = Config {
+            align_dependencies: true,
+            ..Config::default()
+        };
+        fmt_toml(&mut toml, &cfg);
+        similar_asserts::assert_eq!(expected, toml.to_string());
+    }
+
+    #[test]
+    fn key_indent() {
+        let input = r#"[package]
+name = "foo"
+   version = "1"
+  metadata.docs.rs = true
+      # comment
+    keywords = [
+  "a",
+        "b",
+]
+
+[features]
+default = [
+      "a",
+   # c
+  "b"
+]
+"#;
+        let expected = r#"[package]
+  name = "foo"
+  version = "1"
+  metadata.docs.rs = true
+  # comment
+  keywords = [
+      "a",
+      "b",
+  ]
+
+[features]
+  default = [
+      "a",
+      # c
+      "b",
+  ]
+"#;
+        let mut toml = input.parse::<DocumentMut>().unwrap();
+        let cfg = Config {
+            key_indent: Some(2),
+            max_array_line_len: 16,
+            ..Config::default()
+        };
+        fmt_toml(&mut toml, &cfg);
+        similar_asserts::assert_eq!(expected, toml.to_string());
+    }
+
+    #[test]
+    fn indent_style_tab() {
+        let input = "[package]\nname = \"foo\"\nkeywords = [\"alpha\", \"beta\"]\n";
+        let expected = "[package]\n\tname = \"foo\"\n\tkeywords = [\n\t\t\"alpha\",\n\t\t\"beta\",\n\t]\n";
+        let mut toml = input.parse::<DocumentMut>().unwrap();
+        let cfg = Config {
+            key_indent: Some(4),
+            indent_style: "tab".parse().unwrap(),
+            array_wrap: "always".parse().unwrap(),
+            ..Config::default()
+        };
+        fmt_toml(&mut toml, &cfg);
+        similar_asserts::assert_eq!(expected, toml.to_string());
+    }
+
+    #[test]
+    fn inline_table_spacing() {
+        let input = "[dependencies]\nbar = {version=\"1\",features=[ \"a\",\"b\" ],default-features=false}\nbaz = {   path  =  \"../baz\"   }\nqux = {git=\"url\", rev={x=1}}\n";
+        let expected = "[dependencies]\nbar = { version = \"1\", features = [\"a\", \"b\"], default-features = false }\nbaz = { path = \"../baz\" }\nqux = { git = \"url\", rev = { x = 1 } }\n";
+        let mut toml = input.parse::<DocumentMut>().unwrap();
+        fmt_toml(&mut toml, &Config::default());
+        similar_asserts::assert_eq!(expected, toml.to_string());
+    }
+
+    #[test]
+    fn space_around_eq() {
+        let input =
+            "[package]\nname=\"foo\"\nversion   =    \"0.1.0\"\nkeywords\t=[\"a\"]\n\n[dependencies]\nbar  =  { version = \"1\" }\n";
+        let expected = "[package]\nname = \"foo\"\nversion = \"0.1.0\"\nkeywords = [\"a\"]\n\n[dependencies]\nbar = { version = \"1\" }\n";
+        let mut toml = input.parse::<DocumentMut>().unwrap();
+        fmt_toml(&mut toml, &Config::default());
+        similar_asserts::assert_eq!(expected, toml.to_string());
+    }
+
+    #[test]
+    fn normalize_quotes() {
+        let input = r#"[package]
+name = 'foo'
+keywords = ['a', "b"]
+regex = 'a\d+'
+
+[dependencies]
+bar = { version = '1', features = ['x'] }
+
+[target.'cfg(unix)'.dependencies]
+libc = '0.2'
+"#;
+        let expected = r#"[package]
+name = "foo"
+keywords = ["a", "b"]
+regex = 'a\d+'
+
+[dependencies]
+bar = { version = "1", features = ["x"] }
+
+[target.'cfg(unix)'.dependencies]
+libc = "0.2"
+"#;
+        let mut toml = input.parse::<DocumentMut>().unwrap();
+        let cfg = Config {
+            normalize_quotes: true,
+            ..Config::default()
+        };
+        fmt_toml(&mut toml, &cfg);
+        similar_asserts::assert_eq!(expected, toml.to_string());
+    }
+
+    #[test]
+    fn blank_lines_between_tables() {
+        let input = "[package]\nname = \"foo\"\n[dependencies]\na = \"1\"\n\n\n\n# comment\n\n[features]\n\n\n\n[[bin]]\nname = \"foo\"\n";
+        let expected = "[package]\nname = \"foo\"\n\n[dependencies]\na = \"1\"\n\n# comment\n\n[features]\n\n[[bin]]\nname = \"foo\"\n";
+        let mut toml = input.parse::<DocumentMut>().unwrap();
+        let cfg = Config {
+            blank_lines_between_tables: Some(1),
+            ..Config::default()
+        };
+        fmt_toml(&mut toml, &cfg);
+        similar_asserts::assert_eq!(expected, toml.to_string());
+    }
 }
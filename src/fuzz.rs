@@ -13,19 +13,18 @@ fn main() {
         if let Ok(s) = std::str::from_utf8(data) {
             let s = s.replace("\r", "");
             if s.parse::<DocumentMut>().is_ok() {
-                let mut toml = sort::sort_toml(
-                    &s,
-                    sort::MATCHER,
-                    false,
-                    &[
+                let config = Config {
+                    table_order: vec![
                         "package".to_owned(),
                         "features".to_owned(),
                         "dependencies".to_owned(),
                         "build-dependencies".to_owned(),
                         "dev-dependencies".to_owned(),
                     ],
-                );
-                fmt::fmt_toml(&mut toml, &Config::new());
+                    ..Config::default()
+                };
+                let mut toml = sort::sort_toml(&s, sort::MATCHER, false, &config).unwrap();
+                fmt::fmt_toml(&mut toml, &config);
                 let s = toml.to_string();
                 assert!(s.parse::<DocumentMut>().is_ok())
             }
@@ -0,0 +1,411 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+};
+
+use toml_edit::{DocumentMut, InlineTable, Item, Table, Value, value};
+
+use crate::{
+    CARGO_TOML, discover,
+    sort::{DEPENDENCY_KINDS, for_each_dependency_table, normalize_path},
+};
+
+/// The keys of a dependency spec that pick the crate, they move to
+/// `[workspace.dependencies]`.
+const SOURCE_KEYS: &[&str] = &[
+    "version",
+    "package",
+    "registry",
+    "git",
+    "branch",
+    "tag",
+    "rev",
+    "default-features",
+    "default_features",
+];
+
+/// The keys a member keeps next to `workspace = true`.
+const MEMBER_KEYS: &[&str] = &["features", "optional", "public"];
+
+/// The source keys of a dependency spec with their values.
+type Source = Vec<(String, Value)>;
+
+/// The path of a workspace member as written in `workspace.members`, or the
+/// match of a glob, with its manifest.
+pub(crate) type Member = (String, DocumentMut);
+
+/// The source all members agree on for a name, `None` once two of them
+/// disagree, and the members that use it.
+type Usage = (Option<(String, Source)>, BTreeSet<usize>);
+
+/// Moves the dependencies more than one member declares with the same
+/// source to the `[workspace.dependencies]` of `root` and makes the members
+/// inherit them and the ones the workspace already had, see
+/// [`inherit_existing`]. A name the members declare in different ways is left
+/// alone. Returns the names that were hoisted.
+pub(crate) fn hoist(root: &mut DocumentMut, members: &mut [DocumentMut]) -> Vec<String> {
+    let Some(workspace) = root.get_mut("workspace").and_then(Item::as_table_mut) else {
+        return vec![];
+    };
+    let existing = workspace
+        .get("dependencies")
+        .and_then(Item::as_table_like)
+        .map(|deps| deps.iter().map(|(name, _)| name.to_owned()).collect::<BTreeSet<_>>())
+        .unwrap_or_default();
+
+    let mut found: BTreeMap<String, Usage> = BTreeMap::new();
+    for (idx, member) in members.iter_mut().enumerate() {
+        for_each_member_table(member, |deps| {
+            for (name, item) in deps.iter() {
+                let source = source_of(item);
+                let (agreed, users) = found.entry(name.to_owned()).or_insert_with(|| (source.clone(), BTreeSet::new()));
+                if agreed.as_ref().map(|(key, _)| key) != source.as_ref().map(|(key, _)| key) {
+                    *agreed = None;
+                }
+                users.insert(idx);
+            }
+        });
+    }
+    let hoisted = found
+        .into_iter()
+        .filter(|(name, (_, users))| users.len() > 1 && !existing.contains(name))
+        .filter_map(|(name, (source, _))| Some((name, source?.1)))
+        .collect::<BTreeMap<_, _>>();
+
+    if !hoisted.is_empty() {
+        let deps = workspace
+            .entry("dependencies")
+            .or_insert_with(|| Item::Table(Table::new()))
+            .as_table_like_mut();
+        let Some(deps) = deps else {
+            return vec![];
+        };
+        for (name, source) in &hoisted {
+            deps.insert(name, workspace_spec(source));
+        }
+    }
+    for member in members.iter_mut() {
+        inherit_existing(root, member);
+    }
+    hoisted.into_keys().collect()
+}
+
+/// Rewrites the dependencies of a member that the `[workspace.dependencies]`
+/// of `root` declares with the same source to `{ workspace = true }`, the
+/// `features` and `optional` of the member stay. A workspace entry with
+/// features the member does not enable is left alone, inheriting it would
+/// turn them on. Returns the names that were rewritten.
+pub(crate) fn inherit_existing(root: &DocumentMut, member: &mut DocumentMut) -> Vec<String> {
+    let deps = root
+        .get("workspace")
+        .and_then(|w| w.get("dependencies"))
+        .and_then(Item::as_table_like);
+    let Some(deps) = deps else {
+        return vec![];
+    };
+    let sources = deps
+        .iter()
+        .filter_map(|(name, item)| Some((name, (source_of(item)?.0, features_of(item)))))
+        .collect::<BTreeMap<_, _>>();
+    let mut inherited = vec![];
+    for_each_member_table(member, |table| {
+        for (name, item) in table.iter_mut() {
+            let Some((source, features)) = sources.get(name.get()) else {
+                continue;
+            };
+            if source_of(item).is_some_and(|(s, _)| &s == source) && features.is_subset(&features_of(item)) {
+                inherit(item);
+                inherited.push(name.get().to_owned());
+            }
+        }
+    });
+    inherited
+}
+
+/// Reads the manifests of the `workspace.members` of the root in `dir`, a
+/// glob is expanded and the matches inside `workspace.exclude` are skipped.
+/// A member without a `Cargo.toml` is left out, `None` if one can not be
+/// read or parsed.
+pub(crate) fn member_manifests(workspace: &Table, dir: &Path) -> Option<Vec<Member>> {
+    let paths = |key| {
+        workspace
+            .get(key)
+            .and_then(Item::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|v| Some(normalize_path(v.as_str()?).to_owned()))
+            .collect::<Vec<_>>()
+    };
+    let excludes = paths("exclude");
+    let mut found = vec![];
+    for member in paths("members") {
+        if !discover::is_glob(&member) {
+            found.push(member);
+            continue;
+        }
+        for path in glob::glob(&dir.join(&member).to_string_lossy()).ok()? {
+            let path = path.ok()?;
+            let Ok(relative) = path.strip_prefix(dir) else {
+                continue;
+            };
+            if !excludes.iter().any(|e| relative.starts_with(e)) {
+                found.push(relative.to_string_lossy().replace('\\', "/"));
+            }
+        }
+    }
+
+    let mut manifests = vec![];
+    for member in found {
+        let path = dir.join(&member).join(CARGO_TOML);
+        if path.is_file() {
+            manifests.push((member, fs::read_to_string(path).ok()?.parse().ok()?));
+        }
+    }
+    Some(manifests)
+}
+
+/// The names of the `[workspace.dependencies]` of `root` that neither the
+/// root package nor one of the `members` inherits.
+pub(crate) fn unused_dependencies(root: &Table, members: &[Member]) -> Vec<String> {
+    let deps = root
+        .get("workspace")
+        .and_then(|w| w.get("dependencies"))
+        .and_then(Item::as_table_like);
+    let Some(deps) = deps else {
+        return vec![];
+    };
+    let mut inherited = BTreeSet::new();
+    for manifest in std::iter::once(root).chain(members.iter().map(|(_, doc)| doc.as_table())) {
+        for_each_dependency(manifest, |name, item| {
+            if item.get("workspace").and_then(Item::as_bool) == Some(true) {
+                inherited.insert(name.to_owned());
+            }
+        });
+    }
+    deps.iter()
+        .map(|(name, _)| name)
+        .filter(|name| !inherited.contains(*name))
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Calls `f` with the name and spec of every dependency of a manifest, the
+/// ones of `[target]` included and the `[workspace]` ones left out.
+pub(crate) fn for_each_dependency(manifest: &Table, mut f: impl FnMut(&str, &Item)) {
+    let targets = manifest
+        .get("target")
+        .and_then(Item::as_table_like)
+        .into_iter()
+        .flat_map(|targets| targets.iter().filter_map(|(_, t)| t.as_table()));
+    for table in std::iter::once(manifest).chain(targets) {
+        for kind in DEPENDENCY_KINDS {
+            let deps = table.get(kind).and_then(Item::as_table_like);
+            for (name, item) in deps.into_iter().flat_map(|deps| deps.iter()) {
+                f(name, item);
+            }
+        }
+    }
+}
+
+fn features_of(item: &Item) -> BTreeSet<String> {
+    let features = item.get("features").and_then(Item::as_array);
+    features
+        .into_iter()
+        .flatten()
+        .filter_map(|f| Some(f.as_str()?.to_owned()))
+        .collect()
+}
+
+/// Calls `f` with the dependency tables of a member, the `[workspace]` and
+/// `[patch]` ones are not inherited.
+fn for_each_member_table(member: &mut DocumentMut, mut f: impl FnMut(&mut Table)) {
+    for_each_dependency_table(member, |name, deps| {
+        if name != "patch" && !name.starts_with("workspace") {
+            f(deps);
+        }
+    });
+}
+
+/// The source keys of a spec and a text to compare them by, `None` for a spec
+/// that can not be inherited like a `path` dependency or one that already is.
+fn source_of(item: &Item) -> Option<(String, Source)> {
+    let mut source = if item.is_str() {
+        vec![("version".to_owned(), item.as_value()?.clone())]
+    } else {
+        let mut source = vec![];
+        for (key, v) in item.as_table_like()?.iter() {
+            if SOURCE_KEYS.contains(&key) {
+                source.push((key.to_owned(), v.as_value()?.clone()));
+            } else if !MEMBER_KEYS.contains(&key) {
+                return None;
+            }
+        }
+        source
+    };
+    if !source.iter().any(|(key, _)| key == "version" || key == "git") {
+        return None;
+    }
+    source.sort_by(|(a, _), (b, _)| a.cmp(b));
+    let text = source
+        .iter()
+        .map(|(key, v)| {
+            format!(
+                "{key}={}",
+                v.as_str().map_or_else(|| v.to_string().trim().to_owned(), str::to_owned)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    Some((text, source))
+}
+
+/// The entry of `[workspace.dependencies]`, `"1"` for a plain version.
+fn workspace_spec(source: &Source) -> Item {
+    if let [(key, version)] = source.as_slice()
+        && key == "version"
+    {
+        return value(undecorated(version));
+    }
+    let mut spec = InlineTable::new();
+    for (key, v) in source {
+        spec.insert(key, undecorated(v));
+    }
+    value(spec)
+}
+
+fn undecorated(v: &Value) -> Value {
+    let mut v = v.clone();
+    v.decor_mut().clear();
+    v
+}
+
+/// Rewrites a member spec to `{ workspace = true }` with the keys the member
+/// keeps, a `[dependencies.foo]` table stays a table.
+fn inherit(item: &mut Item) {
+    if let Item::Table(table) = item {
+        for key in SOURCE_KEYS {
+            table.remove(key);
+        }
+        table.insert("workspace", value(true));
+        table.sort_values_by(|a, _, b, _| (a.get() != "workspace").cmp(&(b.get() != "workspace")));
+        return;
+    }
+    let mut spec = InlineTable::new();
+    spec.insert("workspace", true.into());
+    if let Some(table) = item.as_table_like() {
+        for (key, v) in table.iter() {
+            if MEMBER_KEYS.contains(&key)
+                && let Some(v) = v.as_value()
+            {
+                spec.insert(key, v.clone());
+            }
+        }
+    }
+    if let Some(old) = item.as_value() {
+        *spec.decor_mut() = old.decor().clone();
+    }
+    *item = value(spec);
+}
+
+#[cfg(test)]
+mod test {
+    use toml_edit::DocumentMut;
+
+    use super::{hoist, inherit_existing};
+
+    #[test]
+    fn hoist_dependencies() {
+        let mut root = "[workspace]\nmembers = [\"a\", \"b\"]\n\n[workspace.dependencies]\nlog = \"0.4\"\n"
+            .parse::<DocumentMut>()
+            .unwrap();
+        let a = r#"[dependencies]
+serde = { version = "1.0", features = ["derive"] }
+tokio = "1"
+log = "0.4"
+local = { path = "../local" }
+
+[dependencies.anyhow]
+version = "1"
+optional = true
+"#;
+        let b = r#"[dependencies]
+serde = "1.0"
+tokio = "1.2"
+local = { path = "../local" }
+
+[target.'cfg(unix)'.dev-dependencies]
+anyhow = { version = "1", default-features = false }
+
+[build-dependencies]
+cc = { git = "https://github.com/rust-lang/cc-rs", branch = "main" }
+"#;
+        let c = "[dev-dependencies]\ncc = { git = \"https://github.com/rust-lang/cc-rs\", branch = \"main\" }\n";
+        let mut members = [a, b, c].map(|m| m.parse::<DocumentMut>().unwrap());
+
+        assert_eq!(hoist(&mut root, &mut members), vec!["cc", "serde"]);
+        assert_eq!(
+            root.to_string(),
+            r#"[workspace]
+members = ["a", "b"]
+
+[workspace.dependencies]
+log = "0.4"
+cc = { branch = "main", git = "https://github.com/rust-lang/cc-rs" }
+serde = "1.0"
+"#
+        );
+        assert_eq!(
+            members[0].to_string(),
+            r#"[dependencies]
+serde = { workspace = true, features = ["derive"] }
+tokio = "1"
+log = { workspace = true }
+local = { path = "../local" }
+
+[dependencies.anyhow]
+version = "1"
+optional = true
+"#
+        );
+        assert!(members[1].to_string().contains("serde = { workspace = true }\n"));
+        assert!(members[1].to_string().contains("cc = { workspace = true }\n"));
+        assert_eq!(members[2].to_string(), "[dev-dependencies]\ncc = { workspace = true }\n");
+
+        let mut root = "[package]\nname = \"a\"\n".parse::<DocumentMut>().unwrap();
+        assert!(hoist(&mut root, &mut members).is_empty());
+    }
+
+    #[test]
+    fn inherit_existing_dependencies() {
+        let root = r#"[workspace.dependencies]
+serde = { version = "1", features = ["derive"] }
+log = "0.4"
+rand = "0.9"
+"#
+        .parse::<DocumentMut>()
+        .unwrap();
+        let mut member = r#"[dependencies]
+serde = { version = "1", features = ["derive", "rc"], optional = true }
+log = { version = "0.4", default-features = false }
+rand = "0.9"
+
+[dev-dependencies]
+serde = "1"
+"#
+        .parse::<DocumentMut>()
+        .unwrap();
+        assert_eq!(inherit_existing(&root, &mut member), vec!["serde", "rand"]);
+        assert_eq!(
+            member.to_string(),
+            r#"[dependencies]
+serde = { workspace = true, features = ["derive", "rc"], optional = true }
+log = { version = "0.4", default-features = false }
+rand = { workspace = true }
+
+[dev-dependencies]
+serde = "1"
+"#
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    io::{self, IsTerminal},
+    ops::ControlFlow,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+};
+
+use termcolor::{Buffer, BufferWriter, StandardStream, WriteColor};
+
+use crate::{Result, color_choice};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Stream {
+    Stdout,
+    Stderr,
+}
+
+thread_local! {
+    /// The output of the job running on this thread, `None` outside of a job.
+    static CAPTURED: RefCell<Option<Vec<(Stream, Buffer)>>> = const { RefCell::new(None) };
+}
+
+fn buffer_writer(stream: Stream) -> BufferWriter {
+    match stream {
+        Stream::Stdout => BufferWriter::stdout(color_choice(io::stdout().is_terminal())),
+        Stream::Stderr => BufferWriter::stderr(color_choice(io::stderr().is_terminal())),
+    }
+}
+
+/// Writes to stdout or stderr with `f`, inside a job the output is kept until
+/// the jobs before it are printed.
+pub(crate) fn write_to(stream: Stream, f: impl FnOnce(&mut dyn WriteColor) -> io::Result<()>) -> Result<()> {
+    CAPTURED.with_borrow_mut(|captured| {
+        match captured {
+            Some(chunks) => {
+                if chunks.last().is_none_or(|(last, _)| *last != stream) {
+                    chunks.push((stream, buffer_writer(stream).buffer()));
+                }
+                let (_, buffer) = chunks.last_mut().expect("a chunk was just pushed");
+                f(buffer)?;
+            }
+            None if stream == Stream::Stderr => f(&mut StandardStream::stderr(color_choice(io::stderr().is_terminal())))?,
+            None => f(&mut StandardStream::stdout(color_choice(io::stdout().is_terminal())))?,
+        }
+        Ok(())
+    })
+}
+
+/// Runs `f` like a job and returns its result with what it wrote to stdout.
+#[cfg(test)]
+pub(crate) fn capture_stdout<T>(f: impl FnOnce() -> T) -> (T, String) {
+    CAPTURED.set(Some(vec![]));
+    let result = f();
+    let stdout = CAPTURED
+        .take()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(stream, _)| *stream == Stream::Stdout)
+        .map(|(_, buffer)| String::from_utf8_lossy(buffer.as_slice()).into_owned())
+        .collect();
+    (result, stdout)
+}
+
+/// Runs `f` for every item on up to `jobs` threads and hands the results to
+/// `done` in the order of the items, each right after the output of its job.
+/// With one job or one item everything runs on the current thread.
+///
+/// No more jobs are started once `done` breaks, the results of the jobs that
+/// were running already are dropped.
+pub(crate) fn run<'a, I: Sync, T: Send>(
+    jobs: usize,
+    items: &'a [I],
+    f: impl Fn(&I) -> T + Sync,
+    mut done: impl FnMut(&'a I, T) -> Result<ControlFlow<()>>,
+) -> Result<()> {
+    if jobs <= 1 || items.len() <= 1 {
+        for item in items {
+            if done(item, f(item))?.is_break() {
+                break;
+            }
+        }
+        return Ok(());
+    }
+
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.min(items.len()) {
+            let (sender, next, f) = (sender.clone(), &next, &f);
+            scope.spawn(move || {
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(i) else {
+                        break;
+                    };
+                    CAPTURED.set(Some(vec![]));
+                    let result = f(item);
+                    let output = CAPTURED.take().unwrap_or_default();
+                    // The receiver is gone after an error, nothing is printed anymore
+                    if sender.send((i, result, output)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        let mut pending = BTreeMap::new();
+        let mut printed = 0;
+        for (i, result, output) in receiver {
+            pending.insert(i, (result, output));
+            while let Some((result, output)) = pending.remove(&printed) {
+                for (stream, buffer) in output {
+                    buffer_writer(stream).print(&buffer)?;
+                }
+                if done(&items[printed], result)?.is_break() {
+                    next.store(items.len(), Ordering::Relaxed);
+                    return Ok(());
+                }
+                printed += 1;
+            }
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        ops::ControlFlow,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use super::{capture_stdout, run};
+    use crate::{Cli, check_toml, fmt::Config, test_utils::TempDir, timings::Timings};
+
+    #[test]
+    fn in_order() {
+        let items = (0..100).collect::<Vec<u64>>();
+        let mut results = vec![];
+        run(
+            8,
+            &items,
+            |i| {
+                // The later items finish first
+                std::thread::sleep(std::time::Duration::from_micros(100 - i));
+                i * 2
+            },
+            |i, result| {
+                results.push((*i, result));
+                Ok(ControlFlow::Continue(()))
+            },
+        )
+        .unwrap();
+        assert_eq!(results, items.iter().map(|i| (*i, i * 2)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn stops() {
+        let items = (0..1000).collect::<Vec<u64>>();
+        for jobs in [1, 8] {
+            let started = AtomicUsize::new(0);
+            let mut results = vec![];
+            run(
+                jobs,
+                &items,
+                |i| {
+                    started.fetch_add(1, Ordering::Relaxed);
+                    std::thread::sleep(std::time::Duration::from_millis(1));
+                    *i
+                },
+                |_, result| {
+                    results.push(result);
+                    Ok(if result == 10 {
+                        ControlFlow::Break(())
+                    } else {
+                        ControlFlow::Continue(())
+                    })
+                },
+            )
+            .unwrap();
+            assert_eq!(results, (0..=10).collect::<Vec<_>>());
+            assert!(started.into_inner() < items.len());
+        }
+    }
+
+    #[test]
+    fn emit_diff_stdout() {
+        let dir = TempDir::new("emit-diff");
+        std::fs::write(dir.join("Cargo.toml"), "[dependencies]\nb = \"1\"\na = \"1\"\n").unwrap();
+        let path = dir.to_string_lossy();
+        for (emit, expected) in [("diff", "--- "), ("stdout", "[dependencies]\na = ")] {
+            let cli = <Cli as clap::Parser>::parse_from(["cargo-sort-fix", "--emit", emit]);
+            let (status, stdout) =
+                capture_stdout(|| check_toml(&path, &cli, &Config::default(), None, &mut vec![], &mut Timings::default()));
+            status.unwrap();
+            assert!(stdout.starts_with(expected), "--emit {emit}: {stdout}");
+        }
+    }
+}
@@ -0,0 +1,487 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, BufRead, Read, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+};
+
+use serde_json::{Value, json};
+
+use crate::{
+    Cli, Result, check,
+    config::{self, Stamp},
+    fmt::{self, Config, Severity},
+    load_config,
+    report::{Diagnostic, Problem},
+    sort,
+};
+
+/// The error code of JSON-RPC for a method the server does not know.
+const METHOD_NOT_FOUND: i32 = -32601;
+
+/// The longest message body read, the body of a longer one is skipped.
+const MAX_MESSAGE_LEN: usize = 64 << 20;
+
+/// A language server for `--lsp` that formats Cargo.toml files and reports the
+/// problems of `--check` as diagnostics, over stdin and stdout.
+pub(crate) struct Server<'a> {
+    cli: &'a Cli,
+    cwd: PathBuf,
+    cache: &'a Cache,
+    /// The text of the open documents by their URI.
+    documents: BTreeMap<String, String>,
+    shutdown: bool,
+}
+
+/// What a server keeps between requests, the `--daemon` shares it between
+/// its connections.
+#[derive(Default)]
+pub(crate) struct Cache {
+    /// The config of a crate directory and the stamp of the files it was read from.
+    configs: Mutex<BTreeMap<PathBuf, (Stamp, Config)>>,
+    /// The last text of a manifest that was sorted, with its config stamp and
+    /// the sorted text.
+    sorted: Mutex<BTreeMap<PathBuf, (Stamp, String, String)>>,
+}
+
+/// Runs the server until the client sends `exit`, returns false if it did not
+/// ask for a shutdown first.
+pub(crate) fn run(cli: &Cli, cwd: &Path) -> Result<bool> {
+    let cache = Cache::default();
+    let mut server = Server::new(cli, cwd, &cache);
+    serve(&mut server, &mut io::stdin().lock(), &mut io::stdout().lock())
+}
+
+/// Answers the messages of `input` on `output` until `exit` or the end of the input.
+pub(crate) fn serve(server: &mut Server<'_>, input: &mut impl BufRead, output: &mut impl Write) -> Result<bool> {
+    while let Some(body) = read_message(input)? {
+        let Ok(message) = serde_json::from_str::<Value>(&body) else {
+            continue;
+        };
+        for reply in server.handle(&message) {
+            write_message(output, &reply)?;
+        }
+        if message["method"] == "exit" {
+            break;
+        }
+    }
+    Ok(server.shutdown)
+}
+
+pub(crate) fn write_message(output: &mut impl Write, body: &str) -> Result<()> {
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    output.flush()?;
+    Ok(())
+}
+
+/// Reads the body of the next message, `None` once the input is closed. A
+/// message without a valid Content-Length, longer than [`MAX_MESSAGE_LEN`] or
+/// not UTF-8 is skipped.
+pub(crate) fn read_message(input: &mut impl BufRead) -> Result<Option<String>> {
+    loop {
+        let mut len = None;
+        loop {
+            let mut header = String::new();
+            if input.read_line(&mut header)? == 0 {
+                return Ok(None);
+            }
+            let header = header.trim();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':')
+                && name.eq_ignore_ascii_case("content-length")
+            {
+                len = value.trim().parse::<usize>().ok();
+            }
+        }
+        let Some(len) = len else {
+            continue;
+        };
+        if len > MAX_MESSAGE_LEN {
+            io::copy(&mut input.take(len as u64), &mut io::sink())?;
+            continue;
+        }
+        let mut body = vec![0; len];
+        input.read_exact(&mut body)?;
+        if let Ok(body) = String::from_utf8(body) {
+            return Ok(Some(body));
+        }
+    }
+}
+
+impl<'a> Server<'a> {
+    pub(crate) fn new(cli: &'a Cli, cwd: &Path, cache: &'a Cache) -> Self {
+        Self {
+            cli,
+            cwd: cwd.to_path_buf(),
+            cache,
+            documents: BTreeMap::new(),
+            shutdown: false,
+        }
+    }
+
+    /// Handles a request or notification and returns the messages to send back.
+    pub(crate) fn handle(&mut self, message: &Value) -> Vec<String> {
+        let method = message["method"].as_str().unwrap_or_default();
+        let id = message.get("id");
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default().to_owned();
+
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": {"openClose": true, "change": 1},
+                    "documentFormattingProvider": true,
+                },
+                "serverInfo": {"name": "cargo-sort-fix", "version": env!("CARGO_PKG_VERSION")},
+            }),
+            "shutdown" => {
+                self.shutdown = true;
+                Value::Null
+            }
+            "textDocument/formatting" => self.formatting(&uri),
+            // The diagnostics as the reply to a request, for `--client`
+            "cargo-sort-fix/check" => match self.document(&uri) {
+                Some((path, text)) => Value::Array(self.diagnostics(&path, &text)),
+                None => Value::Null,
+            },
+            "textDocument/didOpen" | "textDocument/didChange" => {
+                let text = params["textDocument"]
+                    .get("text")
+                    // Full sync, the last change is the whole text
+                    .or_else(|| params["contentChanges"].as_array()?.last()?.get("text"));
+                if let Some(text) = text.and_then(Value::as_str) {
+                    self.documents.insert(uri.clone(), text.to_owned());
+                }
+                return vec![self.publish_diagnostics(&uri)];
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                return vec![notification(
+                    "textDocument/publishDiagnostics",
+                    json!({"uri": uri, "diagnostics": []}),
+                )];
+            }
+            _ => {
+                // Notifications like `initialized` and `exit` get no reply
+                return id
+                    .map(|id| {
+                        let error = json!({"code": METHOD_NOT_FOUND, "message": format!("unknown method {method}")});
+                        json!({"jsonrpc": "2.0", "id": id, "error": error}).to_string()
+                    })
+                    .into_iter()
+                    .collect();
+            }
+        };
+        id.map(|id| json!({"jsonrpc": "2.0", "id": id, "result": result}).to_string())
+            .into_iter()
+            .collect()
+    }
+
+    /// The edit that replaces the whole document with the sorted one, no edit
+    /// if it is sorted already or does not parse.
+    fn formatting(&self, uri: &str) -> Value {
+        let Some((path, text)) = self.document(uri) else {
+            return Value::Null;
+        };
+        match self.sorted(&path, &text) {
+            Ok(sorted) if sorted != text => json!([{
+                "range": {"start": {"line": 0, "character": 0}, "end": end_of(&text)},
+                "newText": sorted,
+            }]),
+            Ok(_) => json!([]),
+            Err(_) => Value::Null,
+        }
+    }
+
+    fn publish_diagnostics(&self, uri: &str) -> String {
+        let diagnostics = match self.document(uri) {
+            Some((path, text)) => self.diagnostics(&path, &text),
+            None => vec![],
+        };
+        notification("textDocument/publishDiagnostics", json!({"uri": uri, "diagnostics": diagnostics}))
+    }
+
+    /// The problems `--check` reports for the document, a manifest that does
+    /// not parse gets one for the error.
+    fn diagnostics(&self, path: &Path, text: &str) -> Vec<Value> {
+        let file = path.display().to_string();
+        let Ok((_, config)) = self.config(path) else {
+            return vec![];
+        };
+        if sort::is_skipped_file(text) {
+            return vec![];
+        }
+        let sorted = match sort::sort_toml(text, sort::MATCHER, config.grouped, &config) {
+            Ok(sorted) => sorted,
+            Err(e) => {
+                let diagnostic = Diagnostic::new(&file, Problem::Error(e.message.clone()));
+                return vec![lsp_diagnostic(&diagnostic, e.line(text), text)];
+            }
+        };
+
+        let mut found = vec![];
+        let unsorted = config.severity_of("unsorted", Severity::Error);
+        if unsorted != Severity::Off && !self.cli.format_only {
+            let violations = check::find_violations(text, &sorted);
+            found.extend(
+                violations
+                    .into_iter()
+                    .map(|v| Diagnostic::new(&file, Problem::Violation(v)).with_severity(unsorted)),
+            );
+        }
+        let mut lints = check::lint(text, &config);
+        lints.extend(check::lint_paths(text, &config::manifest_dir(path), &config));
+        found.extend(lints.into_iter().map(|lint| Diagnostic::new(&file, Problem::Lint(lint))));
+        found
+            .iter()
+            .map(|d| lsp_diagnostic(d, d.location().map_or(1, |(.., line)| line), text))
+            .collect()
+    }
+
+    /// The text sorted and formatted the way the command line would write it.
+    fn sorted(&self, path: &Path, text: &str) -> Result<String> {
+        let (stamp, config) = self.config(path)?;
+        let mut cache = self.cache.sorted.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some((cached, raw, sorted)) = cache.get(path)
+            && *cached == stamp
+            && raw == text
+        {
+            return Ok(sorted.clone());
+        }
+        let sorted = self.sort(text, &config)?;
+        cache.insert(path.to_path_buf(), (stamp, text.to_owned(), sorted.clone()));
+        Ok(sorted)
+    }
+
+    fn sort(&self, text: &str, config: &Config) -> Result<String> {
+        if sort::is_skipped_file(text) {
+            return Ok(text.to_owned());
+        }
+        let mut doc = if self.cli.format_only {
+            sort::parse(text)?
+        } else {
+            sort::sort_toml(text, sort::MATCHER, config.grouped, config)?
+        };
+        if config.dedup_arrays {
+            sort::dedup_arrays(&mut doc, config);
+        }
+        if !(self.cli.no_format || self.cli.sort_only) {
+            fmt::fmt_toml(&mut doc, config);
+        }
+        let crlf = config.crlf.or_else(|| fmt::dominant_crlf(text)).unwrap_or(fmt::DEF_CRLF);
+        let mut sorted = fmt::set_line_endings(&doc.to_string(), crlf);
+        if text.starts_with(fmt::BOM) && !config.strip_bom && !sorted.starts_with(fmt::BOM) {
+            sorted.insert(0, fmt::BOM);
+        }
+        Ok(sorted)
+    }
+
+    /// The config of the crate of `path`, read again once one of its files changes.
+    fn config(&self, path: &Path) -> Result<(Stamp, Config)> {
+        let dir = config::manifest_dir(path);
+        let stamp = config::stamp(&dir, &self.cwd);
+        let mut configs = self.cache.configs.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some((cached, config)) = configs.get(&dir)
+            && *cached == stamp
+        {
+            return Ok((stamp, config.clone()));
+        }
+        let config = load_config(&dir, &self.cwd, self.cli)?;
+        configs.insert(dir, (stamp.clone(), config.clone()));
+        Ok((stamp, config))
+    }
+
+    /// The path and text of an open document, or of the file if the client
+    /// did not send it.
+    fn document(&self, uri: &str) -> Option<(PathBuf, String)> {
+        let path = path_of(uri)?;
+        let text = match self.documents.get(uri) {
+            Some(text) => text.clone(),
+            None => std::fs::read_to_string(&path).ok()?,
+        };
+        Some((path, text))
+    }
+}
+
+fn notification(method: &str, params: Value) -> String {
+    json!({"jsonrpc": "2.0", "method": method, "params": params}).to_string()
+}
+
+/// A diagnostic of LSP for the 1-based `line`, it covers the whole line.
+fn lsp_diagnostic(diagnostic: &Diagnostic, line: usize, text: &str) -> Value {
+    let line = line.saturating_sub(1);
+    let len = text.lines().nth(line).map_or(0, |l| l.encode_utf16().count());
+    let severity = if diagnostic.severity == Severity::Warn { 2 } else { 1 };
+    json!({
+        "range": {"start": {"line": line, "character": 0}, "end": {"line": line, "character": len}},
+        "severity": severity,
+        "code": diagnostic.rule_id(),
+        "source": "cargo-sort-fix",
+        "message": diagnostic.message(),
+    })
+}
+
+/// The position after the last character of `text`, LSP counts the
+/// characters of a line in UTF-16.
+fn end_of(text: &str) -> Value {
+    let line = text.matches('\n').count();
+    let character = text.rsplit('\n').next().unwrap_or_default().encode_utf16().count();
+    json!({"line": line, "character": character})
+}
+
+/// The `file://` URI of an absolute path, the bytes other than letters,
+/// digits and `/:-._~` escaped.
+pub(crate) fn uri_of(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut uri = if path.starts_with('/') {
+        "file://".to_owned()
+    } else {
+        "file:///".to_owned()
+    };
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~:".contains(&byte) {
+            uri.push(char::from(byte));
+        } else {
+            uri.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    uri
+}
+
+/// The path of a `file://` URI with its percent escapes decoded.
+fn path_of(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let mut bytes = vec![];
+    let mut rest = path.as_bytes();
+    while let [first, tail @ ..] = rest {
+        match (first, tail) {
+            (b'%', [hi, lo, tail @ ..]) if hi.is_ascii_hexdigit() && lo.is_ascii_hexdigit() => {
+                bytes.push(u8::from_str_radix(std::str::from_utf8(&[*hi, *lo]).ok()?, 16).ok()?);
+                rest = tail;
+            }
+            _ => {
+                bytes.push(*first);
+                rest = tail;
+            }
+        }
+    }
+    let path = String::from_utf8(bytes).ok()?;
+    // `file:///C:/x` on Windows
+    let path = match path.as_bytes() {
+        [b'/', _, b':', ..] => &path[1..],
+        _ => &path,
+    };
+    Some(PathBuf::from(path))
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use serde_json::{Value, json};
+
+    use super::{Cache, Server, end_of, path_of, read_message, serve, uri_of};
+    use crate::{Cli, test_utils::TempDir};
+
+    fn framed(body: &[u8]) -> Vec<u8> {
+        [format!("Content-Length: {}\r\n\r\n", body.len()).as_bytes(), body].concat()
+    }
+
+    #[test]
+    fn messages() {
+        let input = [
+            b"Content-Length: many\r\n\r\n".to_vec(),
+            b"Content-Length: 99999999999999999999999\r\n\r\n".to_vec(),
+            b"Content-Type: application/vscode-jsonrpc\r\n\r\n".to_vec(),
+            framed(br#"{"a":1}"#),
+            framed(b"\xff"),
+            framed(b"[]"),
+            b"Content-Length: 4294967296\r\n\r\n{}".to_vec(),
+            framed(b"{}"),
+        ]
+        .concat();
+        let mut input = input.as_slice();
+        assert_eq!(read_message(&mut input).unwrap().as_deref(), Some(r#"{"a":1}"#));
+        assert_eq!(read_message(&mut input).unwrap().as_deref(), Some("[]"));
+        // The body of a message that is too long is skipped to the end
+        assert_eq!(read_message(&mut input).unwrap(), None);
+
+        let cli = <Cli as clap::Parser>::parse_from(["cargo-sort-fix", "--lsp"]);
+        let cache = Cache::default();
+        let mut server = Server::new(&cli, &std::env::temp_dir(), &cache);
+        let input = [
+            framed(br#"{"id":1,"method":"#),
+            framed(b"[1] 2"),
+            framed(br#"{"id":2,"method":"shutdown"}"#),
+            framed(br#"{"method":"exit"}"#),
+        ]
+        .concat();
+        let mut output = vec![];
+        assert!(serve(&mut server, &mut input.as_slice(), &mut output).unwrap());
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Content-Length: 38\r\n\r\n{\"id\":2,\"jsonrpc\":\"2.0\",\"result\":null}"
+        );
+    }
+
+    #[test]
+    fn server() {
+        let dir = TempDir::new("lsp");
+        let cli = <Cli as clap::Parser>::parse_from(["cargo-sort-fix", "--lsp"]);
+        let cache = Cache::default();
+        let mut server = Server::new(&cli, &dir, &cache);
+        let uri = format!("file://{}/Cargo%20copy.toml", dir.display());
+        let message = |json: String| serde_json::from_str::<Value>(&json).unwrap();
+
+        let init = server.handle(&message(
+            r#"{"jsonrpc":"2.0","id":"init","method":"initialize","params":{}}"#.to_owned(),
+        ));
+        assert!(init[0].starts_with(r#"{"id":"init","jsonrpc":"2.0","result":{"capabilities""#));
+        assert!(
+            server
+                .handle(&message(r#"{"jsonrpc":"2.0","method":"initialized"}"#.to_owned()))
+                .is_empty()
+        );
+
+        let text = "[dependencies]\nb = \"1\"\na = \"*\"\n";
+        let open = format!(
+            r#"{{"method":"textDocument/didOpen","params":{{"textDocument":{{"uri":"{uri}","text":{}}}}}}}"#,
+            Value::from(text)
+        );
+        let published = server.handle(&message(open));
+        let published = serde_json::from_str::<Value>(&published[0]).unwrap();
+        let diagnostics = published["params"]["diagnostics"].as_array().unwrap();
+        assert_eq!(
+            diagnostics.iter().map(|d| d["code"].as_str().unwrap()).collect::<Vec<_>>(),
+            vec!["unsorted-key"]
+        );
+
+        let format = format!(r#"{{"id":2,"method":"textDocument/formatting","params":{{"textDocument":{{"uri":"{uri}"}}}}}}"#);
+        assert_eq!(
+            server.handle(&message(format)),
+            vec![
+                r#"{"id":2,"jsonrpc":"2.0","result":[{"newText":"[dependencies]\na = \"*\"\nb = \"1\"\n","range":{"end":{"character":0,"line":3},"start":{"character":0,"line":0}}}]}"#
+            ]
+        );
+        let unknown = server.handle(&message(r#"{"id":3,"method":"textDocument/hover"}"#.to_owned()));
+        assert!(unknown[0].contains(r#""error":{"code":-32601"#));
+        assert_eq!(
+            server.handle(&message(r#"{"id":4,"method":"shutdown"}"#.to_owned())),
+            vec![r#"{"id":4,"jsonrpc":"2.0","result":null}"#]
+        );
+        assert!(server.shutdown);
+    }
+
+    #[test]
+    fn positions() {
+        assert_eq!(end_of("a\nbé😀"), json!({"line": 1, "character": 4}));
+        assert_eq!(path_of("file:///home/a%20b/Cargo.toml"), Some("/home/a b/Cargo.toml".into()));
+        assert_eq!(path_of("file:///C:/x/Cargo.toml"), Some("C:/x/Cargo.toml".into()));
+        assert_eq!(path_of("untitled:1"), None);
+        let path = Path::new("/home/a b/ü%/Cargo.toml");
+        assert_eq!(uri_of(path), "file:///home/a%20b/%C3%BC%25/Cargo.toml");
+        assert_eq!(path_of(&uri_of(path)).as_deref(), Some(path));
+    }
+}
@@ -1,21 +1,68 @@
-use std::{fmt::Display, fs::read_to_string, io::Write, path::PathBuf};
+use std::{
+    collections::BTreeSet,
+    fmt::Display,
+    fs::read_to_string,
+    io::Read,
+    num::NonZeroUsize,
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+    time::Instant,
+};
 
+use baseline::Baseline;
 use clap::{crate_authors, crate_name, crate_version};
-use fmt::Config;
-use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+use fmt::{Config, Severity};
+use jobs::Stream;
+use report::{Diagnostic, Problem};
+use termcolor::{Color, ColorChoice, ColorSpec, WriteColor};
+use timings::Timings;
 use toml_edit::{DocumentMut, Item};
 
+mod baseline;
+mod check;
+mod config;
+mod conflict;
+mod daemon;
+mod diff;
+mod discover;
+mod editorconfig;
+mod fix;
 mod fmt;
+mod hoist;
+mod jobs;
+mod lsp;
+mod merge;
+mod report;
 mod sort;
 #[cfg(test)]
 mod test_utils;
+mod timings;
 
 const CARGO_TOML: &str = "Cargo.toml";
 
+/// The name used for the manifest read with `--stdin`.
+const STDIN: &str = "<stdin>";
+
 const EXTRA_HELP: &str = r#"
 NOTE: formatting is applied after the check for sorting so sorted but unformatted toml will not cause a failure.
+
+EXIT STATUS: 0 if every Cargo.toml is sorted, 1 if one is not sorted or formatted or fails a lint, 2 if one could not be read or parsed.
 "#;
 
+/// The exit code when a manifest is not sorted or not formatted.
+const EXIT_UNSORTED: i32 = 1;
+
+/// The exit code when a manifest could not be read or parsed, or the arguments are wrong.
+const EXIT_ERROR: i32 = 2;
+
+/// Marks a pre-commit hook as ours so `--hook install` may overwrite it.
+const HOOK_MARKER: &str = "written by `cargo sort-fix --hook install`";
+/// Sorts the staged manifests, xargs runs it once without files when there are none.
+const HOOK: &str = "git diff --cached --name-only --diff-filter=ACMR -z -- Cargo.toml '*/Cargo.toml' \\
+    | xargs -0 cargo sort-fix --pre-commit
+";
+
 type Result<T, E = Box<dyn std::error::Error + Send + Sync + 'static>> = std::result::Result<T, E>;
 
 #[macro_export]
@@ -53,11 +100,20 @@ fn cargo_subcommand() -> String {
 
 #[derive(clap::Parser, Debug)]
 #[command(author = crate_authors!(", "), version = version_0!(), about = about_info(), bin_name = cargo_subcommand(), after_help = EXTRA_HELP)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// sets cwd, must contain a Cargo.toml file
     #[arg(value_name = "CWD")]
     pub cwd: Vec<String>,
 
+    /// Path to the Cargo.toml to sort, like cargo; with --workspace the workspace it
+    /// is in
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["cwd", "stdin"])]
+    pub manifest_path: Option<PathBuf>,
+
     /// Returns non-zero exit code if Cargo.toml is unsorted, overrides default behavior
     #[arg(short, long)]
     pub check: bool,
@@ -66,6 +122,14 @@ pub struct Cli {
     #[arg(short, long, conflicts_with = "check")]
     pub print: bool,
 
+    /// Reads a Cargo.toml from stdin and prints it sorted to stdout instead of touching files
+    #[arg(long, conflicts_with_all = ["cwd", "workspace", "recursive", "print"])]
+    pub stdin: bool,
+
+    /// Where the sorted result goes, --print is the same as --emit stdout
+    #[arg(long, value_enum, conflicts_with_all = ["check", "print"])]
+    pub emit: Option<Emit>,
+
     /// Skips formatting after sorting
     #[arg(short = 'n', long)]
     pub no_format: bool,
@@ -74,59 +138,429 @@ pub struct Cli {
     #[arg(long, requires = "check")]
     pub check_format: bool,
 
+    /// Only moves lines, skips formatting and the config options that add,
+    /// remove or rewrite lines
+    #[arg(long, conflicts_with_all = ["check_format", "no_group"])]
+    pub sort_only: bool,
+
+    /// Only formats, the keys and tables stay in the order they are in
+    #[arg(long, conflicts_with_all = ["no_format", "sort_only", "grouped", "no_group", "order", "default_feature_first"])]
+    pub format_only: bool,
+
     /// Checks every crate in a workspace
     #[arg(short, long)]
     pub workspace: bool,
 
+    /// Checks every Cargo.toml found in the given directories and their subdirectories
+    #[arg(short, long)]
+    pub recursive: bool,
+
+    /// Sorts that many manifests at the same time, the number of CPUs by default
+    #[arg(short, long, value_name = "N")]
+    pub jobs: Option<NonZeroUsize>,
+
+    /// Reports the time spent parsing, sorting, formatting, checking and
+    /// writing each manifest
+    #[arg(long)]
+    pub timings: bool,
+
+    /// Only processes the Cargo.toml files that git reports as changed since the
+    /// branch forked off BASE_REF, origin/HEAD by default
+    #[arg(
+        long,
+        value_name = "BASE_REF",
+        num_args = 0..=1,
+        default_missing_value = "origin/HEAD",
+        conflicts_with = "stdin"
+    )]
+    pub changed: Option<String>,
+
+    /// Also checks the directories git ignores when using --recursive
+    #[arg(long, requires = "recursive")]
+    pub no_ignore: bool,
+
+    /// Skips the crates matching the glob when checking multiple crates
+    /// (--exclude 'vendor/*' --exclude '**/fuzz')
+    #[arg(short = 'x', long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+
+    /// Stops at the first crate that is not sorted or can not be checked, every
+    /// crate is checked and reported by default
+    #[arg(long)]
+    pub fail_fast: bool,
+
+    /// Checks every crate even when `fail_fast` is set in the config
+    #[arg(long, conflicts_with = "fail_fast")]
+    pub no_fail_fast: bool,
+
     /// Keep blank lines when sorting groups of key value pairs
     #[arg(short, long)]
     pub grouped: bool,
 
+    /// Merge the blank line separated groups of dependency tables into one
+    /// sorted list
+    #[arg(long, conflicts_with = "grouped")]
+    pub no_group: bool,
+
     /// List the order tables should be written out
     /// (--order package,dependencies,features)
     #[arg(short, long, value_delimiter = ',')]
     pub order: Vec<String>,
+
+    /// Keep the `default` feature first when sorting the features table
+    #[arg(long)]
+    pub default_feature_first: bool,
+
+    /// Removes the duplicate entries of the sorted string arrays, workspace members and
+    /// exclude and the values of features
+    #[arg(long, conflicts_with = "sort_only")]
+    pub dedup_arrays: bool,
+
+    /// Fixes the problems --check reports that have a safe fix, like a workspace
+    /// exclude that repeats a member
+    #[arg(long, conflicts_with_all = ["check", "sort_only"])]
+    pub fix: bool,
+
+    /// Adds the crates below a workspace root that no path or glob of workspace.members
+    /// covers to the members
+    #[arg(long, conflicts_with_all = ["check", "sort_only", "stdin"])]
+    pub fix_members: bool,
+
+    /// For pre-commit hooks: sorts the files given, prints the ones it rewrote and
+    /// fails if there are any so they can be reviewed and staged. Nothing to do
+    /// without files
+    #[arg(long, conflicts_with_all = ["check", "stdin", "print", "emit", "workspace", "recursive"])]
+    pub pre_commit: bool,
+
+    /// Runs a language server over stdin and stdout that formats Cargo.toml files and
+    /// reports the problems of --check as diagnostics
+    #[arg(long, conflicts_with_all = ["check", "stdin", "print", "emit", "workspace", "recursive", "pre_commit"])]
+    pub lsp: bool,
+
+    /// Serves the requests of --lsp and --client on a unix socket, keeping the configs
+    /// and sorted manifests between them
+    #[arg(long, conflicts_with_all = ["check", "stdin", "print", "emit", "workspace", "recursive", "pre_commit", "lsp"])]
+    pub daemon: bool,
+
+    /// Sorts or checks the manifests with the running --daemon instead of in this
+    /// process, the sorting options are the ones the daemon was started with
+    #[arg(
+        long,
+        conflicts_with_all = ["stdin", "print", "emit", "fix", "fix_members", "pre_commit", "lsp", "daemon", "message_format", "baseline"]
+    )]
+    pub client: bool,
+
+    /// The socket of --daemon and --client, in $XDG_RUNTIME_DIR or a directory of the
+    /// temporary directory only the user can access by default
+    #[arg(long, value_name = "PATH")]
+    pub socket: Option<PathBuf>,
+
+    /// `install` writes a git pre-commit hook that runs --pre-commit on the staged
+    /// Cargo.toml files
+    #[arg(long, value_enum, value_name = "ACTION")]
+    pub hook: Option<Hook>,
+
+    /// The output format of --check, `json` prints one JSON object per unsorted key
+    /// and `sarif` a SARIF report for code scanning
+    #[arg(long, value_enum, default_value_t = MessageFormat::Human, requires = "check")]
+    pub message_format: MessageFormat,
+
+    /// Ignores the problems recorded in the baseline file, only new ones fail --check
+    #[arg(long, value_name = "FILE", requires = "check")]
+    pub baseline: Option<PathBuf>,
+
+    /// Records every problem --check finds in the --baseline file instead of failing
+    #[arg(long, requires = "baseline")]
+    pub write_baseline: bool,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum Command {
+    /// Writes a cargo-sort-fix.toml with the default table order to start from
+    Init {
+        /// Overwrites an existing config file
+        #[arg(short, long)]
+        force: bool,
+    },
+    /// Moves the dependencies more than one workspace member declares the same way to
+    /// [workspace.dependencies] and makes the members inherit them
+    Hoist {
+        /// Prints the dependencies that would be hoisted without changing any file
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Compares two manifests table by table after sorting them and prints the
+    /// dependencies and keys that were added, removed or changed
+    Diff {
+        /// The old Cargo.toml or the directory of it
+        old: PathBuf,
+        /// The new Cargo.toml or the directory of it
+        new: PathBuf,
+    },
+    /// A git merge driver for Cargo.toml: sorts all three versions and merges them key by
+    /// key, the merged manifest is written to <OURS>
+    MergeDriver {
+        /// The common ancestor, `%O`
+        base: PathBuf,
+        /// The current version that receives the result, `%A`
+        ours: PathBuf,
+        /// The other branch's version, `%B`
+        theirs: PathBuf,
+        /// The path of the manifest in the repository, `%P`, to find its config by
+        path: Option<PathBuf>,
+    },
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    /// Write the pre-commit hook of the current git repository
+    Install,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emit {
+    /// Write the sorted manifests back to their files
+    Files,
+    /// Print the sorted manifests to stdout
+    Stdout,
+    /// Print a diff of the changes sorting would make
+    Diff,
+}
+
+impl Cli {
+    /// The `--emit` target, `--print` and `--stdin` default to stdout.
+    fn emit(&self) -> Emit {
+        self.emit
+            .unwrap_or(if self.print || self.stdin { Emit::Stdout } else { Emit::Files })
+    }
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageFormat {
+    /// Colored messages and a diff
+    Human,
+    /// One JSON object per line on stdout
+    Json,
+    /// A SARIF 2.1.0 report of every crate on stdout
+    Sarif,
+}
+
+/// Colors are only used when writing to a terminal, `ColorChoice::Auto` takes
+/// care of `NO_COLOR` and `TERM=dumb`.
+fn color_choice(is_terminal: bool) -> ColorChoice {
+    if is_terminal { ColorChoice::Auto } else { ColorChoice::Never }
+}
+
+/// Writes `msg` after the `highlight` in `color`.
+fn write_highlighted<S: Display>(stream: Stream, color: Color, highlight: &str, msg: S) -> Result<()> {
+    jobs::write_to(stream, |w| {
+        w.set_color(ColorSpec::new().set_fg(Some(color)))?;
+        write!(w, "{highlight}")?;
+        w.reset()?;
+        writeln!(w, "{msg}")
+    })
 }
 
 fn write_red<S: Display>(highlight: &str, msg: S) -> Result<()> {
-    let mut stderr = StandardStream::stderr(ColorChoice::Auto);
-    stderr.set_color(ColorSpec::new().set_fg(Some(Color::Red)))?;
-    write!(stderr, "{highlight}")?;
-    stderr.reset()?;
-    writeln!(stderr, "{msg}").map_err(Into::into)
+    write_highlighted(Stream::Stderr, Color::Red, highlight, msg)
+}
+
+fn write_yellow<S: Display>(highlight: &str, msg: S) -> Result<()> {
+    write_highlighted(Stream::Stderr, Color::Yellow, highlight, msg)
+}
+
+/// Writes a problem of `--check` as an error or, with `"warn"`, a warning.
+fn write_problem<S: Display>(severity: Severity, msg: S) -> Result<()> {
+    match severity {
+        Severity::Warn => write_yellow("warning: ", msg),
+        _ => write_red("error: ", msg),
+    }
+}
+
+/// Writes a problem of `--check` with the line of the manifest it is on.
+fn write_snippet(severity: Severity, msg: &str, file: &str, source: (&str, usize), note: Option<&str>) -> Result<()> {
+    jobs::write_to(Stream::Stderr, |stderr| {
+        report::write_snippet(stderr, severity, msg, file, source, note)
+    })
 }
 
 fn write_green<S: Display>(highlight: &str, msg: S) -> Result<()> {
-    let mut stdout = StandardStream::stdout(ColorChoice::Auto);
-    stdout.set_color(ColorSpec::new().set_fg(Some(Color::Green)))?;
-    write!(stdout, "{highlight}")?;
-    stdout.reset()?;
-    writeln!(stdout, "{msg}").map_err(Into::into)
+    write_highlighted(Stream::Stdout, Color::Green, highlight, msg)
+}
+
+/// Writes a unified diff to stdout, removed lines are red and added lines green.
+fn write_diff(diff: &str) -> Result<()> {
+    jobs::write_to(Stream::Stdout, |stdout| write_diff_to(stdout, diff))
+}
+
+fn write_diff_to(stdout: &mut dyn WriteColor, diff: &str) -> std::io::Result<()> {
+    for line in diff.lines() {
+        let mut spec = ColorSpec::new();
+        if line.starts_with("---") || line.starts_with("+++") {
+            spec.set_bold(true);
+        } else if line.starts_with("@@") {
+            spec.set_fg(Some(Color::Cyan));
+        } else if line.starts_with('-') {
+            spec.set_fg(Some(Color::Red));
+        } else if line.starts_with('+') {
+            spec.set_fg(Some(Color::Green));
+        }
+        stdout.set_color(&spec)?;
+        write!(stdout, "{line}")?;
+        stdout.reset()?;
+        writeln!(stdout)?;
+    }
+    Ok(())
 }
 
-fn check_toml(path: &str, cli: &Cli, config: &Config) -> Result<bool> {
+/// What became of a manifest, the summary of a run lists it for each.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Status {
+    /// Written back sorted, or with --print and --emit diff not sorted yet.
+    Sorted,
+    /// Sorted already, with --check no problem that fails it.
+    Clean,
+    /// Marked to be skipped.
+    Skipped,
+    /// With --check, not sorted or formatted.
+    Unsorted,
+    /// With --check, sorted and formatted but a lint fails it.
+    Linted,
+    /// Could not be read, parsed or written.
+    Error,
+}
+
+impl Status {
+    const ALL: [Self; 6] = [Self::Sorted, Self::Clean, Self::Skipped, Self::Unsorted, Self::Linted, Self::Error];
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Sorted => "sorted",
+            Self::Clean => "already clean",
+            Self::Skipped => "skipped",
+            Self::Unsorted => "not sorted",
+            Self::Linted => "failing lints",
+            Self::Error => "error",
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            Self::Sorted | Self::Clean => Color::Green,
+            Self::Skipped => Color::Yellow,
+            Self::Unsorted | Self::Linted | Self::Error => Color::Red,
+        }
+    }
+}
+
+/// Lists every manifest of a run with its [`Status`], then how many there are of each.
+fn write_summary(statuses: &[(&str, Status)]) -> Result<()> {
+    let width = Status::ALL.iter().map(|status| status.label().len()).max().unwrap_or_default();
+    jobs::write_to(Stream::Stdout, |stdout| {
+        for (path, status) in statuses {
+            stdout.set_color(ColorSpec::new().set_fg(Some(status.color())))?;
+            write!(stdout, "{:>width$}", status.label())?;
+            stdout.reset()?;
+            writeln!(stdout, " {path}")?;
+        }
+        Ok(())
+    })?;
+    let counts = Status::ALL
+        .iter()
+        .map(|status| (status, statuses.iter().filter(|(_, s)| s == status).count()))
+        .filter(|(_, count)| *count > 0)
+        .map(|(status, count)| format!("{count} {}", status.label()))
+        .collect::<Vec<_>>();
+    write_green("Summary: ", counts.join(", "))
+}
+
+/// Sorts and checks one manifest, with a machine readable `--message-format`
+/// the problems found are pushed to `diagnostics` instead of being printed.
+fn check_toml(
+    path: &str,
+    cli: &Cli,
+    config: &Config,
+    baseline: Option<(&Baseline, &Path)>,
+    diagnostics: &mut Vec<Diagnostic>,
+    timings: &mut Timings,
+) -> Result<Status> {
     let mut path = PathBuf::from(path);
-    if path.is_dir() {
+    if !cli.stdin && path.is_dir() {
         path.push(CARGO_TOML);
     }
+    if !cli.stdin && !path.exists() {
+        return Err(format!("No file found at: {}", path.display()).into());
+    }
+
+    let krate = if cli.stdin {
+        STDIN.to_owned()
+    } else {
+        let krate = path.components().nth_back(1).ok_or("No crate folder found")?.as_os_str();
+        krate.to_string_lossy().into_owned()
+    };
 
-    let krate = path.components().nth_back(1).ok_or("No crate folder found")?.as_os_str();
+    // Nothing but the sorted manifest or its diff may be written to stdout when printing them
+    let to_stdout = !cli.check && cli.emit() == Emit::Stdout;
+    let quiet = !cli.check && cli.emit() != Emit::Files;
+    let human = cli.message_format == MessageFormat::Human;
+    if human && !quiet && !cli.pre_commit {
+        write_green("Checking ", format!("{krate}..."))?;
+    }
 
-    write_green("Checking ", format!("{}...", krate.to_string_lossy()))?;
+    let mut clock = Instant::now();
+    let original = if cli.stdin {
+        let mut original = String::new();
+        std::io::stdin().read_to_string(&mut original)?;
+        original
+    } else {
+        read_to_string(&path).map_err(|_| format!("No file found at: {}", path.display()))?
+    };
+    // Merge conflicts that are all inside dependency tables are resolved, anything else fails to parse below
+    let resolved = conflict::resolve(&original).map_err(|e| format!("Failed to resolve the merge conflicts in {}: {e}", path.display()))?;
+    let toml_raw = resolved.clone().unwrap_or_else(|| original.clone());
 
-    let toml_raw = read_to_string(&path).map_err(|_| format!("No file found at: {}", path.display()))?;
+    if sort::is_skipped_file(&toml_raw) {
+        if to_stdout {
+            jobs::write_to(Stream::Stdout, |stdout| write!(stdout, "{toml_raw}"))?;
+        } else if human && !quiet {
+            write_green("Skipped: ", format!("{CARGO_TOML} for {krate} is marked to be skipped"))?;
+        }
+        timings.parse += timings::lap(&mut clock);
+        return Ok(Status::Skipped);
+    }
 
-    let crlf = toml_raw.contains("\r\n");
+    // Report a broken manifest as an error rather than as unsorted
+    let doc = sort::parse(&toml_raw).map_err(|e| e.with_path(&path))?;
+    timings.parse += timings::lap(&mut clock);
 
     let mut config = config.clone();
-    if config.crlf.is_none() {
-        config.crlf = Some(crlf);
-    }
+    config.crlf = config.crlf.or_else(|| fmt::dominant_crlf(&toml_raw));
 
-    let mut sorted_doc = sort::sort_toml(&toml_raw, sort::MATCHER, cli.grouped, &config.table_order);
+    let mut sorted_doc = if cli.format_only {
+        doc
+    } else {
+        sort::sort_manifest(&path, &toml_raw, sort::MATCHER, config.grouped, &config)?
+    };
+    let deduplicated = if config.dedup_arrays {
+        sort::dedup_arrays(&mut sorted_doc, &config)
+    } else {
+        0
+    };
+    // The directory of the manifest, for the fixes and lints that look at the files next to it
+    let dir = (!cli.stdin).then(|| {
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+        dir.unwrap_or(Path::new(".")).to_path_buf()
+    });
+    let fixed = if cli.fix { fix::fix(&mut sorted_doc, dir.as_deref()) } else { 0 };
+    let added = match &dir {
+        Some(dir) if cli.fix_members => fix::missing_members(&mut sorted_doc, dir, &config)?,
+        _ => 0,
+    };
+    timings.sort += timings::lap(&mut clock);
 
     // if no-format is not found apply formatting
-    let (origin_already_formatted, mut final_str) = if !cli.no_format || cli.check_format {
+    let check_format = config.check_format || config.severity.get("unformatted").is_some_and(|s| *s != Severity::Off);
+    let (origin_already_formatted, mut final_str) = if !(cli.no_format || cli.sort_only) || check_format {
         let before_fmt = sorted_doc.to_string();
         fmt::fmt_toml(&mut sorted_doc, &config);
         let final_str = sorted_doc.to_string();
@@ -135,41 +569,163 @@ fn check_toml(path: &str, cli: &Cli, config: &Config) -> Result<bool> {
         (true, sorted_doc.to_string())
     };
 
-    if config.crlf.unwrap_or(fmt::DEF_CRLF) && !final_str.contains("\r\n") {
-        final_str = final_str.replace('\n', "\r\n");
+    // The line endings of a mixed file are only evened out when formatting
+    let mixed = toml_raw.contains("\r\n") && toml_raw.replace("\r\n", "").contains('\n');
+    if !(cli.sort_only && mixed) {
+        final_str = fmt::set_line_endings(&final_str, config.crlf.unwrap_or(fmt::DEF_CRLF));
     }
 
-    if cli.print {
-        print!("{final_str}");
-        return Ok(true);
+    if toml_raw.starts_with(fmt::BOM) && !config.strip_bom && !final_str.starts_with(fmt::BOM) {
+        final_str.insert(0, fmt::BOM);
+    }
+    timings.format += timings::lap(&mut clock);
+
+    let unchanged = original == final_str;
+    let changed = if unchanged { Status::Clean } else { Status::Sorted };
+    if to_stdout {
+        jobs::write_to(Stream::Stdout, |stdout| write!(stdout, "{final_str}"))?;
+        timings.write += timings::lap(&mut clock);
+        return Ok(changed);
     }
 
-    let origin_already_sorted = toml_raw == final_str;
+    let mut lints = vec![];
     if cli.check {
-        if !origin_already_sorted {
-            write_red("error: ", format!("Dependencies for {} are not sorted", krate.to_string_lossy()))?;
+        lints.extend(check::lint(&toml_raw, &config));
+        if let Some(dir) = &dir {
+            lints.extend(check::lint_paths(&toml_raw, dir, &config));
         }
+        lints.sort_by_key(|lint| lint.line);
+        timings.check += timings::lap(&mut clock);
+    }
+    let missing_newline = check_format && config.trailing_newline && !fmt::ends_with_one_newline(&toml_raw);
+    // Without sorting every change is one of formatting
+    let origin_already_sorted = cli.format_only || unchanged;
+    // Problems that are `"off"` are not reported, only errors fail the check
+    let severity = |rule, found: bool| {
+        if found {
+            config.severity_of(rule, Severity::Error)
+        } else {
+            Severity::Off
+        }
+    };
+    let unsorted = severity("unsorted", !origin_already_sorted);
+    let unformatted = severity("unformatted", !origin_already_formatted);
+    let newline = severity("unformatted", missing_newline);
+    if cli.check {
+        let file = path.display().to_string();
+        let mut found = vec![];
+        if unsorted != Severity::Off {
+            let violations = check::find_violations(&toml_raw, &sorted_doc);
+            timings.check += timings::lap(&mut clock);
+            // Only comments or whitespace moved, nothing to point at
+            if violations.is_empty() {
+                found.push(Diagnostic::new(&file, Problem::Unsorted).with_severity(unsorted));
+            }
+            found.extend(
+                violations
+                    .into_iter()
+                    .map(|v| Diagnostic::new(&file, Problem::Violation(v)).with_severity(unsorted)),
+            );
+        }
+        if unformatted != Severity::Off {
+            found.push(Diagnostic::new(&file, Problem::Unformatted).with_severity(unformatted));
+        }
+        if newline != Severity::Off {
+            found.push(Diagnostic::new(&file, Problem::TrailingNewline).with_severity(newline));
+        }
+        found.extend(lints.into_iter().map(|lint| Diagnostic::new(&file, Problem::Lint(lint))));
+
+        if cli.write_baseline {
+            diagnostics.extend(found);
+            return Ok(Status::Clean);
+        }
+        if let Some((baseline, cwd)) = baseline {
+            found.retain(|diagnostic| !baseline.contains(diagnostic, cwd));
+        }
+        // Problems that are `"off"` are not reported, only errors fail the check
+        let mut failing = found.iter().filter(|diagnostic| diagnostic.severity == Severity::Error).peekable();
+        let status = if failing.peek().is_none() {
+            Status::Clean
+        } else if failing.all(|diagnostic| matches!(diagnostic.problem, Problem::Lint(_))) {
+            Status::Linted
+        } else {
+            Status::Unsorted
+        };
 
-        if !origin_already_formatted {
-            write_red("error: ", format!("{CARGO_TOML} for {} is not formatted", krate.to_string_lossy()))?;
+        let sorting = found
+            .iter()
+            .any(|diagnostic| matches!(diagnostic.problem, Problem::Violation(_) | Problem::Unsorted));
+        if !human {
+            // The file is unsorted only because of its formatting
+            let formatting = found.iter().any(|diagnostic| diagnostic.problem == Problem::Unformatted);
+            found.retain(|diagnostic| !(diagnostic.problem == Problem::Unsorted && formatting));
+            diagnostics.extend(found);
+            return Ok(status);
         }
 
-        return Ok(origin_already_sorted && origin_already_formatted);
+        if sorting {
+            write_problem(unsorted, format!("Dependencies for {krate} are not sorted"))?;
+            for diagnostic in &found {
+                if let Problem::Violation(v) = &diagnostic.problem {
+                    let source = (toml_raw.as_str(), v.line);
+                    write_snippet(diagnostic.severity, &v.message(), &file, source, Some(&v.note()))?;
+                }
+            }
+            write_diff(&diff::unified_diff(&original, &final_str, &path.display().to_string()))?;
+        }
+        for diagnostic in &found {
+            let msg = match &diagnostic.problem {
+                Problem::Unformatted => format!("{CARGO_TOML} for {krate} is not formatted"),
+                Problem::TrailingNewline => format!("{CARGO_TOML} for {krate} does not end with exactly one newline"),
+                Problem::Lint(lint) => {
+                    write_snippet(diagnostic.severity, &lint.message, &file, (&toml_raw, lint.line), None)?;
+                    continue;
+                }
+                _ => continue,
+            };
+            write_problem(diagnostic.severity, msg)?;
+        }
+
+        return Ok(status);
+    }
+
+    if cli.emit() == Emit::Diff {
+        write_diff(&diff::unified_diff(&original, &final_str, &path.display().to_string()))?;
+        return Ok(changed);
     }
 
-    if !origin_already_sorted {
+    if !unchanged {
         std::fs::write(&path, &final_str)?;
-        let msg = format!("{CARGO_TOML} for {:?} has been rewritten", krate.to_string_lossy());
+        timings.write += timings::lap(&mut clock);
+        // One line per file, a changed file fails the commit
+        if cli.pre_commit {
+            write_green("Sorted: ", path.display())?;
+            return Ok(Status::Sorted);
+        }
+        if resolved.is_some() {
+            write_green("Resolved: ", format!("the merge conflicts in the dependencies of {krate}"))?;
+        }
+        let msg = format!("{CARGO_TOML} for {krate:?} has been rewritten");
         write_green("Finished: ", msg)?;
-    } else {
-        let msg = format!("{CARGO_TOML} for {} is sorted already, no changes made", krate.to_string_lossy());
+        if deduplicated > 0 {
+            write_green("Removed: ", format!("{deduplicated} duplicate array entries from {krate}"))?;
+        }
+        if fixed > 0 {
+            write_green("Fixed: ", format!("{fixed} problems in {krate}"))?;
+        }
+        if added > 0 {
+            write_green("Added: ", format!("{added} crates to the workspace members of {krate}"))?;
+        }
+    } else if !cli.pre_commit {
+        let msg = format!("{CARGO_TOML} for {krate} is sorted already, no changes made");
         write_green("Finished: ", msg)?;
     }
 
-    Ok(true)
+    Ok(changed)
 }
 
-fn _main() -> Result<()> {
+/// Returns `Ok(false)` when a manifest is not sorted, errors are reported as `Err`.
+fn _main() -> Result<bool> {
     let mut args: Vec<String> = std::env::args().collect();
     // remove "sort-fix" when invoked `cargo sort-fix` sort-fix is the first arg
     // https://github.com/rust-lang/cargo/issues/7653
@@ -181,17 +737,96 @@ fn _main() -> Result<()> {
     let cwd = std::env::current_dir().map_err(|e| format!("no current directory found: {e}"))?;
     let dir = cwd.to_string_lossy();
 
-    let mut filtered_matches: Vec<String> = cli.cwd.clone();
+    if let Some(Command::Init { force }) = cli.command {
+        let path = cwd.join(config::INIT_FILE);
+        if path.exists() && !force {
+            return Err(format!("{} already exists, use --force to overwrite it", path.display()).into());
+        }
+        std::fs::write(&path, config::starter_config())?;
+        write_green("Finished: ", format!("wrote {}", path.display()))?;
+        return Ok(true);
+    }
+    if cli.lsp {
+        return lsp::run(&cli, &cwd);
+    }
+    let socket = match &cli.socket {
+        Some(socket) => socket.clone(),
+        None if cli.daemon || cli.client => daemon::default_socket()?,
+        None => PathBuf::new(),
+    };
+    if cli.daemon {
+        return daemon::run(&cli, &cwd, &socket);
+    }
+    if cli.hook == Some(Hook::Install) {
+        return install_hook(&cwd);
+    }
+    // pre-commit runs the hook without files when none of them is staged
+    if cli.pre_commit && cli.cwd.is_empty() {
+        return Ok(true);
+    }
+    if let Some(Command::Hoist { dry_run }) = cli.command {
+        return hoist_workspace(&cwd, &cli, dry_run);
+    }
+    if let Some(Command::Diff { old, new }) = &cli.command {
+        return diff_manifests(&cwd, &cli, old, new);
+    }
+    if let Some(Command::MergeDriver { base, ours, theirs, path }) = &cli.command {
+        return merge_driver(&cwd, &cli, [base, ours, theirs], path.as_deref());
+    }
+
+    let mut filtered_matches: Vec<String> = vec![];
+    for path in &cli.cwd {
+        if discover::is_glob(path) {
+            let found = discover::expand_glob(path)?;
+            filtered_matches.extend(found.iter().map(|p| p.display().to_string()));
+        } else {
+            filtered_matches.push(path.clone());
+        }
+    }
+    if let Some(manifest) = &cli.manifest_path {
+        if manifest.file_name().is_none_or(|name| name != CARGO_TOML) || manifest.is_dir() {
+            return Err(format!("the manifest-path must be a path to a {CARGO_TOML} file: {}", manifest.display()).into());
+        }
+        // `Cargo.toml` alone has no crate folder to name
+        let manifest = if manifest.parent().is_some_and(|dir| dir.as_os_str().is_empty()) {
+            cwd.join(manifest)
+        } else {
+            manifest.clone()
+        };
+        // Like `cargo --workspace`, the members of the workspace the crate is in
+        let manifest = if cli.workspace {
+            discover::workspace_root(&manifest)?
+        } else {
+            manifest
+        };
+        filtered_matches.push(manifest.display().to_string());
+    }
     let is_posible_workspace = filtered_matches.is_empty() || filtered_matches.len() == 1;
     if filtered_matches.is_empty() {
         filtered_matches.push(dir.to_string());
     }
 
+    if cli.recursive {
+        let mut found = vec![];
+        for path in &filtered_matches {
+            let path = PathBuf::from(path);
+            if path.is_dir() {
+                discover::find_manifests(&path, !cli.no_ignore, &mut found)?;
+            } else {
+                found.push(path);
+            }
+        }
+        filtered_matches = found.iter().map(|p| p.display().to_string()).collect();
+    }
+
     if cli.workspace && is_posible_workspace {
         let mut file_path = PathBuf::from(&&filtered_matches[0]);
         let dir = if file_path.is_file() {
             let mut path_dir = file_path.clone();
             path_dir.pop();
+            if path_dir.as_os_str().is_empty() {
+                path_dir.push(".");
+            }
             path_dir.to_string_lossy().to_string()
         } else if file_path.is_dir() {
             let path_dir = file_path.clone();
@@ -204,8 +839,9 @@ fn _main() -> Result<()> {
 
         let raw_toml = read_to_string(&file_path).map_err(|_| format!("no file found at: {}", file_path.display()))?;
 
-        let toml = raw_toml.parse::<DocumentMut>()?;
-        let workspace = toml.get("workspace");
+        // A root that does not parse has no members to find, it is reported with the others
+        let toml = raw_toml.parse::<DocumentMut>().ok();
+        let workspace = toml.as_ref().and_then(|toml| toml.get("workspace"));
         if let Some(Item::Table(ws)) = workspace {
             // The workspace excludes, used to filter members by
             let excludes = workspace_items_of_kind(&dir, ws, "exclude")?;
@@ -225,37 +861,422 @@ fn _main() -> Result<()> {
         }
     }
 
-    let mut cwd = cwd.clone();
-    cwd.push("tomlfmt.toml");
-    let mut config = read_to_string(&cwd)
-        .or_else(|_err| {
-            cwd.pop();
-            cwd.push(".tomlfmt.toml");
-            read_to_string(&cwd)
-        })
-        .unwrap_or_default()
-        .parse::<Config>()?;
+    if let Some(base) = &cli.changed {
+        let changed = discover::changed_manifests(&cwd, base)?;
+        filtered_matches.retain(|path| {
+            let path = Path::new(path);
+            let manifest = if path.is_dir() { path.join(CARGO_TOML) } else { path.to_path_buf() };
+            manifest.canonicalize().is_ok_and(|manifest| changed.contains(&manifest))
+        });
+        if filtered_matches.is_empty() && cli.message_format == MessageFormat::Human {
+            write_green("Finished: ", format!("no {CARGO_TOML} changed since {base}"))?;
+            return Ok(true);
+        }
+    }
 
-    if !cli.order.is_empty() {
-        config.table_order = cli.order.clone();
+    // The excludes and fail_fast of the config in the current directory apply to every crate
+    let mut dirs = config::Dirs::default();
+    let root_config = load_config_in(&cwd, &cwd, &cli, &mut dirs)?;
+    let excludes = cli
+        .exclude
+        .iter()
+        .chain(&root_config.exclude)
+        .map(|p| glob::Pattern::new(p))
+        .collect::<Result<Vec<_>, _>>()?;
+    filtered_matches.retain(|path| !discover::is_excluded(Path::new(path), &cwd, &excludes));
+
+    // A crate given twice, as `a` and `a/Cargo.toml`, is checked once
+    let mut seen = BTreeSet::new();
+    filtered_matches.retain(|path| {
+        let path = Path::new(path);
+        let manifest = if path.is_dir() { path.join(CARGO_TOML) } else { path.to_path_buf() };
+        seen.insert(manifest.canonicalize().unwrap_or(manifest))
+    });
+
+    if cli.client {
+        return daemon::client(&cli, &socket, &filtered_matches);
+    }
+
+    if cli.stdin {
+        if cli.emit() == Emit::Files {
+            return Err("--emit files can not be used with --stdin".into());
+        }
+        filtered_matches = vec![STDIN.to_owned()];
     }
 
-    let mut flag = true;
-    for sorted in filtered_matches.iter().map(|path| check_toml(path, &cli, &config)) {
-        match sorted {
-            Ok(true) => continue,
-            Ok(false) => flag = false,
+    let baseline = match &cli.baseline {
+        Some(path) if !cli.write_baseline => {
+            let text = read_to_string(path).map_err(|_| format!("No baseline found at: {}", path.display()))?;
+            Some(Baseline::parse(&text).map_err(|e| format!("Failed to parse {}: {e}", path.display()))?)
+        }
+        _ => None,
+    };
+
+    let mut failed = 0;
+    let mut linted = 0;
+    let mut errored = 0;
+    let mut diagnostics = vec![];
+    let mut statuses = vec![];
+    let mut failures = vec![];
+    let mut file_timings = vec![];
+    let dirs = Mutex::new(dirs);
+    let check = |path: &String| {
+        let mut found = vec![];
+        let mut timings = Timings::default();
+        let dir = if cli.stdin {
+            cwd.clone()
+        } else {
+            config::manifest_dir(Path::new(path))
+        };
+        let config = load_config_in(&dir, &cwd, &cli, &mut dirs.lock().unwrap_or_else(PoisonError::into_inner));
+        let result = config.and_then(|config| {
+            check_toml(
+                path,
+                &cli,
+                &config,
+                baseline.as_ref().map(|b| (b, cwd.as_path())),
+                &mut found,
+                &mut timings,
+            )
+        });
+        (result, found, timings)
+    };
+    let jobs = cli.jobs.map_or_else(
+        || std::thread::available_parallelism().map_or(1, NonZeroUsize::get),
+        NonZeroUsize::get,
+    );
+    jobs::run(jobs, &filtered_matches, check, |path, (result, mut found, timings)| {
+        let status = match result {
+            // A file pre-commit rewrote fails the commit
+            Ok(status @ Status::Sorted) if cli.pre_commit => {
+                failed += 1;
+                status
+            }
+            Ok(status @ Status::Unsorted) => {
+                failed += 1;
+                status
+            }
+            Ok(status @ Status::Linted) => {
+                linted += 1;
+                status
+            }
+            Ok(status) => status,
+            Err(e) if cli.message_format != MessageFormat::Human => {
+                found.push(Diagnostic::new(path, Problem::Error(e.to_string())));
+                failures.push((path.as_str(), e.to_string()));
+                errored += 1;
+                Status::Error
+            }
             Err(e) => {
-                write_red("error: ", e)?;
-                flag = false;
+                write_red("error: ", &e)?;
+                failures.push((path.as_str(), e.to_string()));
+                errored += 1;
+                Status::Error
+            }
+        };
+        statuses.push((path.as_str(), status));
+        file_timings.push((path.as_str(), timings));
+        if cli.message_format == MessageFormat::Json && !cli.write_baseline {
+            for diagnostic in &found {
+                println!("{}", diagnostic.to_json());
             }
         }
+        diagnostics.extend(found);
+        Ok(if root_config.fail_fast && failed + linted + errored > 0 {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        })
+    })?;
+
+    // On stderr, the output of --print and the reports stay parsable
+    if cli.timings {
+        jobs::write_to(Stream::Stderr, |stderr| {
+            write!(stderr, "Timings:\n{}", timings::table(&file_timings))
+        })?;
+    }
+
+    if let Some(path) = cli.baseline.as_ref().filter(|_| cli.write_baseline) {
+        let baseline = Baseline::new(&diagnostics, &cwd);
+        std::fs::write(path, baseline.to_string())?;
+        if cli.message_format == MessageFormat::Human {
+            write_green("Finished: ", format!("recorded {} problems in {}", baseline.len(), path.display()))?;
+        }
+        return Ok(errored == 0);
     }
 
-    if !flag {
-        return Err("Some Cargo.toml files are not sorted or formatted".into());
+    if cli.message_format == MessageFormat::Sarif {
+        // Code scanning expects the locations relative to the repository root
+        for diagnostic in &mut diagnostics {
+            diagnostic.file = diagnostic.relative_file(&cwd);
+        }
+        println!("{}", report::sarif(&diagnostics));
     }
-    Ok(())
+
+    // A combined report for the crates of a workspace or multiple paths
+    let total = filtered_matches.len();
+    if statuses.len() < total && cli.message_format == MessageFormat::Human {
+        write_yellow(
+            "Stopped: ",
+            format!("fail-fast skipped the remaining {} of {total} crates", total - statuses.len()),
+        )?;
+    }
+    if total > 1 && (cli.check || cli.emit() == Emit::Files) && cli.message_format == MessageFormat::Human && !cli.pre_commit {
+        write_summary(&statuses)?;
+        write_green(
+            "Finished: ",
+            format!("{} of {total} crates are sorted", statuses.len() - failed - errored),
+        )?;
+    }
+
+    if errored > 0 {
+        let mut message = format!("{errored} of {total} {CARGO_TOML} files could not be checked");
+        // The first line of each error, the rest of the run may have pushed them out of sight
+        if total > 1 {
+            for (path, e) in &failures {
+                message.push_str(&format!("\n  {path}: {}", e.lines().next().unwrap_or_default()));
+            }
+        }
+        return Err(message.into());
+    }
+    if failed > 0 && cli.pre_commit {
+        write_red(
+            "error: ",
+            format!("{failed} of {total} {CARGO_TOML} files were sorted, review and stage them"),
+        )?;
+        return Ok(false);
+    }
+    if failed > 0 {
+        write_red(
+            "error: ",
+            format!("{failed} of {total} {CARGO_TOML} files are not sorted or formatted"),
+        )?;
+    }
+    // Sorted and formatted, only the lints fail them
+    if linted > 0 {
+        write_red("error: ", format!("{linted} of {total} {CARGO_TOML} files fail a lint"))?;
+    }
+    Ok(failed + linted == 0)
+}
+
+/// Writes the pre-commit hook of the git repository in `cwd`, a hook that was
+/// not written by `--hook install` is left alone.
+fn install_hook(cwd: &Path) -> Result<bool> {
+    let output = std::process::Command::new("git")
+        .args(["rev-parse", "--git-path", "hooks"])
+        .current_dir(cwd)
+        .output()
+        .map_err(|e| format!("Failed to run git: {e}"))?;
+    if !output.status.success() {
+        return Err(format!("{} is not in a git repository", cwd.display()).into());
+    }
+    let hooks = cwd.join(String::from_utf8_lossy(&output.stdout).trim());
+    let path = hooks.join("pre-commit");
+    if read_to_string(&path).is_ok_and(|hook| !hook.contains(HOOK_MARKER)) {
+        return Err(format!("{} already exists, add `cargo sort-fix --pre-commit` to it instead", path.display()).into());
+    }
+    std::fs::create_dir_all(&hooks)?;
+    std::fs::write(&path, format!("#!/bin/sh\n# {HOOK_MARKER}\n{HOOK}"))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+    }
+    write_green("Finished: ", format!("wrote {}", path.display()))?;
+    Ok(true)
+}
+
+/// Runs `cargo sort-fix hoist` for the workspace rooted in `cwd`, every
+/// manifest it changes is sorted and formatted again.
+fn hoist_workspace(cwd: &Path, cli: &Cli, dry_run: bool) -> Result<bool> {
+    let root_path = cwd.join(CARGO_TOML);
+    let root_raw = read_to_string(&root_path).map_err(|_| format!("No file found at: {}", root_path.display()))?;
+    let mut root = root_raw.parse::<DocumentMut>()?;
+    let Some(Item::Table(ws)) = root.get("workspace") else {
+        return Err(format!("{} is not a workspace root", root_path.display()).into());
+    };
+    let dir = cwd.to_string_lossy();
+    let excludes = workspace_items_of_kind(&dir, ws, "exclude")?;
+    let mut manifests = vec![];
+    for member in workspace_items_of_kind(&dir, ws, "members")? {
+        let path = member.join(CARGO_TOML);
+        if excludes.contains(&member) || !path.is_file() {
+            continue;
+        }
+        let raw = read_to_string(&path)?;
+        let doc = raw
+            .parse::<DocumentMut>()
+            .map_err(|e| format!("Failed to parse {}: {e}", path.display()))?;
+        manifests.push((path, raw, doc));
+    }
+
+    let mut members = manifests.iter().map(|(.., doc)| doc.clone()).collect::<Vec<_>>();
+    let hoisted = hoist::hoist(&mut root, &mut members);
+    let changed = std::iter::once((root_path, root_raw, root))
+        .chain(manifests.into_iter().zip(members).map(|((path, raw, _), doc)| (path, raw, doc)))
+        .filter(|(_, raw, doc)| doc.to_string() != *raw)
+        .collect::<Vec<_>>();
+    if changed.is_empty() {
+        write_green("Finished: ", "no dependency can be hoisted or inherited")?;
+        return Ok(true);
+    }
+    if dry_run {
+        if !hoisted.is_empty() {
+            write_green("Hoist: ", hoisted.join(", "))?;
+        }
+        for (path, ..) in &changed {
+            write_green("Would change: ", path.display())?;
+        }
+        return Ok(true);
+    }
+
+    let count = changed.len();
+    for (path, raw, doc) in changed {
+        let config = load_config(&config::manifest_dir(&path), cwd, cli)?;
+        let mut sorted = sort::sort_manifest(&path, &doc.to_string(), sort::MATCHER, config.grouped, &config)?;
+        if !cli.no_format {
+            fmt::fmt_toml(&mut sorted, &config);
+        }
+        let crlf = config.crlf.or_else(|| fmt::dominant_crlf(&raw)).unwrap_or(fmt::DEF_CRLF);
+        std::fs::write(&path, fmt::set_line_endings(&sorted.to_string(), crlf))?;
+    }
+    if hoisted.is_empty() {
+        write_green("Finished: ", format!("{count} manifests inherit from [workspace.dependencies]"))?;
+    } else {
+        write_green("Finished: ", format!("hoisted {} to [workspace.dependencies]", hoisted.join(", ")))?;
+    }
+    Ok(true)
+}
+
+/// Runs `cargo sort-fix diff`, both manifests are sorted with the config of
+/// the new one before they are compared.
+fn diff_manifests(cwd: &Path, cli: &Cli, old: &Path, new: &Path) -> Result<bool> {
+    let read = |path: &Path| -> Result<(PathBuf, String)> {
+        let path = if path.is_dir() { path.join(CARGO_TOML) } else { path.to_path_buf() };
+        let raw = read_to_string(&path).map_err(|_| format!("No file found at: {}", path.display()))?;
+        Ok((path, raw))
+    };
+    let (old_path, old_raw) = read(old)?;
+    let (new_path, new_raw) = read(new)?;
+    let config = load_config(&config::manifest_dir(&new_path), cwd, cli)?;
+    let sorted = |path: &Path, raw: &str| sort::sort_manifest(path, raw, sort::MATCHER, config.grouped, &config);
+
+    let diff = diff::manifest_diff(
+        &sorted(&old_path, &old_raw)?,
+        &sorted(&new_path, &new_raw)?,
+        &old_path.display().to_string(),
+        &new_path.display().to_string(),
+    );
+    if diff.is_empty() {
+        write_green("Finished: ", "the manifests only differ in order and formatting")?;
+    } else {
+        write_diff(&diff)?;
+    }
+    Ok(true)
+}
+
+/// Runs `cargo sort-fix merge-driver`, returns false if a conflict is left in
+/// `ours`. The three versions are sorted and formatted the same way and merged
+/// key by key. A key both sides changed, or a version that does not parse, is
+/// left to `git merge-file`, which merges the sorted files line by line and
+/// writes the conflict markers.
+fn merge_driver(cwd: &Path, cli: &Cli, files: [&PathBuf; 3], path: Option<&Path>) -> Result<bool> {
+    let [base, ours, theirs] = files;
+    let config = load_config(&config::manifest_dir(path.unwrap_or(ours)), cwd, cli)?;
+    let tidy = |doc: DocumentMut, raw: &str| -> Result<String> {
+        let mut sorted = sort::sort_toml(&doc.to_string(), sort::MATCHER, config.grouped, &config)?;
+        if !cli.no_format {
+            fmt::fmt_toml(&mut sorted, &config);
+        }
+        let crlf = config.crlf.or_else(|| fmt::dominant_crlf(raw)).unwrap_or(fmt::DEF_CRLF);
+        Ok(fmt::set_line_endings(&sorted.to_string(), crlf))
+    };
+
+    let raw = files.map(|file| read_to_string(file).map_err(|_| format!("No file found at: {}", file.display())));
+    let [base_raw, ours_raw, theirs_raw] = raw;
+    let (base_raw, ours_raw, theirs_raw) = (base_raw?, ours_raw?, theirs_raw?);
+    let parsed = (
+        base_raw.parse::<DocumentMut>(),
+        ours_raw.parse::<DocumentMut>(),
+        theirs_raw.parse::<DocumentMut>(),
+    );
+    if let (Ok(base_doc), Ok(ours_doc), Ok(theirs_doc)) = parsed {
+        let sorted = |doc: &DocumentMut| sort::sort_toml(&doc.to_string(), sort::MATCHER, config.grouped, &config);
+        let (base_doc, mut merged, theirs_doc) = (sorted(&base_doc)?, sorted(&ours_doc)?, sorted(&theirs_doc)?);
+        let conflicts = merge::merge(&base_doc, &mut merged, &theirs_doc);
+        if conflicts.is_empty() {
+            std::fs::write(ours, tidy(merged, &ours_raw)?)?;
+            return Ok(true);
+        }
+        for conflict in &conflicts {
+            write_red("conflict: ", format!("both sides changed `{conflict}`"))?;
+        }
+        std::fs::write(base, tidy(base_doc, &base_raw)?)?;
+        std::fs::write(ours, tidy(ours_doc, &ours_raw)?)?;
+        std::fs::write(theirs, tidy(theirs_doc, &theirs_raw)?)?;
+    }
+
+    let status = std::process::Command::new("git")
+        .args(["merge-file", "-L", "ours", "-L", "base", "-L", "theirs"])
+        .args([ours, base, theirs])
+        .status()
+        .map_err(|e| format!("Failed to run git merge-file: {e}"))?;
+    Ok(status.success())
+}
+
+/// Reads the config for the crate in `dir` and applies the command line flags over it.
+fn load_config(dir: &Path, fallback: &Path, cli: &Cli) -> Result<Config> {
+    load_config_in(dir, fallback, cli, &mut config::Dirs::default())
+}
+
+/// Like [`load_config`], the directories read before are taken from `dirs`.
+fn load_config_in(dir: &Path, fallback: &Path, cli: &Cli, dirs: &mut config::Dirs) -> Result<Config> {
+    let mut config = config::load(dir, fallback, dirs)?;
+
+    if !cli.order.is_empty() {
+        // Allow `--order "package, dependencies"`
+        config.table_order = cli
+            .order
+            .iter()
+            .map(|t| t.trim())
+            .filter(|t| !t.is_empty())
+            .map(str::to_owned)
+            .collect();
+    }
+    if cli.default_feature_first {
+        config.default_feature_first = true;
+    }
+    if cli.dedup_arrays {
+        config.dedup_arrays = true;
+    }
+    if cli.grouped {
+        config.grouped = true;
+    }
+    if cli.no_group {
+        config.ungroup = true;
+    }
+    if cli.fail_fast {
+        config.fail_fast = true;
+    }
+    if cli.no_fail_fast {
+        config.fail_fast = false;
+    }
+    if cli.check_format {
+        config.check_format = true;
+    }
+    if cli.sort_only {
+        // Everything that is not a reordering of the lines as they are
+        config.max_inline_dependency_len = None;
+        config.inline_dependency_tables = false;
+        config.ungroup = false;
+        config.groups.clear();
+        config.group_headers = false;
+        config.crlf = None;
+        config.strip_bom = false;
+        config.dedup_arrays = false;
+        config.normalize_workspace_paths = false;
+    }
+    Ok(config)
 }
 
 fn array_string_members(value: &Item) -> Vec<&str> {
@@ -281,10 +1302,14 @@ fn workspace_items_of_kind(dir: &str, ws: &toml_edit::Table, kind: &str) -> Resu
 }
 
 fn main() {
-    _main().unwrap_or_else(|e| {
-        write_red("error: ", e).unwrap();
-        std::process::exit(1);
-    });
+    match _main() {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(EXIT_UNSORTED),
+        Err(e) => {
+            write_red("error: ", e).unwrap();
+            std::process::exit(EXIT_ERROR);
+        }
+    }
 }
 
 // #[test]
@@ -0,0 +1,135 @@
+use toml_edit::{DocumentMut, Item, Table};
+
+use crate::{diff, sort::DEPENDENCY_KINDS};
+
+/// Merges the changes from `base` to `theirs` into `ours` key by key, the
+/// order of the keys and tables and their formatting do not matter. A
+/// dependency spec is merged as a whole. Returns the dotted paths of the keys
+/// both sides changed in different ways, `ours` keeps its own value for them.
+pub(crate) fn merge(base: &DocumentMut, ours: &mut DocumentMut, theirs: &DocumentMut) -> Vec<String> {
+    let mut conflicts = vec![];
+    merge_table(
+        Some(base.as_table()),
+        ours.as_table_mut(),
+        theirs.as_table(),
+        "",
+        false,
+        &mut conflicts,
+    );
+    conflicts
+}
+
+fn merge_table(base: Option<&Table>, ours: &mut Table, theirs: &Table, path: &str, dependencies: bool, conflicts: &mut Vec<String>) {
+    let mut keys = ours.iter().map(|(key, _)| key.to_owned()).collect::<Vec<_>>();
+    for (key, _) in theirs.iter().chain(base.into_iter().flat_map(Table::iter)) {
+        if !keys.iter().any(|k| k == key) {
+            keys.push(key.to_owned());
+        }
+    }
+
+    for key in keys {
+        let child = if path.is_empty() { key.clone() } else { format!("{path}.{key}") };
+        let base_item = base.and_then(|b| b.get(&key));
+        let theirs_item = theirs.get(&key);
+        // Two versions of a table are merged key by key
+        if !dependencies
+            && let (Some(Item::Table(sub)), Some(Item::Table(their_sub))) = (ours.get_mut(&key), theirs_item)
+            && !sub.is_dotted()
+            && !their_sub.is_dotted()
+        {
+            let base_sub = base_item.and_then(Item::as_table);
+            let dependencies = DEPENDENCY_KINDS.contains(&key.as_str());
+            merge_table(base_sub, sub, their_sub, &child, dependencies, conflicts);
+            continue;
+        }
+
+        let ours_item = ours.get(&key);
+        let same = |a: Option<&Item>, b: Option<&Item>| {
+            a.map(|a| diff::normalized(a, dependencies)) == b.map(|b| diff::normalized(b, dependencies))
+        };
+        if same(ours_item, theirs_item) || same(base_item, theirs_item) {
+            continue;
+        }
+        if !same(base_item, ours_item) {
+            conflicts.push(child);
+            continue;
+        }
+        match theirs_item {
+            Some(item) => {
+                ours.insert(&key, item.clone());
+            }
+            None => {
+                ours.remove(&key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use toml_edit::DocumentMut;
+
+    use super::merge;
+
+    #[test]
+    fn merge_keys() {
+        let base = r#"[package]
+name = "foo"
+version = "0.1.0"
+
+[dependencies]
+log = "0.4"
+rand = "0.8"
+serde = "1"
+"#
+        .parse::<DocumentMut>()
+        .unwrap();
+        let mut ours = r#"[package]
+name = "foo"
+version = "0.2.0"
+
+[dependencies]
+anyhow = "1"
+log = "0.4"
+rand = "0.8"
+serde = { version = "1", features = ["derive"] }
+"#
+        .parse::<DocumentMut>()
+        .unwrap();
+        let theirs = r#"[package]
+version = "0.1.0"
+name = "foo"
+
+[dependencies]
+log = { version = "0.4" }
+serde = "1"
+tokio = "1"
+
+[dev-dependencies]
+tempfile = "3"
+"#
+        .parse::<DocumentMut>()
+        .unwrap();
+
+        assert!(merge(&base, &mut ours, &theirs).is_empty());
+        assert_eq!(
+            ours.to_string(),
+            r#"[package]
+name = "foo"
+version = "0.2.0"
+
+[dependencies]
+anyhow = "1"
+log = "0.4"
+serde = { version = "1", features = ["derive"] }
+tokio = "1"
+
+[dev-dependencies]
+tempfile = "3"
+"#
+        );
+
+        let theirs = "[package]\nname = \"foo\"\nversion = \"0.3.0\"\n".parse::<DocumentMut>().unwrap();
+        assert_eq!(merge(&base, &mut ours, &theirs), vec!["package.version", "dependencies"]);
+    }
+}
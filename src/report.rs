@@ -0,0 +1,385 @@
+use std::{io, path::Path};
+
+use serde_json::json;
+use termcolor::{Color, ColorSpec, WriteColor};
+
+use crate::{
+    check::{Lint, Violation},
+    fmt::Severity,
+};
+
+/// The rules of the problems sorting and formatting fix, every other rule
+/// but `error` is a lint.
+pub(crate) const SORTING_RULES: &[&str] = &["unsorted-key", "unsorted-table", "unsorted", "unformatted", "trailing-newline"];
+
+/// A problem found in one manifest by `--check`, reported by the machine
+/// readable message formats.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Diagnostic {
+    pub file: String,
+    pub problem: Problem,
+    /// Whether the problem fails the check, a lint brings its own.
+    pub severity: Severity,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Problem {
+    /// A key or table that is out of order.
+    Violation(Violation),
+    /// The file is not sorted but no key moved, only comments or whitespace.
+    Unsorted,
+    /// The file is sorted but formatting changes it.
+    Unformatted,
+    /// The file does not end with exactly one newline, see `trailing_newline`.
+    TrailingNewline,
+    /// A mistake found by one of the lints.
+    Lint(Lint),
+    /// The file could not be checked at all.
+    Error(String),
+}
+
+impl Diagnostic {
+    pub(crate) fn new(file: &str, problem: Problem) -> Self {
+        let severity = match &problem {
+            Problem::Lint(lint) => lint.severity,
+            _ => Severity::Error,
+        };
+        Self {
+            file: file.to_owned(),
+            problem,
+            severity,
+        }
+    }
+
+    pub(crate) fn with_severity(self, severity: Severity) -> Self {
+        Self { severity, ..self }
+    }
+
+    /// The `level` of the SARIF report and `severity` of the JSON message.
+    fn level(&self) -> &'static str {
+        match self.severity {
+            Severity::Warn => "warning",
+            _ => "error",
+        }
+    }
+
+    pub(crate) fn message(&self) -> String {
+        match &self.problem {
+            Problem::Violation(violation) => violation.message(),
+            Problem::Unsorted => "file is not sorted".to_owned(),
+            Problem::Unformatted => "file is not formatted".to_owned(),
+            Problem::TrailingNewline => "file does not end with exactly one newline".to_owned(),
+            Problem::Lint(lint) => lint.message.clone(),
+            Problem::Error(e) => e.clone(),
+        }
+    }
+
+    pub(crate) fn rule_id(&self) -> &'static str {
+        match &self.problem {
+            Problem::Violation(Violation { key: Some(_), .. }) => "unsorted-key",
+            Problem::Violation(Violation { key: None, .. }) => "unsorted-table",
+            Problem::Unsorted => "unsorted",
+            Problem::Unformatted => "unformatted",
+            Problem::TrailingNewline => "trailing-newline",
+            Problem::Lint(lint) => lint.rule,
+            Problem::Error(_) => "error",
+        }
+    }
+
+    fn violation(&self) -> Option<&Violation> {
+        match &self.problem {
+            Problem::Violation(violation) => Some(violation),
+            _ => None,
+        }
+    }
+
+    /// The table, key and line the problem is at.
+    pub(crate) fn location(&self) -> Option<(&str, Option<&str>, usize)> {
+        match &self.problem {
+            Problem::Violation(v) => Some((&v.table, v.key.as_deref(), v.line)),
+            Problem::Lint(lint) => Some((&lint.table, Some(&lint.key), lint.line)),
+            _ => None,
+        }
+    }
+
+    /// The file relative to `cwd` with `/` separators, the way code scanning
+    /// and the baseline expect it.
+    pub(crate) fn relative_file(&self, cwd: &Path) -> String {
+        let path = Path::new(&self.file);
+        let relative = path.strip_prefix(cwd).or_else(|_| path.strip_prefix(".")).unwrap_or(path);
+        relative.display().to_string().replace('\\', "/")
+    }
+
+    /// The JSON object of `--message-format json`, written on a single line.
+    pub(crate) fn to_json(&self) -> String {
+        let location = self.location();
+        json!({
+            "file": self.file,
+            "table": location.map(|(table, ..)| table),
+            "key": location.and_then(|(_, key, _)| key),
+            "line": location.map(|(.., line)| line),
+            "expected_position": self.violation().map(|v| v.expected_position),
+            "severity": self.level(),
+            "message": self.message(),
+        })
+        .to_string()
+    }
+}
+
+/// The rules of the SARIF report, one per kind of [`Problem`].
+const SARIF_RULES: &[(&str, &str)] = &[
+    ("unsorted-key", "A key is not in sorted order within its table"),
+    ("unsorted-table", "A table is not in the configured table order"),
+    ("unsorted", "The manifest is not sorted"),
+    ("unformatted", "The manifest is not formatted"),
+    ("trailing-newline", "The manifest does not end with exactly one newline"),
+    ("duplicate-dependency", "A crate has the same spec in two kinds of dependency table"),
+    ("separator-variant", "A crate is written with `-` in one place and `_` in another"),
+    ("wildcard-version", "A dependency accepts any version"),
+    ("unbounded-version", "A dependency has a `>=` requirement without an upper bound"),
+    ("dangling-feature", "A feature enables a dependency or feature that does not exist"),
+    ("unused-optional", "No feature enables an optional dependency"),
+    ("crates-io-limits", "The keywords or categories break the limits of crates.io"),
+    ("invalid-version", "A version requirement is not valid semver"),
+    ("workspace-overlap", "A workspace member is also excluded"),
+    ("missing-member", "A workspace member has no Cargo.toml"),
+    ("unused-workspace-dependency", "No workspace member inherits a workspace dependency"),
+    (
+        "version-conflict",
+        "Workspace members require a crate in versions cargo can not unify",
+    ),
+    ("error", "The manifest could not be checked"),
+];
+
+/// Returns a SARIF 2.1.0 log of all the diagnostics, the format read by GitHub
+/// code scanning and other dashboards.
+pub(crate) fn sarif(diagnostics: &[Diagnostic]) -> String {
+    let rules = SARIF_RULES
+        .iter()
+        .map(|(id, text)| json!({"id": id, "shortDescription": {"text": text}}))
+        .collect::<Vec<_>>();
+
+    let results = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            let mut location = json!({"artifactLocation": {"uri": diagnostic.file.replace('\\', "/")}});
+            if let Some((.., line)) = diagnostic.location().filter(|(.., line)| *line > 0) {
+                location["region"] = json!({"startLine": line});
+            }
+            json!({
+                "ruleId": diagnostic.rule_id(),
+                "level": diagnostic.level(),
+                "message": {"text": diagnostic.message()},
+                "locations": [{"physicalLocation": location}],
+            })
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": clap::crate_name!(),
+                    "version": clap::crate_version!(),
+                    "informationUri": env!("CARGO_PKG_REPOSITORY"),
+                    "rules": rules,
+                },
+            },
+            "results": results,
+        }],
+    })
+    .to_string()
+}
+
+/// Writes a problem the way rustc shows its errors, the `line` of `source`
+/// it is on with carets under the key or heading and the `note` next to them:
+///
+/// ```text
+/// error: `a` in [dependencies] should be at position 1
+///  --> Cargo.toml:3:1
+///   |
+/// 3 | a = "1"
+///   | ^ expected `a` before `b` in [dependencies]
+/// ```
+pub(crate) fn write_snippet(
+    w: &mut dyn WriteColor,
+    severity: Severity,
+    message: &str,
+    file: &str,
+    (source, line): (&str, usize),
+    note: Option<&str>,
+) -> io::Result<()> {
+    let (color, label) = match severity {
+        Severity::Warn => (Color::Yellow, "warning: "),
+        _ => (Color::Red, "error: "),
+    };
+    let mut gutter = ColorSpec::new();
+    gutter.set_fg(Some(Color::Blue)).set_bold(true);
+    w.set_color(ColorSpec::new().set_fg(Some(color)))?;
+    write!(w, "{label}")?;
+    w.reset()?;
+    writeln!(w, "{message}")?;
+
+    // Line 0 is a problem the lines do not tell apart
+    let Some(text) = line.checked_sub(1).and_then(|i| source.lines().nth(i)) else {
+        w.set_color(&gutter)?;
+        write!(w, " --> ")?;
+        w.reset()?;
+        return writeln!(w, "{file}");
+    };
+    let (start, end) = underline(text);
+    let pad = " ".repeat(line.to_string().len());
+    // Tabs stay tabs so the carets line up with the text above them
+    let indent = text[..start]
+        .chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect::<String>();
+
+    w.set_color(&gutter)?;
+    write!(w, "{pad}--> ")?;
+    w.reset()?;
+    writeln!(w, "{file}:{line}:{}", text[..start].chars().count() + 1)?;
+    w.set_color(&gutter)?;
+    writeln!(w, "{pad} |")?;
+    write!(w, "{line} | ")?;
+    w.reset()?;
+    writeln!(w, "{text}")?;
+    w.set_color(&gutter)?;
+    write!(w, "{pad} | ")?;
+    w.set_color(ColorSpec::new().set_fg(Some(color)).set_bold(true))?;
+    write!(w, "{indent}{}", "^".repeat(text[start..end].chars().count().max(1)))?;
+    if let Some(note) = note {
+        write!(w, " {note}")?;
+    }
+    w.reset()?;
+    writeln!(w)
+}
+
+/// The bytes of the key or `[heading]` a line of a manifest starts with.
+fn underline(text: &str) -> (usize, usize) {
+    let start = text.len() - text.trim_start().len();
+    let rest = &text[start..];
+    let len = if rest.starts_with("[[") {
+        rest.find("]]").map(|i| i + 2)
+    } else if rest.starts_with('[') {
+        rest.find(']').map(|i| i + 1)
+    } else {
+        rest.find('=').map(|i| rest[..i].trim_end().len())
+    };
+    (start, start + len.unwrap_or(rest.trim_end().len()))
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::{Value, json};
+    use termcolor::Buffer;
+
+    use super::{Diagnostic, Problem, sarif, write_snippet};
+    use crate::{check::Violation, fmt::Severity};
+
+    fn violation() -> Violation {
+        Violation {
+            table: "target.'cfg(unix)'.dependencies".to_owned(),
+            key: Some("c".to_owned()),
+            line: 8,
+            expected_position: 3,
+            before: Some("d".to_owned()),
+        }
+    }
+
+    #[test]
+    fn json_output() {
+        assert_eq!(
+            Diagnostic::new("a\\\"b\".toml", Problem::Error("line\n\u{1}".to_owned())).to_json(),
+            concat!(
+                r#"{"expected_position":null,"file":"a\\\"b\".toml","key":null,"line":null,"#,
+                r#""message":"line\n\u0001","severity":"error","table":null}"#
+            )
+        );
+
+        let diagnostic = Diagnostic::new("Cargo.toml", Problem::Violation(violation()));
+        assert_eq!(
+            diagnostic.to_json(),
+            concat!(
+                r#"{"expected_position":3,"file":"Cargo.toml","key":"c","line":8,"#,
+                r#""message":"`c` in [target.'cfg(unix)'.dependencies] should be at position 3","severity":"error","#,
+                r#""table":"target.'cfg(unix)'.dependencies"}"#
+            )
+        );
+        assert_eq!(
+            Diagnostic::new("Cargo.toml", Problem::Unformatted)
+                .with_severity(Severity::Warn)
+                .to_json(),
+            concat!(
+                r#"{"expected_position":null,"file":"Cargo.toml","key":null,"line":null,"#,
+                r#""message":"file is not formatted","severity":"warning","table":null}"#
+            )
+        );
+    }
+
+    #[test]
+    fn snippets() {
+        let source = "[dependencies]\nd = \"1\"\n\ttarget.'cfg(unix)'.c = \"1\"\n\n[[bin]]\n";
+        let snippet = |severity, line, note| {
+            let mut buffer = Buffer::no_color();
+            write_snippet(&mut buffer, severity, "message", "Cargo.toml", (source, line), note).unwrap();
+            String::from_utf8(buffer.into_inner()).unwrap()
+        };
+        let note = violation().note();
+        assert_eq!(
+            snippet(Severity::Error, 3, Some(&note)),
+            "error: message
+ --> Cargo.toml:3:2
+  |
+3 | \ttarget.'cfg(unix)'.c = \"1\"
+  | \t^^^^^^^^^^^^^^^^^^^^ expected `c` before `d` in [target.'cfg(unix)'.dependencies]
+"
+        );
+        assert_eq!(
+            snippet(Severity::Warn, 5, None),
+            "warning: message
+ --> Cargo.toml:5:1
+  |
+5 | [[bin]]
+  | ^^^^^^^
+"
+        );
+        assert_eq!(snippet(Severity::Error, 0, None), "error: message\n --> Cargo.toml\n");
+    }
+
+    #[test]
+    fn sarif_output() {
+        let diagnostics = [
+            Diagnostic::new("crates\\a\\Cargo.toml", Problem::Violation(violation())),
+            Diagnostic::new("Cargo.toml", Problem::Unformatted).with_severity(Severity::Warn),
+        ];
+        let report = serde_json::from_str::<Value>(&sarif(&diagnostics)).unwrap();
+        assert_eq!(report["$schema"], "https://json.schemastore.org/sarif-2.1.0.json");
+        assert_eq!(report["version"], "2.1.0");
+        assert_eq!(report["runs"][0]["tool"]["driver"]["name"], "cargo-sort-fix");
+        assert_eq!(
+            report["runs"][0]["results"],
+            json!([
+                {
+                    "ruleId": "unsorted-key",
+                    "level": "error",
+                    "message": {"text": "`c` in [target.'cfg(unix)'.dependencies] should be at position 3"},
+                    "locations": [{
+                        "physicalLocation": {
+                            "artifactLocation": {"uri": "crates/a/Cargo.toml"},
+                            "region": {"startLine": 8},
+                        },
+                    }],
+                },
+                {
+                    "ruleId": "unformatted",
+                    "level": "warning",
+                    "message": {"text": "file is not formatted"},
+                    "locations": [{"physicalLocation": {"artifactLocation": {"uri": "Cargo.toml"}}}],
+                },
+            ])
+        );
+    }
+}
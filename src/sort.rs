@@ -1,11 +1,74 @@
-use std::{cmp::Ordering, collections::BTreeMap, iter::FromIterator};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashSet},
+    iter::FromIterator,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use toml_edit::{Array, ArrayOfTables, Decor, DocumentMut, InlineTable, Item, Key, RawString, Table, TableLike, TomlError, Value};
 
-use toml_edit::{Array, Decor, DocumentMut, Item, RawString, Table, Value};
+use crate::{
+    check,
+    fmt::{Comparator, Config, DependencyGroup, KeyOrder, Strategy},
+};
 
 /// Leading string for combining keys such as
 /// `[target.'cfg(target_os="linux")'.dependencies]` in Cargo.toml files.
 const TARGET: &str = "target";
 
+/// The `[features]` heading, the arrays of which are also sorted.
+const FEATURES: &str = "features";
+
+/// The `[lints]` heading, each tool table in it is sorted.
+///
+/// A lint is either `name = "level"` or `name = { level = "warn", priority = -1 }`
+/// both of which are ordered by the lint name, the value is left as is.
+const LINTS: &str = "lints";
+
+/// The `[patch]` heading, each `[patch.<registry>]` table in it is sorted.
+const PATCH: &str = "patch";
+
+/// The `[package]` heading, the keys are sorted by `package_key_order`.
+const PACKAGE: &str = "package";
+
+/// The `[profile]` heading, the keys of each profile are sorted by `profile_key_order`.
+const PROFILE: &str = "profile";
+
+/// The comment above a table heading that keeps the keys of the table as they are.
+const KEEP_DIRECTIVE: &str = "cargo-sort-fix: keep";
+
+/// The comment at the top of a manifest that leaves the whole file as it is.
+const SKIP_FILE_DIRECTIVE: &str = "cargo-sort-fix: skip-file";
+
+/// The comment, `# sort: first` or `# sort: last`, that pins a key to the
+/// start or end of its table or group.
+const PIN_DIRECTIVE: &str = "sort:";
+
+/// The start of a comment, `# --- async ---`, that marks a section of a table,
+/// keys are only sorted within their section.
+const SECTION_MARKER: &str = "---";
+
+/// The comment, `# -- workspace --`, written above each block with
+/// `group_headers`, the name goes between the two.
+const GROUP_HEADER: (&str, &str) = ("-- ", " --");
+
+/// The `[workspace]` heading.
+const WORKSPACE: &str = "workspace";
+
+/// The tables that hold dependency specifications, either at the top level,
+/// in a `[target.*]` table or in `[workspace]`.
+pub(crate) const DEPENDENCY_KINDS: &[&str] = &["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// The path lists of `[workspace]`, normalized with `normalize_workspace_paths`.
+const WORKSPACE_PATHS: &[&str] = &["members", "exclude"];
+
+/// The `[[heading]]` cargo targets, sorted by their `name`.
+const CARGO_TARGETS: &[&str] = &["bin", "example", "test", "bench"];
+
+/// The feature that can be kept first with `default_feature_first`.
+const DEFAULT_FEATURE: &str = "default";
+
 /// Stores the paths of target tables in a BTreeMap, the data structure looks like:
 /// ```plain
 /// target_tables: {
@@ -35,13 +98,21 @@ pub(crate) struct Matcher<'a> {
 }
 
 pub(crate) const MATCHER: Matcher<'_> = Matcher {
-    heading: &["dependencies", "dev-dependencies", "build-dependencies"],
+    heading: &[
+        "dependencies",
+        "dev-dependencies",
+        "build-dependencies",
+        "features",
+        "patch",
+        "replace",
+    ],
     heading_key: &[
         ("workspace", "members"),
         ("workspace", "exclude"),
         ("workspace", "dependencies"),
         ("workspace", "dev-dependencies"),
         ("workspace", "build-dependencies"),
+        ("workspace", "lints"),
     ],
 };
 
@@ -56,25 +127,130 @@ enum Heading {
     Complete(Vec<String>),
 }
 
-/// Returns a sorted toml `DocumentMut`.
-pub(crate) fn sort_toml(input: &str, matcher: Matcher<'_>, group: bool, ordering: &[String]) -> DocumentMut {
-    let mut ordering = ordering.to_owned();
-    let mut toml = input.parse::<DocumentMut>().unwrap();
+/// A manifest that is not valid TOML.
+#[derive(Debug)]
+pub(crate) struct ParseError {
+    /// The manifest, `None` for text that was not read from a file.
+    pub(crate) path: Option<PathBuf>,
+    /// The bytes of the input the error is at.
+    pub(crate) span: Option<Range<usize>>,
+    /// The message of toml_edit, without the lines of the input.
+    pub(crate) message: String,
+    /// The message with the line it is on, or both definitions of a
+    /// duplicate key.
+    detail: String,
+}
+
+impl ParseError {
+    pub(crate) fn new(input: &str, error: &TomlError) -> Self {
+        Self {
+            path: None,
+            span: error.span(),
+            message: error.message().to_owned(),
+            detail: check::duplicate_key(input, error).unwrap_or_else(|| error.to_string()),
+        }
+    }
+
+    pub(crate) fn with_path(mut self, path: &Path) -> Self {
+        self.path = Some(path.to_path_buf());
+        self
+    }
+
+    /// The line of `input` the error is on, starting at 1.
+    pub(crate) fn line(&self, input: &str) -> usize {
+        self.span.as_ref().map_or(1, |span| check::line_of(input, span.start))
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "Failed to parse {}: {}", path.display(), self.detail),
+            None => write!(f, "Failed to parse: {}", self.detail),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parses a manifest, the error carries where it is.
+pub(crate) fn parse(input: &str) -> Result<DocumentMut, ParseError> {
+    input.parse().map_err(|e| ParseError::new(input, &e))
+}
+
+/// Like [`sort_toml`] for the manifest at `path`, an error names the file.
+pub(crate) fn sort_manifest(
+    path: &Path,
+    input: &str,
+    matcher: Matcher<'_>,
+    group: bool,
+    config: &Config,
+) -> Result<DocumentMut, ParseError> {
+    sort_toml(input, matcher, group, config).map_err(|e| e.with_path(path))
+}
+
+/// Returns a sorted toml `DocumentMut`, or where the input is not valid TOML.
+pub(crate) fn sort_toml(input: &str, matcher: Matcher<'_>, group: bool, config: &Config) -> Result<DocumentMut, ParseError> {
+    let mut ordering = config.table_order.clone();
+    let mut toml = parse(input)?;
+    // The rules look at the tables as they were written
+    let original = (!config.rules.is_empty()).then(|| toml.clone());
+    let cmp = config.compare;
+    if config.max_inline_dependency_len.is_some() || config.inline_dependency_tables {
+        let max = config.max_inline_dependency_len.unwrap_or(config.max_array_line_len);
+        for_each_dependency_table(&mut toml, |name, table| {
+            if config.is_skipped(name) || is_kept(table) {
+                return;
+            }
+            if config.inline_dependency_tables {
+                fold_dependency_tables(table, max);
+            }
+            if config.max_inline_dependency_len.is_some() {
+                expand_inline_dependencies(table, max);
+            }
+        });
+        place_expanded_tables(toml.as_table_mut(), None);
+        // Parse again so the new tables get a position like the written ones
+        toml = parse(&toml.to_string())?;
+    }
+    // The headings of the config are sorted like the compiled in ones
+    let headings = matcher
+        .heading
+        .iter()
+        .copied()
+        .chain(config.matcher_headings.iter().map(String::as_str))
+        .collect::<Vec<_>>();
+    for path in &config.matcher_keys {
+        let path = path.split('.').collect::<Vec<_>>();
+        sort_path(toml.as_table_mut(), &path, group, cmp);
+    }
     // This takes care of `[workspace] members = [...]`
     for (heading, key) in matcher.heading_key {
         // Since this `&mut toml[&heading]` is like
         // `SomeMap.entry(key).or_insert(Item::None)` we only want to do it if we
         // know the heading is there already
+        let name = format!("{heading}.{key}");
+        if config.is_skipped(&name) {
+            continue;
+        }
+        let overrides = config.table(&name);
+        let group = overrides.and_then(|t| t.grouped).unwrap_or(group) && !(config.ungroup && DEPENDENCY_KINDS.contains(key));
         if toml.as_table().contains_key(heading)
             && let Item::Table(table) = &mut toml[heading]
             && table.contains_key(key)
         {
             match &mut table[key] {
                 Item::Value(Value::Array(arr)) => {
-                    sort_array(arr);
+                    if config.normalize_workspace_paths && *heading == WORKSPACE && WORKSPACE_PATHS.contains(key) {
+                        normalize_workspace_paths(arr);
+                    }
+                    sort_array(arr, cmp);
+                }
+                Item::Table(table) if *key == LINTS => {
+                    sort_sub_tables(table, group, cmp);
                 }
                 Item::Table(table) => {
-                    sort_table(table, group);
+                    sort_table(table, group, cmp);
                 }
                 _ => {}
             }
@@ -89,7 +265,7 @@ pub(crate) fn sort_toml(input: &str, matcher: Matcher<'_>, group: bool, ordering
         if item_key == TARGET
             && let Some(table) = item.as_table()
         {
-            for &key in matcher.heading {
+            for &key in &headings {
                 let mut path = vec![item_key];
                 let mut deps_tables = vec![];
                 nested_tables_with_key(table, &mut path, key, &mut deps_tables);
@@ -101,12 +277,51 @@ pub(crate) fn sort_toml(input: &str, matcher: Matcher<'_>, group: bool, ordering
             }
         }
 
-        if !matcher.heading.contains(&item_key) && target_tables.is_empty() {
-            if !ordering.contains(&head.to_owned()) && !ordering.is_empty() {
-                ordering.push(head.to_owned());
+        let skipped = config.is_skipped(item_key);
+        if item_key == LINTS
+            && !skipped
+            && let Some(table) = item.as_table_mut()
+        {
+            sort_sub_tables(table, group, cmp);
+        }
+        if item_key == PACKAGE
+            && !skipped
+            && let Some(order) = &config.package_key_order
+            && let Some(table) = item.as_table_mut()
+        {
+            sort_table_by_key_order(table, order, cmp);
+        }
+        if item_key == PROFILE
+            && !skipped
+            && let Some(order) = &config.profile_key_order
+            && let Some(table) = item.as_table_mut()
+        {
+            for (_, item) in table.iter_mut() {
+                if let Item::Table(profile) = item {
+                    sort_table_by_key_order(profile, order, cmp);
+                }
             }
+        }
+
+        if CARGO_TARGETS.contains(&item_key)
+            && !skipped
+            && let Item::ArrayOfTables(arr) = item
+        {
+            sort_array_of_tables(arr, "name", cmp);
+        }
+
+        // Tables that are sorted but missing from the ordering still need a position
+        if target_tables.is_empty() && !ordering.contains(&head.to_owned()) && !ordering.is_empty() {
+            ordering.push(head.to_owned());
+        }
+        if !headings.contains(&item_key) && target_tables.is_empty() {
+            continue;
+        }
+        if skipped || (item_key == FEATURES && !config.sort_features) {
             continue;
         }
+        let overrides = config.table(item_key);
+        let group = overrides.and_then(|t| t.grouped).unwrap_or(group) && !(config.ungroup && DEPENDENCY_KINDS.contains(&item_key));
         match item {
             Item::Table(table) => {
                 if first_table.is_none() {
@@ -123,21 +338,117 @@ pub(crate) fn sort_toml(input: &str, matcher: Matcher<'_>, group: bool, ordering
 
                 gather_headings(table, headings, 1);
                 headings.sort();
-                sort_table(table, group);
-                sort_nested_table(table, &target_tables);
+                sort_table(table, group, cmp);
+                sort_nested_table(table, &target_tables, cmp);
+                if item_key == PATCH {
+                    sort_sub_tables(table, group, cmp);
+                }
+                if item_key == FEATURES && !is_kept(table) {
+                    sort_feature_arrays(table, cmp);
+                    if config.default_feature_first {
+                        pin_first(table, DEFAULT_FEATURE, group);
+                    }
+                }
             }
             Item::None => continue,
             _ => {}
         }
     }
 
+    for_each_dependency_table(&mut toml, |name, table| {
+        if config.is_skipped(name) {
+            return;
+        }
+        for (_, item) in table.iter_mut() {
+            sort_dependency_spec(item, config.dependency_key_order.as_ref(), cmp);
+        }
+        if config.ungroup && !is_kept(table) {
+            remove_blank_lines(table);
+        }
+        if !config.groups.is_empty() && !is_kept(table) {
+            sort_by_groups(table, &config.groups, config.group_headers, cmp);
+        }
+        if !config.priority_crates.is_empty() && !is_kept(table) {
+            hoist(table, &config.priority_crates);
+        }
+    });
+
+    if let Some(original) = original {
+        apply_rules(toml.as_table_mut(), original.as_table(), &mut vec![], config, cmp);
+    }
+
     if ordering.is_empty() {
         sort_lexicographical(first_table, &heading_order, &mut toml);
     } else {
         sort_by_ordering(&ordering, &heading_order, &mut toml);
     }
+    pin_tables(&mut toml, &config.pin_first, &config.pin_last);
+
+    Ok(toml)
+}
+
+/// Sorts every table the first matching rule applies to, over whatever the
+/// built in sorting did. `original` is the same table as written in the file.
+fn apply_rules(table: &mut Table, original: &Table, path: &mut Vec<String>, config: &Config, cmp: Comparator) {
+    for (key, item) in table.iter_mut() {
+        path.push(key.get().to_owned());
+        let rule = config.rules.iter().find(|rule| rule.matches(path));
+        let cmp = Comparator {
+            reverse: rule.is_some_and(|rule| rule.reverse),
+            ..cmp
+        };
+        let written = original.get(key.get());
+        match item {
+            Item::Table(inner) => {
+                let empty = Table::new();
+                let written = written.and_then(Item::as_table).unwrap_or(&empty);
+                let strategy = rule.map(|rule| &rule.strategy).filter(|s| !matches!(s, Strategy::ByKey(_)));
+                if strategy.is_some() {
+                    // Start over from the written order, the groups are lost once sorted
+                    let written_order = written.iter().map(|(k, _)| k).collect::<Vec<_>>();
+                    let idx = |k: &str| written_order.iter().position(|w| *w == k).unwrap_or(usize::MAX);
+                    inner.sort_values_by(|a, _, b, _| idx(a.get()).cmp(&idx(b.get())));
+                }
+                match strategy {
+                    Some(Strategy::Alphabetical) => sort_table(inner, false, cmp),
+                    Some(Strategy::Grouped) => sort_table(inner, true, cmp),
+                    Some(Strategy::BySource) if !is_kept(inner) => sort_by_source(inner, config.group_headers, cmp),
+                    _ => {}
+                }
+                apply_rules(inner, written, path, config, cmp);
+            }
+            Item::ArrayOfTables(arr) => {
+                if let Some(Strategy::ByKey(by)) = rule.map(|rule| &rule.strategy) {
+                    sort_array_of_tables(arr, by, cmp);
+                }
+            }
+            _ => {}
+        }
+        path.pop();
+    }
+}
 
-    toml
+/// Sorts the table or array at the dotted `path`, one of the `[matcher] keys`
+/// of the config like `package.metadata.deb.depends`.
+fn sort_path(table: &mut dyn TableLike, path: &[&str], group: bool, cmp: Comparator) {
+    let Some((first, rest)) = path.split_first() else {
+        return;
+    };
+    let Some(item) = table.get_mut(first) else {
+        return;
+    };
+    if !rest.is_empty() {
+        if let Some(inner) = item.as_table_like_mut() {
+            sort_path(inner, rest, group, cmp);
+        }
+        return;
+    }
+    match item {
+        Item::Value(Value::Array(arr)) => sort_array(arr, cmp),
+        Item::Value(Value::InlineTable(inline)) => inline.sort_values_by(|a, _, b, _| cmp.cmp(a.get(), b.get())),
+        Item::Table(inner) => sort_table(inner, group, cmp),
+        _ => {}
+    }
 }
 
 fn nested_tables_with_key<'a>(table: &'a Table, path: &mut Vec<&'a str>, key_name: &str, result: &mut Vec<Vec<&'a str>>) {
@@ -153,20 +464,27 @@ fn nested_tables_with_key<'a>(table: &'a Table, path: &mut Vec<&'a str>, key_nam
     }
 }
 
-fn sort_array(arr: &mut Array) {
+pub(crate) fn sort_array(arr: &mut Array, cmp: Comparator) {
     let mut all_strings = true;
     let trailing = arr.trailing().clone();
     let trailing_comma = arr.trailing_comma();
 
     let mut arr_copy = arr.iter().cloned().collect::<Vec<_>>();
     arr_copy.sort_by(|a, b| match (a, b) {
-        (Value::String(a), Value::String(b)) => a.value().cmp(b.value()),
+        (Value::String(a), Value::String(b)) => cmp.cmp(a.value(), b.value()),
         _ => {
             all_strings = false;
             Ordering::Equal
         }
     });
     if all_strings {
+        // A single line array keeps the spacing of each position, otherwise
+        // the first item would carry its leading space along with it
+        if !arr.to_string().contains('\n') {
+            for (val, old) in arr_copy.iter_mut().zip(arr.iter()) {
+                *val.decor_mut() = old.decor().clone();
+            }
+        }
         *arr = Array::from_iter(arr_copy);
     }
 
@@ -174,390 +492,2569 @@ fn sort_array(arr: &mut Array) {
     arr.set_trailing_comma(trailing_comma);
 }
 
-fn sort_table(table: &mut Table, group: bool) {
-    if group {
-        sort_by_group(table);
-    } else {
-        table.sort_values();
+/// Removes the exact duplicates of the string arrays `sort_toml` sorts, the
+/// workspace `members` and `exclude`, the values of `[features]` and the
+/// arrays of `[matcher] keys`. Returns how many entries were removed.
+pub(crate) fn dedup_arrays(toml: &mut DocumentMut, config: &Config) -> usize {
+    let mut paths = ["workspace.members", "workspace.exclude"]
+        .into_iter()
+        .chain(config.matcher_keys.iter().map(String::as_str))
+        .filter(|path| !config.is_skipped(path))
+        .map(|path| path.split('.').map(str::to_owned).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    if let Some(features) = toml.get(FEATURES).and_then(Item::as_table)
+        && config.sort_features
+        && !config.is_skipped(FEATURES)
+        && !is_kept(features)
+    {
+        paths.extend(features.iter().map(|(name, _)| vec![FEATURES.to_owned(), name.to_owned()]));
     }
-}
-
-fn sort_nested_table(table: &mut Table, target_tables: &TargetTablePaths) {
-    // The `table` name must be `target`
-    for paths in target_tables.values() {
-        for path in paths {
-            if path.len() > 1 {
-                sort_table_by_path(table, &path[1..]);
-            }
+    let mut removed = 0;
+    for path in &paths {
+        if let Some(arr) = array_at(toml.as_table_mut(), path) {
+            removed += dedup_array(arr);
         }
     }
+    removed
 }
 
-fn sort_table_by_path(table: &mut Table, path: &[String]) {
-    let Some(first) = path.first() else {
-        table.sort_values();
-        return;
-    };
-    if let Some(Item::Table(inner_table)) = table.get_mut(first) {
-        sort_table_by_path(inner_table, &path[1..]);
+fn array_at<'a>(table: &'a mut dyn TableLike, path: &[String]) -> Option<&'a mut Array> {
+    let (last, parents) = path.split_last()?;
+    let mut table = table;
+    for key in parents {
+        table = table.get_mut(key)?.as_table_like_mut()?;
     }
+    table.get_mut(last)?.as_array_mut()
 }
 
-fn gather_headings(table: &Table, keys: &mut Vec<Heading>, depth: usize) {
-    if table.is_empty() && !table.is_implicit() {
-        let next = match keys.pop().unwrap() {
-            Heading::Next(segs) => Heading::Complete(segs),
-            comp => comp,
+/// Writes every path of `workspace.members` or `workspace.exclude` without a
+/// `./` prefix or a trailing `/` and removes the duplicates this leaves.
+fn normalize_workspace_paths(arr: &mut Array) {
+    for value in arr.iter_mut() {
+        let Some(path) = value.as_str() else {
+            continue;
         };
-        keys.push(next);
-    }
-    for (head, item) in table.iter() {
-        match item {
-            Item::Value(_) => {
-                if keys.last().is_some_and(|h| matches!(h, Heading::Complete(_))) {
-                    continue;
-                }
-                let next = match keys.pop().unwrap() {
-                    Heading::Next(segs) => Heading::Complete(segs),
-                    _complete => unreachable!("the above if check prevents this"),
-                };
-                keys.push(next);
-                continue;
-            }
-            Item::Table(table) => {
-                let next = match keys.pop().unwrap() {
-                    Heading::Next(mut segs) => {
-                        segs.push(head.into());
-                        Heading::Next(segs)
-                    }
-                    // This happens when
-                    //
-                    // [heading]       // transitioning from here to
-                    // [heading.segs]  // here
-                    Heading::Complete(segs) => {
-                        let take = depth.max(1);
-                        let mut next = segs[..take].to_vec();
-                        next.push(head.into());
-                        keys.push(Heading::Complete(segs));
-                        Heading::Next(next)
-                    }
-                };
-                keys.push(next);
-                gather_headings(table, keys, depth + 1);
-            }
-            Item::ArrayOfTables(_arr) => unreachable!("no [[heading]] are sorted"),
-            Item::None => unreachable!("an empty table will not be sorted"),
+        let normalized = normalize_path(path);
+        if normalized != path {
+            let mut new = Value::from(normalized);
+            *new.decor_mut() = value.decor().clone();
+            *value = new;
         }
     }
+    dedup_array(arr);
 }
 
-fn sort_by_group(table: &mut Table) {
-    let table_clone = table.clone();
-    table.clear();
-
-    let mut groups = BTreeMap::new();
-    let mut group_decor = BTreeMap::default();
-
-    let mut curr = 0;
-    for (idx, (k, _)) in table_clone.iter().enumerate() {
-        let (k, v) = table_clone.get_key_value(k).unwrap();
+/// A workspace path without a `./` prefix or a trailing `/`, `./` becomes `.`.
+pub(crate) fn normalize_path(path: &str) -> &str {
+    let mut rest = path;
+    while let Some(inner) = rest.strip_prefix("./") {
+        rest = inner;
+    }
+    match rest.trim_end_matches('/') {
+        "" if path.starts_with('/') => "/",
+        "" => ".",
+        trimmed => trimmed,
+    }
+}
 
-        // If the item is a dotted table, grab the decor of the first item of the table
-        // instead.
-        let decor = if let Some(first_in_dotted) = v.as_table().filter(|t| t.is_dotted()).and_then(|t| t.key(t.iter().next()?.0)) {
-            first_in_dotted.leaf_decor()
-        } else {
-            k.leaf_decor()
-        };
+/// Keeps the first of every string of the array, returns how many were removed.
+fn dedup_array(arr: &mut Array) -> usize {
+    let mut seen = HashSet::new();
+    retain_values(arr, |v| v.as_str().is_none_or(|s| seen.insert(s.to_owned())))
+}
 
-        let blank_lines = decor
-            .prefix()
-            .and_then(RawString::as_str)
-            .unwrap_or("")
-            .lines()
-            .filter(|l| !l.starts_with('#'))
-            .count();
+/// Keeps the values `keep` returns true for, returns how many were removed.
+/// When the first value goes the next one takes its prefix, `[ "b"]` would
+/// be left otherwise.
+pub(crate) fn retain_values(arr: &mut Array, mut keep: impl FnMut(&Value) -> bool) -> usize {
+    let before = arr.len();
+    let prefix = arr.get(0).and_then(|v| v.decor().prefix()).cloned();
+    let mut first_kept = None;
+    arr.retain(|v| {
+        let kept = keep(v);
+        first_kept.get_or_insert(kept);
+        kept
+    });
+    if first_kept == Some(false)
+        && let (Some(prefix), Some(first)) = (prefix, arr.get_mut(0))
+    {
+        first.decor_mut().set_prefix(prefix);
+    }
+    before - arr.len()
+}
 
-        if blank_lines > 0 {
-            let decor = k.leaf_decor().clone();
-            let k = k.clone().with_leaf_decor(Decor::default());
+/// Sorts the list of enabled features/dependencies of every feature,
+/// `full = ["b", "a"]` becomes `full = ["a", "b"]`.
+fn sort_feature_arrays(table: &mut Table, cmp: Comparator) {
+    for (_, item) in table.iter_mut() {
+        if let Item::Value(Value::Array(arr)) = item {
+            sort_array(arr, cmp);
+        }
+    }
+}
 
-            groups.entry(idx).or_insert_with(|| vec![(k, v)]);
-            group_decor.insert(idx, decor);
-            curr = idx;
-        } else {
-            groups.entry(curr).or_default().push((k.clone(), v));
+/// Sorts every table one level below `table`, the tools of `[lints]` or the
+/// registries of `[patch]`.
+fn sort_sub_tables(table: &mut Table, group: bool, cmp: Comparator) {
+    for (_, item) in table.iter_mut() {
+        if let Item::Table(inner) = item {
+            sort_table(inner, group, cmp);
         }
     }
+}
 
-    for (idx, mut group) in groups {
-        group.sort_by(|a, b| a.0.cmp(&b.0));
-        let group_decor = group_decor.remove(&idx);
+/// Sorts `[[heading]]` tables by their `key`, `name` for cargo targets, tables
+/// without the key come last.
+///
+/// The tables take over the positions the array had in the document so
+/// the `[[heading]]`s are still written out in the same place.
+fn sort_array_of_tables(arr: &mut ArrayOfTables, key: &str, cmp: Comparator) {
+    let mut positions = arr.iter().map(Table::position).collect::<Vec<_>>();
+    positions.sort();
 
-        for (idx, (mut k, v)) in group.into_iter().enumerate() {
-            if idx == 0
-                && let Some(group_decor) = group_decor.clone()
-            {
-                k = k.with_leaf_decor(group_decor);
-            }
+    let mut tables = arr.iter().cloned().collect::<Vec<_>>();
+    tables.sort_by(|a, b| {
+        let (a, b) = (a.get(key).and_then(Item::as_str), b.get(key).and_then(Item::as_str));
+        match (a, b) {
+            (Some(a), Some(b)) => cmp.cmp(a, b),
+            _ => a.is_none().cmp(&b.is_none()),
+        }
+    });
 
-            table.insert_formatted(&k, v.clone());
+    arr.clear();
+    for (mut table, position) in tables.into_iter().zip(positions) {
+        if let Some(position) = position {
+            table.set_position(position);
         }
+        arr.push(table);
     }
 }
 
-fn sort_lexicographical(first_table: Option<usize>, heading_order: &BTreeMap<(usize, String), Vec<Heading>>, toml: &mut DocumentMut) {
-    // Since the root table is always index 0 we add one
-    let first_table_idx = first_table.unwrap_or_default() + 1;
-    for (idx, heading) in heading_order.iter().flat_map(|(_, segs)| segs).enumerate() {
-        if let Heading::Complete(segs) = heading {
-            let mut nested = 0;
-            let mut table = Some(toml.as_table_mut());
-            for seg in segs {
-                nested += 1;
-                table = table.and_then(|t| t[seg].as_table_mut());
+/// Sorts the `features` of a dependency, both `dep = { features = [..] }` and
+/// `[dependencies.dep] features = [..]`, and with an `order` the keys of an
+/// inline dependency table.
+fn sort_dependency_spec(item: &mut Item, order: Option<&KeyOrder>, cmp: Comparator) {
+    match item {
+        Item::Value(Value::InlineTable(spec)) => {
+            if let Some(Value::Array(features)) = spec.get_mut("features") {
+                sort_array(features, cmp);
             }
-            // Do not reorder the unsegmented tables
-            if nested > 1
-                && let Some(table) = table
-            {
-                table.set_position((first_table_idx + idx) as isize);
+            if let Some(order) = order {
+                sort_inline_table_by_key_order(spec, order, cmp);
+            }
+        }
+        Item::Table(spec) => {
+            if let Some(Item::Value(Value::Array(features))) = spec.get_mut("features") {
+                sort_array(features, cmp);
             }
         }
+        _ => {}
     }
 }
 
-fn sort_by_ordering(ordering: &[String], heading_order: &BTreeMap<(usize, String), Vec<Heading>>, toml: &mut DocumentMut) {
-    let mut idx = 0;
-    for heading in ordering {
-        let mut matches: Vec<(&(usize, String), &Vec<Heading>)> = heading_order
-            .iter()
-            .filter(|((_s, key), headings)| {
-                key == heading
-                    || headings.iter().any(|h| {
-                        if let Heading::Complete(segs) = h {
-                            segs.iter().any(|seg| seg == heading)
-                        } else {
-                            false
-                        }
-                    })
-            })
-            .collect();
+fn sort_table_by_key_order(table: &mut Table, order: &KeyOrder, cmp: Comparator) {
+    if is_kept(table) {
+        return;
+    }
+    match order {
+        KeyOrder::Alphabetical => table.sort_values_by(|a, _, b, _| cmp.cmp(a.get(), b.get())),
+        KeyOrder::Listed(keys) => table.sort_values_by(|a, _, b, _| cmp_listed(keys, a.get(), b.get(), cmp)),
+    }
+}
 
-        /// Use `heading` as the split point, and divide `segs` into two parts:
-        /// - Traverse left (backward) to the start (including `heading`)
-        /// - Traverse right (forward) to the end (after `heading`)
-        ///
-        /// Then join both parts into a dot-separated string, for example:
-        /// `[target.'cfg(windows)'.dependencies.windows-sys]` will be
-        /// `[dependencies.'cfg(windows)'.target.windows-sys]`
-        fn join_segs_around_heading(segs: &[String], heading: &str) -> Option<String> {
-            if let Some(pos) = segs.iter().position(|seg| seg == heading) {
-                let mut left: Vec<_> = segs[..=pos].iter().rev().cloned().collect();
-                let right: Vec<_> = if pos + 1 < segs.len() {
-                    segs[pos + 1..].to_vec()
+/// Sorts the keys of an inline table, the spacing of each position is kept so
+/// `{ version = "1", path = ".." }` does not become `{path = "..", version = "1" }`.
+fn sort_inline_table_by_key_order(table: &mut InlineTable, order: &KeyOrder, cmp: Comparator) {
+    let decors = table
+        .iter()
+        .filter_map(|(k, v)| Some((table.key(k)?.leaf_decor().clone(), v.decor().clone())))
+        .collect::<Vec<_>>();
+
+    match order {
+        KeyOrder::Alphabetical => table.sort_values_by(|a, _, b, _| cmp.cmp(a.get(), b.get())),
+        KeyOrder::Listed(keys) => table.sort_values_by(|a, _, b, _| cmp_listed(keys, a.get(), b.get(), cmp)),
+    }
+
+    let keys = table.iter().map(|(k, _)| k.to_owned()).collect::<Vec<_>>();
+    for (key, (key_decor, value_decor)) in keys.iter().zip(decors) {
+        if let Some(mut k) = table.key_mut(key) {
+            *k.leaf_decor_mut() = key_decor;
+        }
+        if let Some(v) = table.get_mut(key) {
+            *v.decor_mut() = value_decor;
+        }
+    }
+}
+
+/// Compares keys by their position in `keys`, the keys not listed come last
+/// in the order of `cmp`.
+fn cmp_listed(keys: &[String], a: &str, b: &str, cmp: Comparator) -> Ordering {
+    let pos = |k: &str| keys.iter().position(|key| key == k).unwrap_or(keys.len());
+    pos(a).cmp(&pos(b)).then_with(|| cmp.cmp(a, b))
+}
+
+/// Rewrites every inline dependency, `dep = { .. }`, whose line is longer than
+/// `max` into a `[dependencies.dep]` table, sorting places it among the rest.
+fn expand_inline_dependencies(table: &mut Table, max: usize) {
+    let position = table.position();
+    for (mut key, item) in table.iter_mut() {
+        let Item::Value(Value::InlineTable(spec)) = item else {
+            continue;
+        };
+        let line = format!("{} = {}", key.display_repr(), spec.to_string().trim());
+        if line.len() <= max {
+            continue;
+        }
+        let mut spec = spec.clone();
+        spec.fmt();
+        let mut expanded = spec.into_table();
+        expanded.decor_mut().set_prefix("\n");
+        // Right after the parent table
+        if let Some(position) = position {
+            expanded.set_position(position);
+        }
+        key.leaf_decor_mut().clear();
+        *item = Item::Table(expanded);
+    }
+}
+
+/// Gives the tables [`expand_inline_dependencies`] made under a dotted key,
+/// like `dependencies.dep = { .. }` in `[target.unix]`, the position of the
+/// nearest table with a header, and hides that header once it has no keys of
+/// its own left. Returns whether a table was placed in `table`.
+fn place_expanded_tables(table: &mut Table, position: Option<isize>) -> bool {
+    let mut placed = false;
+    for (_, item) in table.iter_mut() {
+        let Item::Table(child) = item else {
+            continue;
+        };
+        let headed = !child.is_dotted() && !child.is_implicit();
+        if headed
+            && child.position().is_none()
+            && let Some(position) = position
+        {
+            child.set_position(position);
+            placed = true;
+        }
+        let inner = place_expanded_tables(child, if headed { child.position() } else { position });
+        if headed && inner && !has_values(child) {
+            child.set_implicit(true);
+        }
+        placed |= child.is_dotted() && inner;
+    }
+    placed
+}
+
+/// Whether `table` has a key with a value, directly or under a dotted key.
+fn has_values(table: &Table) -> bool {
+    table.iter().any(|(_, item)| match item {
+        Item::Value(_) => true,
+        Item::Table(table) => table.is_dotted() && has_values(table),
+        _ => false,
+    })
+}
+
+/// Rewrites every `[dependencies.dep]` table that fits on a line of `max` into
+/// an inline dependency, `dep = { .. }`. Tables with comments or tables of
+/// their own are left alone so nothing is lost.
+fn fold_dependency_tables(table: &mut Table, max: usize) {
+    for (mut key, item) in table.iter_mut() {
+        let Item::Table(spec) = item else {
+            continue;
+        };
+        let has_comments = |prefix: Option<&RawString>| prefix.and_then(RawString::as_str).is_some_and(|p| p.contains('#'));
+        if is_kept(spec)
+            || has_comments(spec.decor().prefix())
+            || spec.iter().any(|(k, v)| {
+                !v.is_value()
+                    || has_comments(spec.key(k).and_then(|k| k.leaf_decor().prefix()))
+                    || v.as_value()
+                        .is_some_and(|v| v.decor().suffix().and_then(RawString::as_str).is_some_and(|s| s.contains('#')))
+            })
+        {
+            continue;
+        }
+        let mut inline = spec.clone().into_inline_table();
+        inline.fmt();
+        let line = format!("{} = {}", key.display_repr(), inline);
+        if line.len() > max {
+            continue;
+        }
+        key.leaf_decor_mut().clear();
+        *item = Item::Value(Value::InlineTable(inline));
+    }
+}
+
+/// Calls `f` with every table of dependency specifications, `[dependencies]`,
+/// `[target.'cfg(unix)'.dev-dependencies]`, `[workspace.dependencies]`,
+/// `[patch.crates-io]` and so on, along with the name of the table for the
+/// `skip` config, `target` and `patch` cover all their tables.
+pub(crate) fn for_each_dependency_table(toml: &mut DocumentMut, mut f: impl FnMut(&str, &mut Table)) {
+    fn kinds_of(parent: &str, table: &mut Table, f: &mut impl FnMut(&str, &mut Table)) {
+        for (key, item) in table.iter_mut() {
+            if DEPENDENCY_KINDS.contains(&key.get())
+                && let Item::Table(deps) = item
+            {
+                let name = if parent == TARGET {
+                    TARGET.to_owned()
                 } else {
-                    Vec::new()
+                    format!("{parent}.{}", key.get())
                 };
-                left.extend(right);
-                return Some(left.join("."));
+                f(&name, deps);
             }
-            None
         }
+    }
+
+    for (key, item) in toml.as_table_mut().iter_mut() {
+        let Item::Table(table) = item else {
+            continue;
+        };
+        match key.get() {
+            TARGET => {
+                for (_, cfg) in table.iter_mut() {
+                    if let Item::Table(cfg) = cfg {
+                        kinds_of(TARGET, cfg, &mut f);
+                    }
+                }
+            }
+            WORKSPACE => kinds_of(WORKSPACE, table, &mut f),
+            PATCH => {
+                for (_, registry) in table.iter_mut() {
+                    if let Item::Table(registry) = registry {
+                        f(PATCH, registry);
+                    }
+                }
+            }
+            kind if DEPENDENCY_KINDS.contains(&kind) => f(kind, table),
+            _ => {}
+        }
+    }
+}
+
+/// Moves `key` to the top of the table leaving the order of the rest untouched,
+/// with `grouped` to the top of the group of keys it is in. The blank line
+/// and comments above the group stay on top of it.
+fn pin_first(table: &mut Table, key: &str, grouped: bool) {
+    let keys = table.iter().map(|(k, _)| k.to_owned()).collect::<Vec<_>>();
+    let Some(pos) = keys.iter().position(|k| k == key) else {
+        return;
+    };
+    let starts_group = |k: &str| {
+        let prefix = table.key(k).and_then(|k| k.leaf_decor().prefix()?.as_str()).unwrap_or_default();
+        prefix.lines().any(|l| l.trim().is_empty())
+    };
+    let start = if grouped {
+        (0..=pos).rev().find(|&i| i == 0 || starts_group(&keys[i])).unwrap_or(0)
+    } else {
+        0
+    };
+    if start == pos {
+        return;
+    }
+    if grouped && let (Some(first), Some(pinned)) = (table.key(&keys[start]), table.key(key)) {
+        let (first, pinned) = (first.leaf_decor().clone(), pinned.leaf_decor().clone());
+        if let Some(mut k) = table.key_mut(&keys[start]) {
+            *k.leaf_decor_mut() = pinned;
+        }
+        if let Some(mut k) = table.key_mut(key) {
+            *k.leaf_decor_mut() = first;
+        }
+    }
+    let rank = |k: &str| {
+        if k == key {
+            (start, false)
+        } else {
+            (keys.iter().position(|other| other == k).unwrap_or(keys.len()), true)
+        }
+    };
+    table.sort_values_by(|a, _, b, _| rank(a.get()).cmp(&rank(b.get())));
+}
+
+/// Removes the blank lines above the keys of a table, the groups they made are
+/// merged into one. Comments stay where they are.
+fn remove_blank_lines(table: &mut Table) {
+    let keys = table.iter().map(|(k, _)| k.to_owned()).collect::<Vec<_>>();
+    for key in keys {
+        if let Some(mut k) = table.key_mut(&key) {
+            let decor = k.leaf_decor_mut();
+            let prefix = decor.prefix().and_then(RawString::as_str).unwrap_or_default();
+            if prefix.lines().any(|l| l.trim().is_empty()) {
+                let comments = comments_of(prefix);
+                decor.set_prefix(comments);
+            }
+        }
+    }
+}
+
+/// Returns the comment lines of a key's prefix without the blank lines.
+fn comments_of(prefix: &str) -> String {
+    prefix.lines().filter(|l| !l.trim().is_empty()).map(|l| format!("{l}\n")).collect()
+}
+
+/// Moves the `keys` to the top of the table in the order they are listed,
+/// after the keys pinned with `# sort: first`, the rest keep their order.
+fn hoist(table: &mut Table, keys: &[String]) {
+    let pos = |k: &Key| keys.iter().position(|key| key == k.get()).unwrap_or(keys.len());
+    table.sort_values_by(|k1, v1, k2, v2| (pin_rank(k1, v1), pos(k1)).cmp(&(pin_rank(k2, v2), pos(k2))));
+}
+
+/// Returns true if the comment `# cargo-sort-fix: skip-file` is in the comments
+/// at the top of the manifest, before the first key or table.
+pub(crate) fn is_skipped_file(input: &str) -> bool {
+    input
+        .lines()
+        .map(str::trim)
+        .take_while(|line| line.is_empty() || line.starts_with('#'))
+        .filter_map(|line| line.strip_prefix('#'))
+        .any(|comment| comment.trim() == SKIP_FILE_DIRECTIVE)
+}
+
+/// Returns true if the comment `# cargo-sort-fix: keep` is right above the
+/// heading of the table, its keys are then left in the order they are written.
+fn is_kept(table: &Table) -> bool {
+    let prefix = table.decor().prefix().and_then(RawString::as_str).unwrap_or_default();
+    prefix
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix('#'))
+        .any(|comment| comment.trim() == KEEP_DIRECTIVE)
+}
+
+fn sort_table(table: &mut Table, group: bool, cmp: Comparator) {
+    if is_kept(table) {
+        return;
+    }
+    if group {
+        sort_by_group(table, cmp, true);
+    } else if table
+        .iter()
+        .any(|(k, _)| table.key(k).is_some_and(|k| is_section_start(k.leaf_decor())))
+    {
+        sort_by_group(table, cmp, false);
+    } else {
+        table.sort_values_by(|k1, v1, k2, v2| pin_rank(k1, v1).cmp(&pin_rank(k2, v2)).then_with(|| cmp_keys(cmp, k1, v1, k2, v2)));
+    }
+}
+
+/// Compares two keys of a table, a renamed dependency by its `package` with
+/// `sort_by_package` and by the key itself otherwise.
+fn cmp_keys(cmp: Comparator, k1: &Key, v1: &Item, k2: &Key, v2: &Item) -> Ordering {
+    fn name<'a>(key: &'a Key, value: &'a Item, by_package: bool) -> &'a str {
+        let package = value.as_table_like().and_then(|t| t.get("package")).and_then(Item::as_str);
+        package.filter(|_| by_package).unwrap_or(key.get())
+    }
+    cmp.cmp(name(k1, v1, cmp.by_package), name(k2, v2, cmp.by_package))
+        .then_with(|| cmp.cmp(k1.get(), k2.get()))
+}
+
+/// Where a dependency comes from, the blocks of the `by-source` rule in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Source {
+    Workspace,
+    Path,
+    Git,
+    Registry,
+}
+
+impl Source {
+    fn of(spec: &Item) -> Self {
+        let Some(spec) = spec.as_table_like() else {
+            return Self::Registry;
+        };
+        if spec.get("workspace").and_then(Item::as_bool) == Some(true) {
+            Self::Workspace
+        } else if spec.contains_key("path") {
+            Self::Path
+        } else if spec.contains_key("git") {
+            Self::Git
+        } else {
+            Self::Registry
+        }
+    }
+}
+
+/// Sorts a dependency table into one block per `Source`, each sorted on its
+/// own and separated by a blank line, the blank lines as written are dropped.
+fn sort_by_source(table: &mut Table, headers: bool, cmp: Comparator) {
+    let names = ["workspace", "path", "git", "registry"];
+    sort_into_blocks(table, cmp, headers.then_some(&names[..]), |_, v| Source::of(v) as usize);
+}
+
+/// Sorts a dependency table into the `[groups]` of the config, in the order
+/// they are listed, the dependencies no group matches come last.
+fn sort_by_groups(table: &mut Table, groups: &[DependencyGroup], headers: bool, cmp: Comparator) {
+    let names = groups.iter().map(|g| g.name.as_str()).chain(["other"]).collect::<Vec<_>>();
+    sort_into_blocks(table, cmp, headers.then_some(&names[..]), |k, _| {
+        groups.iter().position(|group| group.matches(k.get())).unwrap_or(groups.len())
+    });
+}
+
+/// Sorts the keys of a table into the blocks `block` puts them in, lowest
+/// first, with a blank line between two blocks. Each block is sorted on its
+/// own and the blank lines as written are dropped, comments stay.
+///
+/// With `headers` each block starts with a `# -- name --` comment of its
+/// name, the headers already there are removed first so they follow the keys.
+fn sort_into_blocks(table: &mut Table, cmp: Comparator, headers: Option<&[&str]>, block: impl Fn(&Key, &Item) -> usize) {
+    let table_clone = table.clone();
+    table.clear();
+
+    let mut entries = table_clone
+        .iter()
+        .filter_map(|(k, _)| table_clone.get_key_value(k))
+        .map(|(k, v)| (block(k, v), pin_rank(k, v), k, v))
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)).then_with(|| cmp_keys(cmp, a.2, a.3, b.2, b.3)));
+
+    let mut prev = None;
+    for (block, _, k, v) in entries {
+        let mut prefix = comments_of(k.leaf_decor().prefix().and_then(RawString::as_str).unwrap_or_default());
+        if let Some(names) = headers {
+            prefix = prefix.lines().filter(|l| !is_group_header(l)).map(|l| format!("{l}\n")).collect();
+            if prev != Some(block) {
+                let (open, close) = GROUP_HEADER;
+                prefix.insert_str(0, &format!("# {open}{}{close}\n", names[block]));
+            }
+        }
+        if prev.is_some_and(|prev| prev != block) {
+            prefix.insert(0, '\n');
+        }
+        prev = Some(block);
+
+        let mut decor = k.leaf_decor().clone();
+        decor.set_prefix(prefix);
+        table.insert_formatted(&k.clone().with_leaf_decor(decor), v.clone());
+    }
+}
+
+/// Moves every key into the first group of its section that has a key of the
+/// same family, `tokio-util` joins the group of `tokio`.
+fn join_families(groups: &mut BTreeMap<usize, Vec<(u8, Key, &Item)>>, sections: &BTreeMap<usize, usize>) {
+    let family = |k: &Key| k.get().split(['-', '_']).next().unwrap_or_default().to_owned();
+
+    let mut homes = BTreeMap::new();
+    let mut moves = vec![];
+    for (&idx, group) in groups.iter() {
+        for (pos, (_, k, _)) in group.iter().enumerate() {
+            let home = *homes.entry((sections[&idx], family(k))).or_insert(idx);
+            if home != idx {
+                moves.push((idx, pos, home));
+            }
+        }
+    }
+    // Last first so the positions of the keys still to move stay valid
+    for (idx, pos, home) in moves.into_iter().rev() {
+        let entry = groups.get_mut(&idx).unwrap().remove(pos);
+        groups.get_mut(&home).unwrap().push(entry);
+    }
+}
+
+/// Returns true if the line is a `# -- name --` comment written by `group_headers`.
+fn is_group_header(line: &str) -> bool {
+    let (open, close) = GROUP_HEADER;
+    line.trim()
+        .strip_prefix('#')
+        .map(str::trim_start)
+        .is_some_and(|c| c.starts_with(open) && c.trim_end().ends_with(close) && !c.starts_with(SECTION_MARKER))
+}
+
+/// Returns true if a `# --- name ---` section marker is in the comments above a key.
+fn is_section_start(decor: &Decor) -> bool {
+    decor
+        .prefix()
+        .and_then(RawString::as_str)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix('#'))
+        .any(|comment| comment.trim().starts_with(SECTION_MARKER))
+}
+
+/// Where a key is pinned by a `# sort: first` or `# sort: last` comment above
+/// it or at the end of its line, `0` is first, `1` unpinned and `2` last.
+fn pin_rank(key: &Key, value: &Item) -> u8 {
+    let above = key.leaf_decor().prefix().and_then(RawString::as_str).unwrap_or_default();
+    let after = value
+        .as_value()
+        .and_then(|v| v.decor().suffix())
+        .and_then(RawString::as_str)
+        .unwrap_or_default();
+    let directive = above
+        .lines()
+        .chain(after.lines())
+        .filter_map(|line| line.trim().strip_prefix('#'))
+        .find_map(|comment| comment.trim().strip_prefix(PIN_DIRECTIVE));
+    match directive.map(str::trim) {
+        Some("first") => 0,
+        Some("last") => 2,
+        _ => 1,
+    }
+}
+
+fn sort_nested_table(table: &mut Table, target_tables: &TargetTablePaths, cmp: Comparator) {
+    // The `table` name must be `target`
+    for paths in target_tables.values() {
+        for path in paths {
+            if path.len() > 1 {
+                sort_table_by_path(table, &path[1..], cmp);
+            }
+        }
+    }
+}
+
+fn sort_table_by_path(table: &mut Table, path: &[String], cmp: Comparator) {
+    let Some(first) = path.first() else {
+        if !is_kept(table) {
+            table.sort_values_by(|k1, v1, k2, v2| cmp_keys(cmp, k1, v1, k2, v2));
+        }
+        return;
+    };
+    if let Some(Item::Table(inner_table)) = table.get_mut(first) {
+        sort_table_by_path(inner_table, &path[1..], cmp);
+    }
+}
+
+fn gather_headings(table: &Table, keys: &mut Vec<Heading>, depth: usize) {
+    if table.is_empty() && !table.is_implicit() {
+        let next = match keys.pop().unwrap() {
+            Heading::Next(segs) => Heading::Complete(segs),
+            comp => comp,
+        };
+        keys.push(next);
+    }
+    for (head, item) in table.iter() {
+        match item {
+            Item::Value(_) => {
+                if keys.last().is_some_and(|h| matches!(h, Heading::Complete(_))) {
+                    continue;
+                }
+                let next = match keys.pop().unwrap() {
+                    Heading::Next(segs) => Heading::Complete(segs),
+                    _complete => unreachable!("the above if check prevents this"),
+                };
+                keys.push(next);
+                continue;
+            }
+            Item::Table(table) => {
+                let next = match keys.pop().unwrap() {
+                    Heading::Next(mut segs) => {
+                        segs.push(head.into());
+                        Heading::Next(segs)
+                    }
+                    // This happens when
+                    //
+                    // [heading]       // transitioning from here to
+                    // [heading.segs]  // here
+                    Heading::Complete(segs) => {
+                        let take = depth.max(1);
+                        let mut next = segs[..take].to_vec();
+                        next.push(head.into());
+                        keys.push(Heading::Complete(segs));
+                        Heading::Next(next)
+                    }
+                };
+                keys.push(next);
+                gather_headings(table, keys, depth + 1);
+            }
+            // Only the top level `[[heading]]`s are sorted, see `sort_array_of_tables`
+            Item::ArrayOfTables(_arr) => {}
+            Item::None => unreachable!("an empty table will not be sorted"),
+        }
+    }
+}
+
+/// Sorts the keys within each group, a group starts at a section marker and,
+/// with `by_blank_lines`, at a blank line.
+fn sort_by_group(table: &mut Table, cmp: Comparator, by_blank_lines: bool) {
+    let table_clone = table.clone();
+    table.clear();
+
+    let mut groups = BTreeMap::new();
+    let mut group_decor = BTreeMap::default();
+    // The section of every group, keys never move to another section
+    let mut sections = BTreeMap::new();
+
+    let mut curr = 0;
+    let mut section = 0;
+    for (idx, (k, _)) in table_clone.iter().enumerate() {
+        let (k, v) = table_clone.get_key_value(k).unwrap();
+
+        // If the item is a dotted table, grab the decor of the first item of the table
+        // instead.
+        let decor = if let Some(first_in_dotted) = v.as_table().filter(|t| t.is_dotted()).and_then(|t| t.key(t.iter().next()?.0)) {
+            first_in_dotted.leaf_decor()
+        } else {
+            k.leaf_decor()
+        };
+
+        let blank_lines = decor
+            .prefix()
+            .and_then(RawString::as_str)
+            .unwrap_or("")
+            .lines()
+            .filter(|l| !l.starts_with('#'))
+            .count();
+
+        if is_section_start(decor) {
+            section += 1;
+        }
+        if (by_blank_lines && blank_lines > 0) || is_section_start(decor) {
+            sections.insert(idx, section);
+            let decor = k.leaf_decor().clone();
+            let rank = pin_rank(k, v);
+            let k = k.clone().with_leaf_decor(Decor::default());
+
+            groups.entry(idx).or_insert_with(|| vec![(rank, k, v)]);
+            group_decor.insert(idx, decor);
+            curr = idx;
+        } else {
+            sections.entry(curr).or_insert(section);
+            groups.entry(curr).or_default().push((pin_rank(k, v), k.clone(), v));
+        }
+    }
+
+    if cmp.prefix_affinity {
+        join_families(&mut groups, &sections);
+    }
+
+    for (idx, mut group) in groups {
+        group.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| cmp_keys(cmp, &a.1, a.2, &b.1, b.2)));
+        let group_decor = group_decor.remove(&idx);
+
+        for (idx, (_, mut k, v)) in group.into_iter().enumerate() {
+            if idx == 0
+                && let Some(group_decor) = group_decor.clone()
+            {
+                k = k.with_leaf_decor(group_decor);
+            }
+
+            table.insert_formatted(&k, v.clone());
+        }
+    }
+}
+
+fn sort_lexicographical(first_table: Option<usize>, heading_order: &BTreeMap<(usize, String), Vec<Heading>>, toml: &mut DocumentMut) {
+    // Since the root table is always index 0 we add one
+    let first_table_idx = first_table.unwrap_or_default() + 1;
+    for (idx, heading) in heading_order.values().flatten().enumerate() {
+        if let Heading::Complete(segs) = heading {
+            let mut nested = 0;
+            let mut table = Some(toml.as_table_mut());
+            for seg in segs {
+                nested += 1;
+                table = table.and_then(|t| t[seg].as_table_mut());
+            }
+            // Do not reorder the unsegmented tables
+            if nested > 1
+                && let Some(table) = table
+            {
+                table.set_position((first_table_idx + idx) as isize);
+            }
+        }
+    }
+}
+
+fn sort_by_ordering(ordering: &[String], heading_order: &BTreeMap<(usize, String), Vec<Heading>>, toml: &mut DocumentMut) {
+    let mut idx = 0;
+    let mut placed = HashSet::new();
+    for heading in ordering {
+        let mut matches: Vec<(&(usize, String), &Vec<Heading>)> = heading_order
+            .iter()
+            .filter(|((_s, key), headings)| {
+                key == heading
+                    || headings.iter().any(|h| {
+                        if let Heading::Complete(segs) = h {
+                            segs.iter().any(|seg| seg == heading)
+                        } else {
+                            false
+                        }
+                    })
+            })
+            .collect();
+
+        /// Use `heading` as the split point, and divide `segs` into two parts:
+        /// - Traverse left (backward) to the start (including `heading`)
+        /// - Traverse right (forward) to the end (after `heading`)
+        ///
+        /// Then join both parts into a dot-separated string, for example:
+        /// `[target.'cfg(windows)'.dependencies.windows-sys]` will be
+        /// `[dependencies.'cfg(windows)'.target.windows-sys]`
+        fn join_segs_around_heading(segs: &[String], heading: &str) -> Option<String> {
+            if let Some(pos) = segs.iter().position(|seg| seg == heading) {
+                let mut left: Vec<_> = segs[..=pos].iter().rev().cloned().collect();
+                let right: Vec<_> = if pos + 1 < segs.len() {
+                    segs[pos + 1..].to_vec()
+                } else {
+                    Vec::new()
+                };
+                left.extend(right);
+                return Some(left.join("."));
+            }
+            None
+        }
+
+        fn extract_heading_segments(headings: &[Heading], heading: &str) -> String {
+            headings
+                .iter()
+                .filter_map(|h| {
+                    if let Heading::Complete(segs) = h {
+                        return join_segs_around_heading(segs, heading);
+                    }
+                    None
+                })
+                .max_by_key(|s| s.len())
+                .unwrap_or_default()
+        }
+
+        matches.sort_by(|((_, a_key), a_headings), ((_, b_key), b_headings)| {
+            let a1_longest = extract_heading_segments(a_headings, heading);
+            let b1_longest = extract_heading_segments(b_headings, heading);
+            let ord = a1_longest.cmp(&b1_longest);
+            if ord == Ordering::Equal { a_key.cmp(b_key) } else { ord }
+        });
+
+        if is_table_pattern(heading) {
+            let pattern = heading.split('.').map(glob::Pattern::new).collect::<Result<Vec<_>, _>>();
+            let mut found = vec![];
+            if let Ok(pattern) = pattern {
+                tables_matching(toml.as_table(), &pattern, &mut vec![], &mut found);
+            }
+            found.sort();
+            for path in found {
+                let mut table = Some(toml.as_table_mut());
+                for seg in &path {
+                    table = table.and_then(|t| t[seg].as_table_mut());
+                }
+                if let Some(table) = table {
+                    let mut path = path.clone();
+                    place(table, &path, &mut idx, &mut placed);
+                    walk_tables_set_position(table, &mut path, &mut idx, &mut placed);
+                }
+            }
+        } else if !matches.is_empty() {
+            for &((_, key), to_sort_headings) in &matches {
+                let mut to_sort_headings = to_sort_headings
+                    .iter()
+                    .filter(|h| {
+                        if let Heading::Complete(segs) = h {
+                            if key == TARGET {
+                                // Get rid of the items that do not contain the heading
+                                return segs.iter().any(|seg| seg == heading);
+                            } else {
+                                return true;
+                            }
+                        }
+                        false
+                    })
+                    .collect::<Vec<_>>();
+                to_sort_headings.sort_by_key(|h| {
+                    if let Heading::Complete(segs) = h {
+                        if key == TARGET {
+                            join_segs_around_heading(segs, heading).unwrap_or_default()
+                        } else {
+                            segs.to_vec().join(".")
+                        }
+                    } else {
+                        String::new()
+                    }
+                });
+                for h in to_sort_headings {
+                    if let Heading::Complete(segs) = h {
+                        let mut table = Some(toml.as_table_mut());
+                        for seg in segs {
+                            table = table.and_then(|t| t[seg].as_table_mut());
+                        }
+                        // Do not reorder the unsegmented tables
+                        if let Some(table) = table {
+                            place(table, segs, &mut idx, &mut placed);
+                        }
+                    }
+                }
+            }
+        } else if let Some(tab) = toml.as_table_mut()[heading].as_table_mut() {
+            let mut path = vec![heading.clone()];
+            place(tab, &path, &mut idx, &mut placed);
+            walk_tables_set_position(tab, &mut path, &mut idx, &mut placed);
+        } else if let Some(arrtab) = toml.as_table_mut()[heading].as_array_of_tables_mut() {
+            for (i, tab) in arrtab.iter_mut().enumerate() {
+                let mut path = vec![format!("{heading}[{i}]")];
+                place(tab, &path, &mut idx, &mut placed);
+                walk_tables_set_position(tab, &mut path, &mut idx, &mut placed);
+            }
+        }
+    }
+}
+
+/// Returns true for an entry of the ordering like `profile.*` or
+/// `target.*.dependencies` that names tables by their dotted path, each
+/// segment can be a glob.
+fn is_table_pattern(heading: &str) -> bool {
+    heading.contains(['.', '*', '?', '['])
+}
+
+/// Collects the path of every table whose path matches `pattern` segment by segment.
+fn tables_matching(table: &Table, pattern: &[glob::Pattern], path: &mut Vec<String>, found: &mut Vec<Vec<String>>) {
+    let Some((first, rest)) = pattern.split_first() else {
+        return;
+    };
+    for (key, item) in table.iter() {
+        if let Item::Table(inner) = item
+            && first.matches(key)
+        {
+            path.push(key.to_owned());
+            if rest.is_empty() {
+                found.push(path.clone());
+            } else {
+                tables_matching(inner, rest, path, found);
+            }
+            path.pop();
+        }
+    }
+}
+
+/// Gives the table at `path` the next position, unless an earlier entry of the
+/// ordering already placed it, the first entry that matches a table wins.
+fn place(table: &mut Table, path: &[String], idx: &mut isize, placed: &mut HashSet<String>) {
+    if placed.insert(path.join(".")) {
+        table.set_position(*idx);
+        *idx += 1;
+    }
+}
+
+fn walk_tables_set_position(table: &mut Table, path: &mut Vec<String>, idx: &mut isize, placed: &mut HashSet<String>) {
+    for (key, item) in table.iter_mut() {
+        match item {
+            Item::Table(tab) => {
+                path.push(key.get().to_owned());
+                place(tab, path, idx, placed);
+                walk_tables_set_position(tab, path, idx, placed);
+                path.pop();
+            }
+            Item::ArrayOfTables(arr) => {
+                for (i, tab) in arr.iter_mut().enumerate() {
+                    path.push(format!("{}[{i}]", key.get()));
+                    place(tab, path, idx, placed);
+                    walk_tables_set_position(tab, path, idx, placed);
+                    path.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Moves the tables matching `first` before and the tables matching `last`
+/// after every other table, whatever the ordering did.
+///
+/// An entry is a dotted path like `workspace.metadata` or `profile.*`, the
+/// sub-tables of a pinned table move with it and keep their order.
+fn pin_tables(toml: &mut DocumentMut, first: &[String], last: &[String]) {
+    if first.is_empty() && last.is_empty() {
+        return;
+    }
+    let mut tables = vec![];
+    table_positions(toml.as_table(), &mut vec![], &mut tables);
+    tables.sort();
+    let min = tables.first().map_or(0, |(pos, _)| *pos);
+    let max = tables.last().map_or(0, |(pos, _)| *pos);
+
+    let mut pinned = HashSet::new();
+    let mut pin = |entries: &[String]| {
+        let mut paths = vec![];
+        for entry in entries {
+            let Ok(pattern) = entry.split('.').map(glob::Pattern::new).collect::<Result<Vec<_>, _>>() else {
+                continue;
+            };
+            for (_, path) in &tables {
+                let under = path.len() >= pattern.len() && pattern.iter().zip(path).all(|(pat, seg)| pat.matches(seg));
+                if under && pinned.insert(path.clone()) {
+                    paths.push(path.clone());
+                }
+            }
+        }
+        paths
+    };
+    let first = pin(first);
+    let last = pin(last);
+
+    let mut positions = BTreeMap::new();
+    let start = min - first.len() as isize;
+    positions.extend(first.into_iter().zip(start..));
+    positions.extend(last.into_iter().zip(max + 1..));
+    set_table_positions(toml.as_table_mut(), &mut vec![], &positions);
+}
+
+/// Collects the position and path of every table with a position, the
+/// elements of an array of tables are `name[index]`.
+fn table_positions(table: &Table, path: &mut Vec<String>, tables: &mut Vec<(isize, Vec<String>)>) {
+    for (key, item) in table.iter() {
+        match item {
+            Item::Table(inner) => {
+                path.push(key.to_owned());
+                if let Some(pos) = inner.position() {
+                    tables.push((pos, path.clone()));
+                }
+                table_positions(inner, path, tables);
+                path.pop();
+            }
+            Item::ArrayOfTables(arr) => {
+                for (i, inner) in arr.iter().enumerate() {
+                    path.push(format!("{key}[{i}]"));
+                    if let Some(pos) = inner.position() {
+                        tables.push((pos, path.clone()));
+                    }
+                    table_positions(inner, path, tables);
+                    path.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn set_table_positions(table: &mut Table, path: &mut Vec<String>, positions: &BTreeMap<Vec<String>, isize>) {
+    for (key, item) in table.iter_mut() {
+        match item {
+            Item::Table(inner) => {
+                path.push(key.get().to_owned());
+                if let Some(&pos) = positions.get(path) {
+                    inner.set_position(pos);
+                }
+                set_table_positions(inner, path, positions);
+                path.pop();
+            }
+            Item::ArrayOfTables(arr) => {
+                for (i, inner) in arr.iter_mut().enumerate() {
+                    path.push(format!("{}[{i}]", key.get()));
+                    if let Some(&pos) = positions.get(path) {
+                        inner.set_position(pos);
+                    }
+                    set_table_positions(inner, path, positions);
+                    path.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::MATCHER;
+    use crate::{
+        fmt::{Comparator, Config, KeyOrder},
+        test_utils::assert_eq,
+    };
+
+    /// A config without a table ordering, tables are sorted lexicographically.
+    fn config() -> Config {
+        Config {
+            table_order: vec![],
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn parse_error() {
+        let input = "[package]\nname = \"a\"\n[dependencies\nb = \"1\"\n";
+        let e = super::sort_toml(input, MATCHER, false, &config()).unwrap_err();
+        assert_eq!(e.line(input), 3);
+        assert_eq!(e.span, Some(34..34));
+        assert_eq!(e.message, "unclosed table, expected `]`");
+        assert!(e.to_string().starts_with("Failed to parse: TOML parse error at line 3, column 14"));
+
+        let path = std::path::Path::new("crates/a/Cargo.toml");
+        let e = super::sort_manifest(path, input, MATCHER, false, &config()).unwrap_err();
+        assert_eq!(e.path.as_deref(), Some(path));
+        assert!(e.to_string().starts_with("Failed to parse crates/a/Cargo.toml: TOML parse error"));
+
+        let duplicate = "[dependencies]\na = \"1\"\na = \"2\"\n";
+        let e = super::sort_toml(duplicate, MATCHER, false, &config()).unwrap_err();
+        assert_eq!(e.line(duplicate), 3);
+        assert!(e.to_string().ends_with("line 2 and line 3"), "{e}");
+    }
+
+    #[test]
+    fn toml_edit_check() {
+        let input = fs::read_to_string("examp/workspace.toml").unwrap();
+        let expected = fs::read_to_string("examp/workspace.sorted.toml").unwrap();
+        let sorted = super::sort_toml(&input, MATCHER, false, &config()).unwrap();
+        assert_eq(expected, sorted);
+    }
+
+    #[test]
+    fn toml_combined_key_check() {
+        let input = fs::read_to_string("examp/tun.toml").unwrap();
+        let expected = fs::read_to_string("examp/tun.sorted.toml").unwrap();
+        let sorted = super::sort_toml(&input, MATCHER, false, &Config::default()).unwrap();
+
+        assert_eq(expected, sorted);
+    }
+
+    #[test]
+    fn toml_workspace_deps_edit_check() {
+        let input = fs::read_to_string("examp/workspace_deps.toml").unwrap();
+        let expected = fs::read_to_string("examp/workspace_deps.sorted.toml").unwrap();
+        let sorted = super::sort_toml(&input, MATCHER, false, &config()).unwrap();
+        assert_eq(expected, sorted);
+    }
+
+    #[test]
+    fn grouped_check() {
+        let input = fs::read_to_string("examp/ruma.toml").unwrap();
+        let expected = fs::read_to_string("examp/ruma.sorted.toml").unwrap();
+        let sorted = super::sort_toml(&input, MATCHER, true, &config()).unwrap();
+        assert_eq(expected, sorted);
+    }
+
+    #[test]
+    fn sort_correct() {
+        let input = fs::read_to_string("examp/right.toml").unwrap();
+        let sorted = super::sort_toml(&input, MATCHER, true, &config()).unwrap();
+        assert_eq(input, sorted);
+    }
+
+    #[test]
+    fn sort_comments() {
+        let input = fs::read_to_string("examp/comments.toml").unwrap();
+        let expected = fs::read_to_string("examp/comments.sorted.toml").unwrap();
+        let sorted = super::sort_toml(&input, MATCHER, true, &config()).unwrap();
+        assert_eq(expected, sorted);
+    }
+
+    #[test]
+    fn sort_tables() {
+        let input = fs::read_to_string("examp/fend.toml").unwrap();
+        let sorted = super::sort_toml(&input, MATCHER, true, &config()).unwrap();
+        assert_ne!(input, sorted.to_string());
+        // println!("{}", sorted.to_string());
+    }
+
+    #[test]
+    fn sort_devfirst() {
+        let input = fs::read_to_string("examp/reorder.toml").unwrap();
+        let sorted = super::sort_toml(&input, MATCHER, true, &config()).unwrap();
+        assert_eq(input, sorted);
+
+        let input = fs::read_to_string("examp/noreorder.toml").unwrap();
+        let sorted = super::sort_toml(&input, MATCHER, true, &config()).unwrap();
+        assert_eq(input, sorted);
+    }
+
+    #[test]
+    fn issue_104() {
+        let input = fs::read_to_string("regressions/104.toml").unwrap();
+        let sorted = super::sort_toml(&input, MATCHER, true, &config()).unwrap();
+        assert_eq(input, sorted);
+    }
+
+    #[test]
+    fn reorder() {
+        let input = fs::read_to_string("examp/clippy.toml").unwrap();
+        let config = Config {
+            table_order: vec![
+                "package".to_owned(),
+                "features".to_owned(),
+                "dependencies".to_owned(),
+                "build-dependencies".to_owned(),
+                "dev-dependencies".to_owned(),
+            ],
+            ..Config::default()
+        };
+        let sorted = super::sort_toml(&input, MATCHER, true, &config).unwrap();
+        assert_ne!(input, sorted.to_string());
+    }
+
+    #[test]
+    fn sort_features() {
+        let input = r#"
+[package]
+name = "foo"
+
+[features]
+std = ["alloc", "serde?/std"]
+alloc = []
+default = ["std"]
+"#;
+        let expected = r#"
+[package]
+name = "foo"
+
+[features]
+alloc = []
+default = ["std"]
+std = ["alloc", "serde?/std"]
+"#;
+        let sorted = super::sort_toml(input, MATCHER, false, &config()).unwrap();
+        assert_eq(expected, sorted);
+    }
+
+    #[test]
+    fn sort_feature_arrays() {
+        let input = r#"
+[features]
+full = ["std", "dep:serde", "alloc"]
+std = [
+    "serde?/std",
+    "alloc",
+]
+"#;
+        let expected = r#"
+[features]
+full = ["alloc", "dep:serde", "std"]
+std = [
+    "alloc",
+    "serde?/std",
+]
+"#;
+        let sorted = super::sort_toml(input, MATCHER, false, &config()).unwrap();
+        assert_eq(expected, sorted);
+    }
+
+    #[test]
+    fn dedup_arrays() {
+        let input = r#"
+[workspace]
+members = ["b", "a", "b"]
+exclude = [
+    "x",
+    "x",
+]
+
+[features]
+std = ["alloc", "alloc", "serde?/std"]
+alloc = []
+
+[package.metadata.deb]
+depends = ["libc", "libc"]
+"#;
+        let expected = r#"
+[workspace]
+members = ["a", "b"]
+exclude = [
+    "x",
+]
+
+[features]
+alloc = []
+std = ["alloc", "serde?/std"]
+
+[package.metadata.deb]
+depends = ["libc"]
+"#;
+        let config = Config {
+            matcher_keys: vec!["package.metadata.deb.depends".to_owned()],
+            normalize_workspace_paths: false,
+            ..config()
+        };
+        let mut sorted = super::sort_toml(input, MATCHER, false, &config).unwrap();
+        assert_eq!(super::dedup_arrays(&mut sorted, &config), 4);
+        assert_eq(expected, sorted);
+    }
+
+    #[test]
+    fn normalize_workspace_paths() {
+        let input = r#"
+[workspace]
+members = ["./crates/b/", "crates/a", "crates/b", "./", "tools/*/"]
+exclude = [
+    "./vendor//",
+    "vendor",
+    'target/',
+]
+"#;
+        let expected = r#"
+[workspace]
+members = [".", "crates/a", "crates/b", "tools/*"]
+exclude = [
+    "target",
+    "vendor",
+]
+"#;
+        let sorted = super::sort_toml(input, MATCHER, false, &config()).unwrap();
+        assert_eq(expected, sorted);
+
+        let config = Config {
+            normalize_workspace_paths: false,
+            ..config()
+        };
+        let sorted = super::sort_toml(input, MATCHER, false, &config).unwrap();
+        assert!(
+            sorted
+                .to_string()
+                .contains(r#"members = ["./", "./crates/b/", "crates/a", "crates/b", "tools/*/"]"#)
+        );
+    }
+
+    #[test]
+    fn default_feature_first() {
+        let input = r#"
+[features]
+std = []
+default = ["std"]
+alloc = []
+"#;
+        let expected = r#"
+[features]
+default = ["std"]
+alloc = []
+std = []
+"#;
+        let config = Config {
+            default_feature_first: true,
+            ..config()
+        };
+        let sorted = super::sort_toml(input, MATCHER, false, &config).unwrap();
+        assert_eq(expected, sorted);
+    }
+
+    #[test]
+    fn default_feature_first_grouped() {
+        let input = r#"
+[features]
+std = []
+default = ["std"]
+
+# optional integrations
+tokio = []
+extra = []
+"#;
+        let expected = r#"
+[features]
+default = ["std"]
+std = []
+
+# optional integrations
+extra = []
+tokio = []
+"#;
+        let config = Config {
+            default_feature_first: true,
+            ..config()
+        };
+        let sorted = super::sort_toml(input, MATCHER, true, &config).unwrap();
+        assert_eq(expected, sorted);
+
+        let input = r#"
+[features]
+std = []
+alloc = []
+
+# optional integrations
+tokio = []
+default = ["std"]
+"#;
+        let expected = r#"
+[features]
+alloc = []
+std = []
+
+# optional integrations
+default = ["std"]
+tokio = []
+"#;
+        let sorted = super::sort_toml(input, MATCHER, true, &config).unwrap();
+        assert_eq(expected, sorted);
+    }
+
+    #[test]
+    fn table_overrides() {
+        let input = r#"
+[features]
+std = []
+alloc = []
+
+[dependencies]
+b = "1"
+a = "1"
+
+[dev-dependencies]
+d = "1"
+
+c = "1"
+"#;
+        let expected = r#"
+[features]
+std = []
+alloc = []
+
+[dependencies]
+b = "1"
+a = "1"
+
+[dev-dependencies]
+d = "1"
+
+c = "1"
+"#;
+        let config = "sort_features = false\n[tables.dependencies]\nsort = false\n[tables.dev-dependencies]\ngrouped = true\n"
+            .parse::<Config>()
+            .unwrap();
+        let config = Config {
+            table_order: vec![],
+            ..config
+        };
+        let sorted = super::sort_toml(input, MATCHER, false, &config).unwrap();
+        assert_eq(expected, sorted);
+    }
+
+    #[test]
+    fn metadata_config_untouched() {
+        let input = r#"
+[package]
+version = "0.1.0"
+name = "foo"
+
+[package.metadata.cargo-sort-fix]
+table_order = ["package", "dependencies", "features"]
+package_key_order = "alphabetical"
+
+[package.metadata.cargo-sort-fix.tables.features]
+sort = false
+"#;
+        let expected = r#"
+[package]
+name = "foo"
+version = "0.1.0"
+
+[package.metadata.cargo-sort-fix]
+table_order = ["package", "dependencies", "features"]
+package_key_order = "alphabetical"
+
+[package.metadata.cargo-sort-fix.tables.features]
+sort = false
+"#;
+        let config = Config {
+            package_key_order: Some(KeyOrder::Alphabetical),
+            ..config()
+        };
+        let sorted = super::sort_toml(input, MATCHER, false, &config).unwrap();
+        assert_eq(expected, sorted);
+    }
+
+    #[test]
+    fn ordering_patterns() {
+        let input = r#"[package]
+name = "foo"
+
+[dev-dependencies]
+a = "1"
+
+[target.'cfg(unix)'.dependencies]
+libc = "1"
+
+[dependencies]
+b = "1"
+
+[profile.release]
+lto = true
+
+[profile.dev]
+opt-level = 1
+"#;
+        let expected = r#"[package]
+name = "foo"
+
+[profile.dev]
+opt-level = 1
+
+[profile.release]
+lto = true
+
+[target.'cfg(unix)'.dependencies]
+libc = "1"
+
+[dependencies]
+b = "1"
+
+[dev-dependencies]
+a = "1"
+"#;
+        let config = Config {
+            table_order: ["package", "profile.*", "target.*.dependencies", "dependencies", "dev-dependencies"]
+                .map(str::to_owned)
+                .to_vec(),
+            ..config()
+        };
+        let sorted = super::sort_toml(input, MATCHER, false, &config).unwrap();
+        assert_eq(expected, sorted);
+    }
+
+    #[test]
+    fn pin_tables() {
+        let input = r#"
+[workspace.metadata.release]
+tag = true
+
+[profile.release]
+lto = true
+
+[dependencies]
+a = "1"
+
+[package]
+name = "foo"
+
+[[bin]]
+name = "foo"
+"#;
+        let expected = r#"
+[package]
+name = "foo"
+
+[dependencies]
+a = "1"
+
+[[bin]]
+name = "foo"
+
+[profile.release]
+lto = true
+
+[workspace.metadata.release]
+tag = true
+"#;
+        let config = Config {
+            pin_first: vec!["package".to_owned()],
+            pin_last: vec!["profile".to_owned(), "workspace.metadata".to_owned()],
+            ..config()
+        };
+        let sorted = super::sort_toml(input, MATCHER, false, &config).unwrap();
+        assert_eq(expected, sorted);
+    }
+
+    #[test]
+    fn keep_directive() {
+        let input = r#"
+# Ordered by tier
+# cargo-sort-fix: keep
+[features]
+full = ["std", "alloc"]
+std = ["alloc"]
+alloc = []
+
+[dependencies]
+b = "1"
+a = "1"
+
+#cargo-sort-fix: keep
+[target.'cfg(unix)'.dependencies]
+nix = "1"
+libc = "1"
+"#;
+        let expected = r#"
+# Ordered by tier
+# cargo-sort-fix: keep
+[features]
+full = ["std", "alloc"]
+std = ["alloc"]
+alloc = []
+
+[dependencies]
+a = "1"
+b = "1"
+
+#cargo-sort-fix: keep
+[target.'cfg(unix)'.dependencies]
+nix = "1"
+libc = "1"
+"#;
+        let sorted = super::sort_toml(input, MATCHER, false, &config()).unwrap();
+        assert_eq(expected, sorted);
+    }
+
+    #[test]
+    fn pin_keys() {
+        let input = r#"
+[dependencies]
+c = "1"
+# sort: last
+a = "1"
+b = "1"
+z = "1" # sort: first
+"#;
+        let expected = r#"
+[dependencies]
+z = "1" # sort: first
+b = "1"
+c = "1"
+# sort: last
+a = "1"
+"#;
+        let sorted = super::sort_toml(input, MATCHER, false, &config()).unwrap();
+        assert_eq(expected, sorted);
+
+        let input = r#"
+[dependencies]
+c = "1"
+# sort: first
+d = "1"
+
+b = "1"
+a = "1" # sort: last
+"#;
+        let expected = r#"
+[dependencies]
+# sort: first
+d = "1"
+c = "1"
+
+b = "1"
+a = "1" # sort: last
+"#;
+        let sorted = super::sort_toml(input, MATCHER, true, &config()).unwrap();
+        assert_eq(expected, sorted);
+    }
+
+    #[test]
+    fn skip_file_directive() {
+        assert!(super::is_skipped_file(
+            "# Generated by a tool\n#cargo-sort-fix: skip-file\n\n[package]\n"
+        ));
+        assert!(!super::is_skipped_file("[package]\n# cargo-sort-fix: skip-file\n"));
+        assert!(!super::is_skipped_file("# cargo-sort-fix: keep\n[dependencies]\n"));
+    }
+
+    #[test]
+    fn skip_tables() {
+        let input = r#"
+[features]
+std = []
+alloc = []
+
+[dependencies]
+b = { version = "1", features = ["z", "a"] }
+a = "1"
+
+[patch.crates-io]
+z = { path = "z" }
+y = { path = "y" }
+
+[lints.rust]
+unsafe_code = "forbid"
+missing_docs = "warn"
+"#;
+        let expected = r#"
+[features]
+std = []
+alloc = []
+
+[dependencies]
+a = "1"
+b = { version = "1", features = ["a", "z"] }
+
+[patch.crates-io]
+z = { path = "z" }
+y = { path = "y" }
+
+[lints.rust]
+unsafe_code = "forbid"
+missing_docs = "warn"
+"#;
+        let config = Config {
+            skip: ["features", "patch", "lints"].map(str::to_owned).to_vec(),
+            ..config()
+        };
+        let sorted = super::sort_toml(input, MATCHER, false, &config).unwrap();
+        assert_eq(expected, sorted);
+    }
+
+    #[test]
+    fn config_matcher() {
+        let input = r#"
+[package.metadata.deb]
+depends = ["libssl", "libc6"]
+assets = ["b", "a"]
+
+[custom-deps]
+b = "1"
+a = "1"
+"#;
+        let expected = r#"
+[package.metadata.deb]
+depends = ["libc6", "libssl"]
+assets = ["b", "a"]
+
+[custom-deps]
+a = "1"
+b = "1"
+"#;
+        let config = "[matcher]\nheadings = [\"custom-deps\"]\nkeys = [\"package.metadata.deb.depends\"]\n"
+            .parse::<Config>()
+            .unwrap();
+        let config = Config {
+            table_order: vec![],
+            ..config
+        };
+        let sorted = super::sort_toml(input, MATCHER, false, &config).unwrap();
+        assert_eq(expected, sorted);
+    }
+
+    #[test]
+    fn sort_rules() {
+        let input = r#"
+[dependencies]
+b = "1"
+a = "1"
+
+[target.'cfg(unix)'.dependencies]
+d = "1"
+
+c = "1"
+b = "1"
+
+[package.metadata.foo]
+z = 1
+y = 1
+
+[[test]]
+name = "b"
+path = "a.rs"
+
+[[test]]
+name = "a"
+path = "b.rs"
+"#;
+        let expected = r#"
+[dependencies]
+b = "1"
+a = "1"
+
+[package.metadata.foo]
+y = 1
+z = 1
+
+[target.'cfg(unix)'.dependencies]
+d = "1"
+
+b = "1"
+c = "1"
+
+[[test]]
+name = "b"
+path = "a.rs"
+
+[[test]]
+name = "a"
+path = "b.rs"
+"#;
+        let rules = r#"
+[rules]
+dependencies = "keep"
+"target.*.dependencies" = "grouped"
+"package.metadata.*" = "alphabetical"
+test = "by-key(path)"
+"#;
+        let config = Config {
+            table_order: vec![],
+            ..rules.parse::<Config>().unwrap()
+        };
+        let sorted = super::sort_toml(input, MATCHER, false, &config).unwrap();
+        assert_eq(expected, sorted);
+    }
+
+    #[test]
+    fn natural_sort() {
+        let input = r#"
+[dependencies]
+crate10 = "1"
+crate2 = "1"
+windows-sys-0-48 = "1"
+windows-sys-0-5 = "1"
+
+[features]
+all = ["crate10", "crate2"]
+"#;
+        let expected = r#"
+[dependencies]
+crate2 = "1"
+crate10 = "1"
+windows-sys-0-5 = "1"
+windows-sys-0-48 = "1"
+
+[features]
+all = ["crate2", "crate10"]
+"#;
+        let config = Config {
+            compare: Comparator {
+                natural: true,
+                ..Comparator::default()
+            },
+            ..config()
+        };
+        let sorted = super::sort_toml(input, MATCHER, false, &config).unwrap();
+        assert_eq(expected, sorted);
+    }
 
-        fn extract_heading_segments(headings: &[Heading], heading: &str) -> String {
-            headings
-                .iter()
-                .filter_map(|h| {
-                    if let Heading::Complete(segs) = h {
-                        return join_segs_around_heading(segs, heading);
-                    }
-                    None
-                })
-                .max_by_key(|s| s.len())
-                .unwrap_or_default()
-        }
+    #[test]
+    fn fold_separators() {
+        let input = r#"
+[dependencies]
+foo-bar = "1"
+foo_baz = "1"
+foo-bar-derive = "1"
+foo_bar_macros = "1"
+foo-a = "1"
+"#;
+        let expected = r#"
+[dependencies]
+foo-a = "1"
+foo-bar = "1"
+foo-bar-derive = "1"
+foo_bar_macros = "1"
+foo_baz = "1"
+"#;
+        let config = Config {
+            compare: Comparator {
+                fold_separators: true,
+                ..Comparator::default()
+            },
+            ..config()
+        };
+        let sorted = super::sort_toml(input, MATCHER, false, &config).unwrap();
+        assert_eq(expected, sorted);
+    }
 
-        matches.sort_by(|((_, a_key), a_headings), ((_, b_key), b_headings)| {
-            let a1_longest = extract_heading_segments(a_headings, heading);
-            let b1_longest = extract_heading_segments(b_headings, heading);
-            let ord = a1_longest.cmp(&b1_longest);
-            if ord == Ordering::Equal { a_key.cmp(b_key) } else { ord }
-        });
+    #[test]
+    fn sort_by_package() {
+        let input = r#"
+[dependencies]
+tokio = "1"
+async-std = "1"
+rt = { package = "tokio-util", version = "0.7" }
 
-        if !matches.is_empty() {
-            for &((_, key), to_sort_headings) in &matches {
-                let mut to_sort_headings = to_sort_headings
-                    .iter()
-                    .filter(|h| {
-                        if let Heading::Complete(segs) = h {
-                            if key == TARGET {
-                                // Get rid of the items that do not contain the heading
-                                return segs.iter().any(|seg| seg == heading);
-                            } else {
-                                return true;
-                            }
-                        }
-                        false
-                    })
-                    .collect::<Vec<_>>();
-                to_sort_headings.sort_by_key(|h| {
-                    if let Heading::Complete(segs) = h {
-                        if key == TARGET {
-                            join_segs_around_heading(segs, heading).unwrap_or_default()
-                        } else {
-                            segs.to_vec().join(".")
-                        }
-                    } else {
-                        String::new()
-                    }
-                });
-                for h in to_sort_headings {
-                    if let Heading::Complete(segs) = h {
-                        let mut table = Some(toml.as_table_mut());
-                        for seg in segs {
-                            table = table.and_then(|t| t[seg].as_table_mut());
-                        }
-                        // Do not reorder the unsegmented tables
-                        if let Some(table) = table {
-                            table.set_position(idx);
-                            idx += 1;
-                        }
-                    }
-                }
-            }
-        } else if let Some(tab) = toml.as_table_mut()[heading].as_table_mut() {
-            tab.set_position(idx);
-            idx += 1;
-            walk_tables_set_position(tab, &mut idx);
-        } else if let Some(arrtab) = toml.as_table_mut()[heading].as_array_of_tables_mut() {
-            for tab in arrtab.iter_mut() {
-                tab.set_position(idx);
-                idx += 1;
-                walk_tables_set_position(tab, &mut idx);
-            }
-        }
+[target.'cfg(unix)'.dependencies]
+z = { package = "a", version = "1" }
+b = "1"
+"#;
+        let expected = r#"
+[dependencies]
+async-std = "1"
+tokio = "1"
+rt = { package = "tokio-util", version = "0.7" }
+
+[target.'cfg(unix)'.dependencies]
+z = { package = "a", version = "1" }
+b = "1"
+"#;
+        let config = Config {
+            compare: Comparator {
+                by_package: true,
+                ..Comparator::default()
+            },
+            ..config()
+        };
+        let sorted = super::sort_toml(input, MATCHER, false, &config).unwrap();
+        assert_eq(expected, sorted);
     }
-}
 
-fn walk_tables_set_position(table: &mut Table, idx: &mut isize) {
-    for (_, item) in table.iter_mut() {
-        match item {
-            Item::Table(tab) => {
-                tab.set_position(*idx);
-                *idx += 1;
-                walk_tables_set_position(tab, idx);
-            }
-            Item::ArrayOfTables(arr) => {
-                for tab in arr.iter_mut() {
-                    tab.set_position(*idx);
-                    *idx += 1;
-                    walk_tables_set_position(tab, idx);
-                }
-            }
-            _ => {}
-        }
+    #[test]
+    fn reverse_rules() {
+        let input = r#"
+[[bin]]
+name = "a"
+
+[[bin]]
+name = "b"
+
+[patch.crates-io]
+a = { path = "a" }
+c = { path = "c" }
+b = { path = "b" }
+"#;
+        let expected = r#"
+[[bin]]
+name = "b"
+
+[[bin]]
+name = "a"
+
+[patch.crates-io]
+c = { path = "c" }
+b = { path = "b" }
+a = { path = "a" }
+"#;
+        let rules = r#"
+[rules]
+"patch.*" = { strategy = "alphabetical", reverse = true }
+bin = { strategy = "by-key(name)", reverse = true }
+"#;
+        let config = Config {
+            table_order: vec![],
+            ..rules.parse::<Config>().unwrap()
+        };
+        let sorted = super::sort_toml(input, MATCHER, false, &config).unwrap();
+        assert_eq(expected, sorted);
     }
-}
 
-#[cfg(test)]
-mod test {
-    use std::fs;
+    #[test]
+    fn priority_crates() {
+        let input = r#"
+[dependencies]
+anyhow = "1"
+my-framework = { path = "framework" }
+serde = "1"
+core-utils = { path = "core" }
 
-    use super::MATCHER;
-    use crate::test_utils::assert_eq;
+[target.'cfg(unix)'.dev-dependencies]
+libc = "0.2"
+core-utils = { path = "core" }
+"#;
+        let expected = r#"
+[dependencies]
+core-utils = { path = "core" }
+my-framework = { path = "framework" }
+anyhow = "1"
+serde = "1"
+
+[target.'cfg(unix)'.dev-dependencies]
+core-utils = { path = "core" }
+libc = "0.2"
+"#;
+        let config = Config {
+            priority_crates: vec!["core-utils".to_owned(), "my-framework".to_owned()],
+            ..config()
+        };
+        let sorted = super::sort_toml(input, MATCHER, false, &config).unwrap();
+        assert_eq(expected, sorted);
+    }
 
     #[test]
-    fn toml_edit_check() {
-        let input = fs::read_to_string("examp/workspace.toml").unwrap();
-        let expected = fs::read_to_string("examp/workspace.sorted.toml").unwrap();
-        let sorted = super::sort_toml(&input, MATCHER, false, &[]);
+    fn sort_by_source() {
+        let input = r#"
+[dependencies]
+serde = "1"
+local = { path = "../local" }
+
+anyhow = "1"
+# the fork
+forked = { git = "https://example.com/forked" }
+shared = { workspace = true }
+"#;
+        let expected = r#"
+[dependencies]
+shared = { workspace = true }
+
+local = { path = "../local" }
+
+# the fork
+forked = { git = "https://example.com/forked" }
+
+anyhow = "1"
+serde = "1"
+"#;
+        let config = Config {
+            table_order: vec![],
+            .."[rules]\ndependencies = \"by-source\"".parse::<Config>().unwrap()
+        };
+        let sorted = super::sort_toml(input, MATCHER, false, &config).unwrap();
         assert_eq(expected, sorted);
     }
 
     #[test]
-    fn toml_combined_key_check() {
-        let input = fs::read_to_string("examp/tun.toml").unwrap();
-        let expected = fs::read_to_string("examp/tun.sorted.toml").unwrap();
-        let o = crate::fmt::DEF_TABLE_ORDER;
-        let o = o.iter().map(|&s| s.to_owned()).collect::<Vec<_>>();
-        let sorted = super::sort_toml(&input, MATCHER, false, &o);
+    fn dependency_groups() {
+        let input = r#"
+[dependencies]
+anyhow = "1"
+serde_json = "1"
+hyper = "1"
+
+tokio = "1"
+serde = "1"
+
+[target.'cfg(unix)'.dependencies]
+tokio-util = "0.7"
+libc = "0.2"
+"#;
+        let expected = r#"
+[dependencies]
+hyper = "1"
+tokio = "1"
+
+serde = "1"
+serde_json = "1"
+
+anyhow = "1"
+
+[target.'cfg(unix)'.dependencies]
+tokio-util = "0.7"
 
+libc = "0.2"
+"#;
+        let groups = r#"
+[groups]
+async = ["tokio*", "hyper*"]
+serde = ["serde*"]
+"#;
+        let config = Config {
+            table_order: vec![],
+            ..groups.parse::<Config>().unwrap()
+        };
+        let sorted = super::sort_toml(input, MATCHER, false, &config).unwrap();
         assert_eq(expected, sorted);
     }
 
     #[test]
-    fn toml_workspace_deps_edit_check() {
-        let input = fs::read_to_string("examp/workspace_deps.toml").unwrap();
-        let expected = fs::read_to_string("examp/workspace_deps.sorted.toml").unwrap();
-        let sorted = super::sort_toml(&input, MATCHER, false, &[]);
+    fn section_markers() {
+        let input = r#"
+[dependencies]
+serde = "1"
+anyhow = "1"
+# --- async ---
+tokio = "1"
+async-trait = "0.1"
+
+bytes = "1"
+"#;
+        let expected = r#"
+[dependencies]
+anyhow = "1"
+serde = "1"
+# --- async ---
+async-trait = "0.1"
+
+bytes = "1"
+tokio = "1"
+"#;
+        let sorted = super::sort_toml(input, MATCHER, false, &config()).unwrap();
+        assert_eq(expected, sorted);
+
+        let expected = r#"
+[dependencies]
+anyhow = "1"
+serde = "1"
+# --- async ---
+async-trait = "0.1"
+tokio = "1"
+
+bytes = "1"
+"#;
+        let sorted = super::sort_toml(input, MATCHER, true, &config()).unwrap();
         assert_eq(expected, sorted);
     }
 
     #[test]
-    fn grouped_check() {
-        let input = fs::read_to_string("examp/ruma.toml").unwrap();
-        let expected = fs::read_to_string("examp/ruma.sorted.toml").unwrap();
-        let sorted = super::sort_toml(&input, MATCHER, true, &[]);
+    fn prefix_affinity() {
+        let input = r#"
+[dependencies]
+tokio = "1"
+serde = "1"
+
+anyhow = "1"
+tokio-util = "0.7"
+
+# tracing
+tracing-subscriber = "0.3"
+tracing = "0.1"
+"#;
+        let expected = r#"
+[dependencies]
+serde = "1"
+tokio = "1"
+tokio-util = "0.7"
+
+anyhow = "1"
+
+# tracing
+tracing = "0.1"
+tracing-subscriber = "0.3"
+"#;
+        let config = Config {
+            compare: Comparator {
+                prefix_affinity: true,
+                ..Comparator::default()
+            },
+            ..config()
+        };
+        let sorted = super::sort_toml(input, MATCHER, true, &config).unwrap();
         assert_eq(expected, sorted);
     }
 
     #[test]
-    fn sort_correct() {
-        let input = fs::read_to_string("examp/right.toml").unwrap();
-        let sorted = super::sort_toml(&input, MATCHER, true, &[]);
-        assert_eq(input, sorted);
+    fn group_headers() {
+        let input = r#"
+[dependencies]
+# -- other --
+anyhow = "1"
+serde_json = "1"
+
+# -- serde --
+tokio = "1"
+serde = "1"
+"#;
+        let expected = r#"
+[dependencies]
+# -- async --
+tokio = "1"
+
+# -- serde --
+serde = "1"
+serde_json = "1"
+
+# -- other --
+anyhow = "1"
+"#;
+        let groups = r#"
+group_headers = true
+
+[groups]
+async = ["tokio*"]
+serde = ["serde*"]
+"#;
+        let config = Config {
+            table_order: vec![],
+            ..groups.parse::<Config>().unwrap()
+        };
+        let sorted = super::sort_toml(input, MATCHER, false, &config).unwrap();
+        assert_eq(expected, sorted);
+        let sorted = super::sort_toml(expected, MATCHER, false, &config).unwrap();
+        assert_eq(expected, sorted);
     }
 
     #[test]
-    fn sort_comments() {
-        let input = fs::read_to_string("examp/comments.toml").unwrap();
-        let expected = fs::read_to_string("examp/comments.sorted.toml").unwrap();
-        let sorted = super::sort_toml(&input, MATCHER, true, &[]);
+    fn expand_inline_dependencies() {
+        let input = r#"
+[dependencies]
+tokio = { version = "1", features = ["full"], default-features = false, optional = true }
+anyhow = "1"
+zzz = "1"
+
+[dev-dependencies]
+a = "1"
+"#;
+        let expected = r#"
+[dependencies]
+anyhow = "1"
+zzz = "1"
+
+[dependencies.tokio]
+version = "1"
+features = ["full"]
+default-features = false
+optional = true
+
+[dev-dependencies]
+a = "1"
+"#;
+        let config = Config {
+            max_inline_dependency_len: Some(60),
+            ..config()
+        };
+        let sorted = super::sort_toml(input, MATCHER, false, &config).unwrap();
         assert_eq(expected, sorted);
     }
 
     #[test]
-    fn sort_tables() {
-        let input = fs::read_to_string("examp/fend.toml").unwrap();
-        let sorted = super::sort_toml(&input, MATCHER, true, &[]);
-        assert_ne!(input, sorted.to_string());
-        // println!("{}", sorted.to_string());
+    fn expand_dotted_inline_dependencies() {
+        let input = r#"
+[target.unix]
+dependencies.foo = { version = "1", features = ["full"] }
+
+[target.windows]
+version = "1"
+dependencies.bar = { version = "1", features = ["full"] }
+"#;
+        let expected = r#"
+[target.unix.dependencies.foo]
+version = "1"
+features = ["full"]
+
+[target.windows]
+version = "1"
+
+[target.windows.dependencies.bar]
+version = "1"
+features = ["full"]
+"#;
+        let config = Config {
+            max_inline_dependency_len: Some(20),
+            ..config()
+        };
+        let sorted = super::sort_toml(input, MATCHER, false, &config).unwrap();
+        assert_eq(expected, sorted);
     }
 
     #[test]
-    fn sort_devfirst() {
-        let input = fs::read_to_string("examp/reorder.toml").unwrap();
-        let sorted = super::sort_toml(&input, MATCHER, true, &[]);
-        assert_eq(input, sorted);
+    fn fold_dependency_tables() {
+        let input = r#"
+[dependencies]
+zzz = "1"
 
-        let input = fs::read_to_string("examp/noreorder.toml").unwrap();
-        let sorted = super::sort_toml(&input, MATCHER, true, &[]);
-        assert_eq(input, sorted);
+[dependencies.anyhow]
+version = "1"
+
+[dependencies.tokio]
+version = "1"
+features = ["full", "macros", "rt-multi-thread", "sync", "time"]
+default-features = false
+
+# kept as a table for the comment
+[dependencies.serde]
+version = "1"
+"#;
+        let expected = r#"
+[dependencies]
+anyhow = { version = "1" }
+zzz = "1"
+
+# kept as a table for the comment
+[dependencies.serde]
+version = "1"
+
+[dependencies.tokio]
+version = "1"
+features = ["full", "macros", "rt-multi-thread", "sync", "time"]
+default-features = false
+"#;
+        let config = Config {
+            inline_dependency_tables: true,
+            ..config()
+        };
+        let sorted = super::sort_toml(input, MATCHER, false, &config).unwrap();
+        assert_eq(expected, sorted);
     }
 
     #[test]
-    fn issue_104() {
-        let input = fs::read_to_string("regressions/104.toml").unwrap();
-        let sorted = super::sort_toml(&input, MATCHER, true, &[]);
+    fn ungroup() {
+        let input = r#"
+[dependencies]
+serde = "1"
+
+# async
+tokio = "1"
+async-trait = "0.1"
+
+anyhow = "1"
+
+[features]
+b = []
+
+a = []
+"#;
+        let expected = r#"
+[dependencies]
+anyhow = "1"
+async-trait = "0.1"
+serde = "1"
+# async
+tokio = "1"
+
+[features]
+b = []
+
+a = []
+"#;
+        let config = Config { ungroup: true, ..config() };
+        let sorted = super::sort_toml(input, MATCHER, true, &config).unwrap();
+        assert_eq(expected, sorted);
+    }
+
+    #[test]
+    fn sort_lints() {
+        let input = r#"
+[lints.rust]
+unsafe_code = "forbid"
+missing_docs = "warn"
+
+[lints.clippy]
+pedantic = { level = "warn", priority = -1 }
+module_name_repetitions = "allow"
+"#;
+        let expected = r#"
+[lints.rust]
+missing_docs = "warn"
+unsafe_code = "forbid"
+
+[lints.clippy]
+module_name_repetitions = "allow"
+pedantic = { level = "warn", priority = -1 }
+"#;
+        let sorted = super::sort_toml(input, MATCHER, false, &config()).unwrap();
+        assert_eq(expected, sorted);
+    }
+
+    #[test]
+    fn sort_workspace_lints() {
+        let input = r#"
+[workspace.lints.rust]
+unsafe_code = "forbid"
+missing_docs = "warn"
+
+[workspace.lints.clippy]
+pedantic = { level = "warn", priority = -1 }
+module_name_repetitions = "allow"
+"#;
+        let expected = r#"
+[workspace.lints.rust]
+missing_docs = "warn"
+unsafe_code = "forbid"
+
+[workspace.lints.clippy]
+module_name_repetitions = "allow"
+pedantic = { level = "warn", priority = -1 }
+"#;
+        let sorted = super::sort_toml(input, MATCHER, false, &config()).unwrap();
+        assert_eq(expected, sorted);
+    }
+
+    #[test]
+    fn sort_patch() {
+        let input = r#"
+[package]
+name = "foo"
+
+[patch.crates-io]
+syn = { path = "../syn" }
+quote = { git = "https://github.com/dtolnay/quote" }
+
+[patch.crates-io.serde]
+path = "../serde"
+
+[patch.crates-io.anyhow]
+path = "../anyhow"
+
+[patch."https://github.com/example/baz"]
+qux = { path = "../qux" }
+baz = { path = "../baz" }
+"#;
+        let expected = r#"
+[package]
+name = "foo"
+
+[patch.crates-io]
+quote = { git = "https://github.com/dtolnay/quote" }
+syn = { path = "../syn" }
+
+[patch.crates-io.anyhow]
+path = "../anyhow"
+
+[patch.crates-io.serde]
+path = "../serde"
+
+[patch."https://github.com/example/baz"]
+baz = { path = "../baz" }
+qux = { path = "../qux" }
+"#;
+        let sorted = super::sort_toml(input, MATCHER, false, &config()).unwrap();
+        assert_eq(expected, sorted);
+        let sorted = super::sort_toml(input, MATCHER, false, &Config::default()).unwrap();
+        assert_eq(expected, sorted);
+    }
+
+    #[test]
+    fn sort_replace() {
+        let input = r#"
+[replace]
+"serde:1.0.0" = { path = "../serde" }
+"foo:0.1.0" = { git = "https://github.com/example/foo" }
+"bar:0.2.0" = { path = "../bar" }
+"#;
+        let expected = r#"
+[replace]
+"bar:0.2.0" = { path = "../bar" }
+"foo:0.1.0" = { git = "https://github.com/example/foo" }
+"serde:1.0.0" = { path = "../serde" }
+"#;
+        let sorted = super::sort_toml(input, MATCHER, false, &config()).unwrap();
+        assert_eq(expected, sorted);
+    }
+
+    #[test]
+    fn profile_key_order() {
+        let input = r#"
+[profile.release]
+lto = true
+codegen-units = 1
+opt-level = 3
+debug = false
+"#;
+        let canonical = r#"
+[profile.release]
+opt-level = 3
+debug = false
+lto = true
+codegen-units = 1
+"#;
+        let alphabetical = r#"
+[profile.release]
+codegen-units = 1
+debug = false
+lto = true
+opt-level = 3
+"#;
+        let sorted = super::sort_toml(input, MATCHER, false, &config()).unwrap();
         assert_eq(input, sorted);
+
+        let config = Config {
+            profile_key_order: "profile_key_order = \"canonical\"".parse::<Config>().unwrap().profile_key_order,
+            ..config()
+        };
+        let sorted = super::sort_toml(input, MATCHER, false, &config).unwrap();
+        assert_eq(canonical, sorted);
+
+        let config = Config {
+            profile_key_order: Some(KeyOrder::Alphabetical),
+            ..config
+        };
+        let sorted = super::sort_toml(input, MATCHER, false, &config).unwrap();
+        assert_eq(alphabetical, sorted);
     }
 
     #[test]
-    fn reorder() {
-        let input = fs::read_to_string("examp/clippy.toml").unwrap();
-        let sorted = super::sort_toml(
-            &input,
-            MATCHER,
-            true,
-            &[
-                "package".to_owned(),
-                "features".to_owned(),
-                "dependencies".to_owned(),
-                "build-dependencies".to_owned(),
-                "dev-dependencies".to_owned(),
-            ],
-        );
-        assert_ne!(input, sorted.to_string());
+    fn sort_cargo_targets() {
+        let input = r#"
+[package]
+name = "foo"
+
+[[example]]
+name = "read-async"
+required-features = ["async"]
+
+# The synchronous example
+[[example]]
+name = "ping-tun"
+
+[dependencies]
+tun = "0.7"
+
+[[bin]]
+path = "src/main.rs"
+
+[[bin]]
+name = "foo-cli"
+"#;
+        let expected = r#"
+[package]
+name = "foo"
+
+# The synchronous example
+[[example]]
+name = "ping-tun"
+
+[[example]]
+name = "read-async"
+required-features = ["async"]
+
+[dependencies]
+tun = "0.7"
+
+[[bin]]
+name = "foo-cli"
+
+[[bin]]
+path = "src/main.rs"
+"#;
+        let sorted = super::sort_toml(input, MATCHER, false, &config()).unwrap();
+        assert_eq(expected, sorted);
+    }
+
+    #[test]
+    fn package_key_order() {
+        let input = r#"
+[package]
+description = "Sorts Cargo.toml"
+license = "MIT"
+edition = "2024"
+version = "0.1.0"
+name = "foo"
+publish = false
+
+[package.metadata.docs.rs]
+all-features = true
+"#;
+        let canonical = r#"
+[package]
+name = "foo"
+version = "0.1.0"
+edition = "2024"
+description = "Sorts Cargo.toml"
+license = "MIT"
+publish = false
+
+[package.metadata.docs.rs]
+all-features = true
+"#;
+        let listed = r#"
+[package]
+name = "foo"
+version = "0.1.0"
+description = "Sorts Cargo.toml"
+edition = "2024"
+license = "MIT"
+publish = false
+
+[package.metadata.docs.rs]
+all-features = true
+"#;
+        let config = Config {
+            package_key_order: "package_key_order = \"canonical\"".parse::<Config>().unwrap().package_key_order,
+            ..config()
+        };
+        let sorted = super::sort_toml(input, MATCHER, false, &config).unwrap();
+        assert_eq(canonical, sorted);
+
+        let config = Config {
+            package_key_order: Some(KeyOrder::Listed(vec!["name".to_owned(), "version".to_owned()])),
+            ..config
+        };
+        let sorted = super::sort_toml(input, MATCHER, false, &config).unwrap();
+        assert_eq(listed, sorted);
+    }
+
+    #[test]
+    fn dependency_key_order() {
+        let input = r#"
+[dependencies]
+serde = { features = ["derive"], optional = true, version = "1" }
+foo = { path = "../foo", version = "0.1" }
+
+[workspace.dependencies]
+bar = { git = "https://github.com/example/bar", branch = "main", version = "2" }
+
+[target.'cfg(unix)'.dependencies]
+nix = { features = ["ioctl"], version = "0.30" }
+"#;
+        let expected = r#"
+[dependencies]
+foo = { version = "0.1", path = "../foo" }
+serde = { version = "1", features = ["derive"], optional = true }
+
+[workspace.dependencies]
+bar = { version = "2", git = "https://github.com/example/bar", branch = "main" }
+
+[target.'cfg(unix)'.dependencies]
+nix = { version = "0.30", features = ["ioctl"] }
+"#;
+        let config = Config {
+            dependency_key_order: "dependency_key_order = \"canonical\""
+                .parse::<Config>()
+                .unwrap()
+                .dependency_key_order,
+            ..config()
+        };
+        let sorted = super::sort_toml(input, MATCHER, false, &config).unwrap();
+        assert_eq(expected, sorted);
+    }
+
+    #[test]
+    fn sort_dependency_features() {
+        let input = r#"
+[dependencies]
+serde = { version = "1", features = ["derive", "alloc"] }
+
+[dependencies.syn]
+version = "2"
+features = ["full", "extra-traits"]
+"#;
+        let expected = r#"
+[dependencies]
+serde = { version = "1", features = ["alloc", "derive"] }
+
+[dependencies.syn]
+version = "2"
+features = ["extra-traits", "full"]
+"#;
+        let sorted = super::sort_toml(input, MATCHER, false, &config()).unwrap();
+        assert_eq(expected, sorted);
     }
 }
@@ -1,3 +1,9 @@
+use std::{
+    fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+
 pub(crate) fn assert_eq<L: ToString, R: ToString>(left: L, right: R) {
     let left = left.to_string();
     let right = right.to_string();
@@ -8,3 +14,39 @@ pub(crate) fn assert_eq<L: ToString, R: ToString>(left: L, right: R) {
     #[cfg(not(windows))]
     similar_asserts::assert_eq!(left, right);
 }
+
+/// An empty directory in the temporary directory for the files of a test,
+/// removed again when it is dropped, also when the test fails.
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Creates `cargo-sort-fix-{name}-{pid}`, the `name` keeps the tests that
+    /// run at the same time apart.
+    pub(crate) fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("cargo-sort-fix-{name}-{}", std::process::id()));
+        // Left over by a run that was killed
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,96 @@
+use std::time::{Duration, Instant};
+
+/// The time spent on each phase of a manifest, for `--timings`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Timings {
+    /// Reading the file and parsing it.
+    pub(crate) parse: Duration,
+    /// Sorting, with the fixes and removing duplicates.
+    pub(crate) sort: Duration,
+    /// Formatting, with the line endings.
+    pub(crate) format: Duration,
+    /// The lints and finding the violations of `--check`.
+    pub(crate) check: Duration,
+    /// Writing the file back or printing it.
+    pub(crate) write: Duration,
+}
+
+const PHASES: [&str; 6] = ["parse", "sort", "format", "check", "write", "total"];
+
+impl Timings {
+    fn phases(&self) -> [Duration; 6] {
+        let total = self.parse + self.sort + self.format + self.check + self.write;
+        [self.parse, self.sort, self.format, self.check, self.write, total]
+    }
+
+    fn add(&mut self, other: &Self) {
+        self.parse += other.parse;
+        self.sort += other.sort;
+        self.format += other.format;
+        self.check += other.check;
+        self.write += other.write;
+    }
+}
+
+/// The time since the last lap of `clock`, which starts the next one.
+pub(crate) fn lap(clock: &mut Instant) -> Duration {
+    let now = Instant::now();
+    let elapsed = now - *clock;
+    *clock = now;
+    elapsed
+}
+
+/// A table of the timings of each file in milliseconds, the sum of every
+/// file in the last row.
+pub(crate) fn table(files: &[(&str, Timings)]) -> String {
+    let mut total = Timings::default();
+    for (_, timings) in files {
+        total.add(timings);
+    }
+    let width = files.iter().map(|(file, _)| file.len()).chain([5]).max().unwrap_or_default();
+
+    let mut out = format!("{:width$}", "");
+    for phase in PHASES {
+        out.push_str(&format!(" {phase:>9}"));
+    }
+    out.push('\n');
+    for (file, timings) in files.iter().chain([&("total", total)]) {
+        out.push_str(&format!("{file:width$}"));
+        for duration in timings.phases() {
+            out.push_str(&format!(" {:>7.2}ms", duration.as_secs_f64() * 1000.0));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{Timings, table};
+
+    #[test]
+    fn timings_table() {
+        let ms = Duration::from_micros;
+        let a = Timings {
+            parse: ms(120),
+            sort: ms(1500),
+            format: ms(250),
+            check: ms(0),
+            write: ms(40),
+        };
+        let b = Timings {
+            parse: ms(10),
+            ..Timings::default()
+        };
+        assert_eq!(
+            table(&[("crates/a/Cargo.toml", a), ("b", b)]),
+            "                        parse      sort    format     check     write     total
+crates/a/Cargo.toml    0.12ms    1.50ms    0.25ms    0.00ms    0.04ms    1.91ms
+b                      0.01ms    0.00ms    0.00ms    0.00ms    0.00ms    0.01ms
+total                  0.13ms    1.50ms    0.25ms    0.00ms    0.04ms    1.92ms
+"
+        );
+    }
+}